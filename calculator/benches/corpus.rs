@@ -0,0 +1,103 @@
+use calculator::bench_corpus::{Expected, Workload};
+use calculator::diagnostic::{DiagnosticsLimit, parse_with_diagnostics};
+use calculator::math_expression_parser::parse_all;
+use calculator::{MathExpression, MathExpressionTokenizer, TokenizerTraits, evaluate};
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use std::hint::black_box;
+
+const SEED: u64 = 0x5eed;
+const SIZE: usize = 10_000;
+
+fn inputs() -> Vec<(Workload, String)> {
+    Workload::ALL
+        .into_iter()
+        .map(|workload| (workload, workload.generate(SEED, SIZE)))
+        .collect()
+}
+
+// Every token, continuing past the errors of the error-heavy input.
+fn tokenize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("corpus tokenize");
+    for (workload, input) in inputs() {
+        let input: &'static str = input.leak();
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_function(workload.name(), |b| {
+            b.iter(|| {
+                let mut tokenizer = MathExpressionTokenizer::new(black_box(input)).unwrap();
+                while tokenizer.has_token() {
+                    let _ = black_box(tokenizer.next_token());
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+// Collecting every problem, which is what the error-heavy input exercises.
+fn validate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("corpus validate");
+    for (workload, input) in inputs() {
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(workload.name()),
+            &input,
+            |b, input| {
+                b.iter(|| {
+                    black_box(parse_with_diagnostics(
+                        black_box(input),
+                        DiagnosticsLimit::default(),
+                    ))
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("corpus parse");
+    for (workload, input) in inputs() {
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(workload.name()),
+            &input,
+            |b, input| {
+                b.iter(|| match workload.expected() {
+                    Expected::Script => {
+                        black_box(parse_all(black_box(input)));
+                    }
+                    Expected::Expression | Expected::Error => {
+                        let _ = black_box(black_box(input).parse::<MathExpression>());
+                    }
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+// Only the inputs that parse into a single expression can be evaluated.
+fn evaluate_parsed(c: &mut Criterion) {
+    let mut group = c.benchmark_group("corpus evaluate");
+    for (workload, input) in inputs() {
+        if workload.expected() != Expected::Expression {
+            continue;
+        }
+
+        let expr: MathExpression = input.parse().unwrap();
+        group.throughput(Throughput::Elements(expr.tokens().len() as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(workload.name()),
+            &expr,
+            |b, expr| b.iter(|| black_box(evaluate(black_box(expr), &input))),
+        );
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(20);
+    targets = tokenize, validate, parse, evaluate_parsed
+}
+criterion_main!(benches);
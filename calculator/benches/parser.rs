@@ -0,0 +1,133 @@
+use calculator::math_expression_parser::parse_all;
+use calculator::{
+    ExprArena, MathExpressionParser, MathExpressionTokenizer, evaluate, parse_and_evaluate,
+};
+use criterion::{Criterion, criterion_group, criterion_main};
+use std::hint::black_box;
+
+const SHORT_EXPRESSIONS: [&str; 4] = ["1 + 2", "(1 + 2) * 3", "4 / (5 - 6) * 7", "((8))"];
+
+fn parse_short_expressions(c: &mut Criterion) {
+    let parser = MathExpressionParser::new();
+
+    c.bench_function("parse 1M short expressions", |b| {
+        b.iter(|| {
+            for i in 0..1_000_000 {
+                let expr = SHORT_EXPRESSIONS[i % SHORT_EXPRESSIONS.len()];
+                let tokenizer = MathExpressionTokenizer::new(black_box(expr)).unwrap();
+                black_box(parser.parse(tokenizer).unwrap());
+            }
+        })
+    });
+}
+
+fn parse_into_short_expressions(c: &mut Criterion) {
+    let mut parser = MathExpressionParser::new();
+    let mut out = parser
+        .parse(MathExpressionTokenizer::new("0").unwrap())
+        .unwrap();
+
+    c.bench_function("parse_into 1M short expressions", |b| {
+        b.iter(|| {
+            for i in 0..1_000_000 {
+                let expr = SHORT_EXPRESSIONS[i % SHORT_EXPRESSIONS.len()];
+                let tokenizer = MathExpressionTokenizer::new(black_box(expr)).unwrap();
+                parser.parse_into(tokenizer, &mut out).unwrap();
+                black_box(&out);
+            }
+        })
+    });
+}
+
+fn parse_and_walk_million_tokens(c: &mut Criterion) {
+    let expr: &'static str = (0..500_000)
+        .map(|i| (i % 10).to_string())
+        .collect::<Vec<_>>()
+        .join("+")
+        .leak();
+
+    c.bench_function("parse and walk 1M tokens", |b| {
+        b.iter(|| {
+            let tokenizer = MathExpressionTokenizer::new(black_box(expr)).unwrap();
+            let parsed = MathExpressionParser::new().parse(tokenizer).unwrap();
+            let checksum: usize = parsed.positioned_tokens().map(|(idx, _)| idx).sum();
+            black_box(checksum)
+        })
+    });
+}
+
+fn evaluate_million_tokens(c: &mut Criterion) {
+    let expr: &'static str = (0..500_000)
+        .map(|i| (i % 10).to_string())
+        .collect::<Vec<_>>()
+        .join("+")
+        .leak();
+
+    c.bench_function("parse then evaluate 1M tokens", |b| {
+        b.iter(|| {
+            let tokenizer = MathExpressionTokenizer::new(black_box(expr)).unwrap();
+            let parsed = MathExpressionParser::new().parse(tokenizer).unwrap();
+            black_box(evaluate(&parsed, expr).unwrap())
+        })
+    });
+
+    c.bench_function("parse_and_evaluate 1M tokens", |b| {
+        b.iter(|| {
+            let tokenizer = MathExpressionTokenizer::new(black_box(expr)).unwrap();
+            black_box(parse_and_evaluate(tokenizer).unwrap())
+        })
+    });
+}
+
+fn parse_and_drop_trees(c: &mut Criterion) {
+    let parser = MathExpressionParser::new();
+
+    c.bench_function("parse and drop 100k boxed trees", |b| {
+        b.iter(|| {
+            for i in 0..100_000 {
+                let expr = SHORT_EXPRESSIONS[i % SHORT_EXPRESSIONS.len()];
+                let tokenizer = MathExpressionTokenizer::new(black_box(expr)).unwrap();
+                black_box(parser.parse_ast(tokenizer).unwrap());
+            }
+        })
+    });
+
+    let mut arena = ExprArena::new();
+    c.bench_function("parse and drop 100k arena trees", |b| {
+        b.iter(|| {
+            for i in 0..100_000 {
+                let expr = SHORT_EXPRESSIONS[i % SHORT_EXPRESSIONS.len()];
+                let tokenizer = MathExpressionTokenizer::new(black_box(expr)).unwrap();
+                arena.clear();
+                black_box(parser.parse_ast_in(tokenizer, &mut arena).unwrap());
+            }
+        })
+    });
+}
+
+fn parse_script(c: &mut Criterion) {
+    let script = (0..100_000)
+        .map(|i| SHORT_EXPRESSIONS[i % SHORT_EXPRESSIONS.len()])
+        .collect::<Vec<_>>()
+        .join(";\n");
+
+    c.bench_function("parse_all 100k statements", |b| {
+        b.iter(|| black_box(parse_all(black_box(&script))))
+    });
+
+    #[cfg(feature = "rayon")]
+    c.bench_function("parse_script_parallel 100k statements", |b| {
+        b.iter(|| {
+            black_box(calculator::math_expression_parser::parse_script_parallel(
+                black_box(&script),
+            ))
+        })
+    });
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = parse_short_expressions, parse_into_short_expressions, parse_and_walk_million_tokens, evaluate_million_tokens, parse_and_drop_trees, parse_script
+}
+criterion_main!(benches);
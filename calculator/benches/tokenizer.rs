@@ -0,0 +1,117 @@
+use calculator::routing::classify_input;
+use calculator::{MathExpressionTokenizer, TokenizerTraits};
+use criterion::{Criterion, criterion_group, criterion_main};
+use std::hint::black_box;
+
+fn numeric_literals(count: usize) -> String {
+    (0..count)
+        .map(|i| format!("{}.{}", i, i % 97))
+        .collect::<Vec<_>>()
+        .join(" + ")
+}
+
+fn tokenize_numbers(c: &mut Criterion) {
+    let expr: &'static str = numeric_literals(100_000).leak();
+
+    c.bench_function("tokenize 100k numeric literals", |b| {
+        b.iter(|| {
+            let mut tokenizer = MathExpressionTokenizer::new(black_box(expr)).unwrap();
+            while tokenizer.has_token() {
+                black_box(tokenizer.next_token().unwrap());
+            }
+        })
+    });
+}
+
+// Run with and without `--features fast-float` to compare float parsers.
+fn tokenize_million_literals(c: &mut Criterion) {
+    let expr: &'static str = (0..1_000_000u64)
+        .map(|i| format!("{}.{}", i.wrapping_mul(2_654_435_761) % 1_000_000, i % 9973))
+        .collect::<Vec<_>>()
+        .join("+")
+        .leak();
+
+    let mut group = c.benchmark_group("float parsing");
+    group.sample_size(10);
+    group.bench_function("tokenize 1M float literals", |b| {
+        b.iter(|| {
+            let mut tokenizer = MathExpressionTokenizer::new(black_box(expr)).unwrap();
+            while tokenizer.has_token() {
+                black_box(tokenizer.next_token().unwrap());
+            }
+        })
+    });
+    group.finish();
+}
+
+fn tokenize_whitespace_heavy(c: &mut Criterion) {
+    let gap = " ".repeat(64);
+    let expr: &'static str = (0..10_000)
+        .map(|i| (i % 10).to_string())
+        .collect::<Vec<_>>()
+        .join(&format!("{gap}+{gap}"))
+        .leak();
+
+    c.bench_function("tokenize whitespace-heavy expression", |b| {
+        b.iter(|| {
+            let mut tokenizer = MathExpressionTokenizer::new(black_box(expr)).unwrap();
+            while tokenizer.has_token() {
+                black_box(tokenizer.next_token().unwrap());
+            }
+        })
+    });
+}
+
+fn tokenize_large_ascii(c: &mut Criterion) {
+    let mut expr = String::with_capacity(10 * 1024 * 1024);
+    let mut i = 0;
+    while expr.len() < 10 * 1024 * 1024 {
+        expr.push_str(&format!("{}.{}    *  ( {} - 7 )   + ", i, i % 13, i % 101));
+        i += 1;
+    }
+    expr.push('1');
+    let expr: &'static str = expr.leak();
+
+    let mut group = c.benchmark_group("large input");
+    group.sample_size(10);
+    group.bench_function("tokenize 10 MB ASCII expression", |b| {
+        b.iter(|| {
+            let mut tokenizer = MathExpressionTokenizer::new(black_box(expr)).unwrap();
+            while tokenizer.has_token() {
+                black_box(tokenizer.next_token().unwrap());
+            }
+        })
+    });
+    group.finish();
+}
+
+// Messages as a chat bot sees them, one of them far longer than the scan.
+fn classify_messages(c: &mut Criterion) {
+    let long = format!("1 + {}", "2 * ".repeat(100_000));
+    let messages = [
+        "(12.5 + 3) * 2",
+        "meet at 3 tomorrow?",
+        "x = 4 * 2",
+        ":help",
+        "🎉🎉 congrats 🎉🎉",
+        long.as_str(),
+    ];
+
+    c.bench_function("classify chat messages", |b| {
+        b.iter(|| {
+            for message in messages {
+                black_box(classify_input(black_box(message)));
+            }
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    tokenize_numbers,
+    tokenize_million_literals,
+    tokenize_whitespace_heavy,
+    tokenize_large_ascii,
+    classify_messages
+);
+criterion_main!(benches);
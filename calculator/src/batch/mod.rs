@@ -0,0 +1,300 @@
+use crate::math_expression_evaluator::{CalcError, parse_and_evaluate};
+use crate::math_expression_parser::MathExpressionParserError;
+use crate::math_expression_tokenizer::{MathExpressionTokenizer, write_number};
+
+use std::fmt::Write as _;
+use std::io::{self, BufRead, Write};
+use std::time::{Duration, Instant};
+
+// Records written between two flushes of the output.
+const FLUSH_EVERY: usize = 1024;
+
+// How `evaluate_to_writer` writes its records. Every record is one line and
+// starts with the one-based number of the input line it belongs to.
+//
+//   Text     `3: 7` or `4: error E0102: Found invalid token ...`
+//   Csv      a `line,value,error` header, then `3,7,` or `4,,"E0102: ..."`
+//   Ndjson   `{"line":3,"value":7}` or
+//            `{"line":4,"error":{"code":"E0102","message":"..."}}`
+//
+// Values are printed as in expressions. JSON has no infinities or NaN, so
+// NDJSON gives those as the strings "inf", "-inf" and "NaN".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Csv,
+    Ndjson,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchSummary {
+    pub total: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub duration: Duration,
+}
+
+// Evaluates every line of `lines` as an expression and writes one record per
+// line to `out`, holding only the current line in memory. Blank lines are
+// skipped and not counted. Expressions that fail are written as error
+// records, with positions in their line; only reading or writing fails the
+// whole batch, including a line that is not UTF-8.
+pub fn evaluate_to_writer(
+    mut lines: impl BufRead,
+    mut out: impl Write,
+    format: OutputFormat,
+) -> io::Result<BatchSummary> {
+    let start = Instant::now();
+    let mut summary = BatchSummary {
+        total: 0,
+        succeeded: 0,
+        failed: 0,
+        duration: Duration::ZERO,
+    };
+    let mut line = String::new();
+    let mut record = String::new();
+    let mut line_number = 0;
+
+    if format == OutputFormat::Csv {
+        writeln!(out, "line,value,error")?;
+    }
+
+    loop {
+        line.clear();
+        if lines.read_line(&mut line)? == 0 {
+            break;
+        }
+        line_number += 1;
+
+        let source = line.strip_suffix('\n').unwrap_or(&line);
+        let source = source.strip_suffix('\r').unwrap_or(source);
+        if source.trim().is_empty() {
+            continue;
+        }
+
+        let result = MathExpressionTokenizer::new(source.to_owned())
+            .map_err(|err| CalcError::from(MathExpressionParserError::from(err)))
+            .and_then(parse_and_evaluate);
+
+        summary.total += 1;
+        match &result {
+            Ok(_) => summary.succeeded += 1,
+            Err(_) => summary.failed += 1,
+        }
+
+        record.clear();
+        write_record(&mut record, format, line_number, &result);
+        out.write_all(record.as_bytes())?;
+        if summary.total.is_multiple_of(FLUSH_EVERY) {
+            out.flush()?;
+        }
+    }
+
+    out.flush()?;
+    summary.duration = start.elapsed();
+    Ok(summary)
+}
+
+fn write_record(
+    record: &mut String,
+    format: OutputFormat,
+    line: usize,
+    result: &Result<f64, CalcError>,
+) {
+    let _ = match (format, result) {
+        (OutputFormat::Text, Ok(value)) => {
+            let _ = write!(record, "{line}: ");
+            write_number(record, *value)
+        }
+        (OutputFormat::Text, Err(err)) => {
+            write!(record, "{line}: error {}: {}", err.code(), message(err))
+        }
+        (OutputFormat::Csv, Ok(value)) => {
+            let _ = write!(record, "{line},");
+            let _ = write_number(record, *value);
+            write!(record, ",")
+        }
+        (OutputFormat::Csv, Err(err)) => {
+            let error = format!("{}: {}", err.code(), message(err));
+            write!(record, "{line},,\"{}\"", error.replace('"', "\"\""))
+        }
+        (OutputFormat::Ndjson, Ok(value)) => {
+            let _ = write!(record, "{{\"line\":{line},\"value\":");
+            if value.is_finite() {
+                let _ = write_number(record, *value);
+            } else {
+                let _ = write!(record, "\"{value}\"");
+            }
+            write!(record, "}}")
+        }
+        (OutputFormat::Ndjson, Err(err)) => {
+            let _ = write!(
+                record,
+                "{{\"line\":{line},\"error\":{{\"code\":\"{}\",\"message\":",
+                err.code()
+            );
+            write_json_string(record, &message(err));
+            write!(record, "}}}}")
+        }
+    };
+    record.push('\n');
+}
+
+// The message of the underlying error, without the "Parser error: " or
+// "Evaluator error: " prefix `CalcError` adds.
+fn message(err: &CalcError) -> String {
+    match err {
+        CalcError::Parser(err) => err.to_string(),
+        CalcError::Evaluator(err) => err.to_string(),
+    }
+}
+
+fn write_json_string(out: &mut String, text: &str) {
+    out.push('"');
+    for ch in text.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            ch if ch.is_control() => {
+                let _ = write!(out, "\\u{:04x}", ch as u32);
+            }
+            ch => out.push(ch),
+        }
+    }
+    out.push('"');
+}
+
+// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INPUT: &str = "1 + 2\n\
+                         2 * (3\n\
+                         \n\
+                         10 / 4\r\n\
+                         1 / 0\n\
+                         \"x\" + 1\n\
+                         2 ^ 1024";
+
+    fn run(input: &str, format: OutputFormat) -> (String, BatchSummary) {
+        let mut out = Vec::new();
+        let summary = evaluate_to_writer(input.as_bytes(), &mut out, format).unwrap();
+        (String::from_utf8(out).unwrap(), summary)
+    }
+
+    #[test]
+    fn test_ndjson_records() {
+        let (out, summary) = run(INPUT, OutputFormat::Ndjson);
+        assert_eq!(
+            out,
+            "{\"line\":1,\"value\":3}\n\
+             {\"line\":2,\"error\":{\"code\":\"E0202\",\"message\":\"Unclosed brace at position 5\"}}\n\
+             {\"line\":4,\"value\":2.5}\n\
+             {\"line\":5,\"error\":{\"code\":\"E0501\",\"message\":\"Division by zero at position 3\"}}\n\
+             {\"line\":6,\"error\":{\"code\":\"E0102\",\"message\":\"Tokenizer error: Found invalid token '\\\"' at position 1\"}}\n\
+             {\"line\":7,\"value\":\"inf\"}\n"
+        );
+        assert_eq!(
+            (summary.total, summary.succeeded, summary.failed),
+            (6, 3, 3)
+        );
+
+        for record in out.lines() {
+            let record: serde_json::Value = serde_json::from_str(record).unwrap();
+            assert!(record["line"].is_u64());
+        }
+    }
+
+    #[test]
+    fn test_text_and_csv_records() {
+        let input = "1 + 2\n1 / 0\n";
+
+        let (out, _) = run(input, OutputFormat::Text);
+        assert_eq!(
+            out,
+            "1: 3\n\
+             2: error E0501: Division by zero at position 3\n"
+        );
+
+        let (out, summary) = run(input, OutputFormat::Csv);
+        assert_eq!(
+            out,
+            "line,value,error\n\
+             1,3,\n\
+             2,,\"E0501: Division by zero at position 3\"\n"
+        );
+        assert_eq!(
+            (summary.total, summary.succeeded, summary.failed),
+            (2, 1, 1)
+        );
+    }
+
+    #[test]
+    fn test_empty_input() {
+        let (out, summary) = run("", OutputFormat::Ndjson);
+        assert_eq!(out, "");
+        assert_eq!(
+            (summary.total, summary.succeeded, summary.failed),
+            (0, 0, 0)
+        );
+    }
+
+    // Counts flushes and fails writes after `fail_after` bytes.
+    struct Sink {
+        written: usize,
+        flushes: usize,
+        fail_after: usize,
+    }
+
+    impl Write for Sink {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.written + buf.len() > self.fail_after {
+                return Err(io::Error::other("disk full"));
+            }
+            self.written += buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushes += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_flushes_periodically() {
+        let input = "1\n".repeat(FLUSH_EVERY * 3 + 1);
+        let mut sink = Sink {
+            written: 0,
+            flushes: 0,
+            fail_after: usize::MAX,
+        };
+        let summary = evaluate_to_writer(input.as_bytes(), &mut sink, OutputFormat::Text).unwrap();
+
+        assert_eq!(summary.succeeded, FLUSH_EVERY * 3 + 1);
+        // Every `FLUSH_EVERY` records, and once at the end.
+        assert_eq!(sink.flushes, 4);
+    }
+
+    #[test]
+    fn test_write_errors_abort() {
+        let mut sink = Sink {
+            written: 0,
+            flushes: 0,
+            fail_after: 10,
+        };
+        let err = evaluate_to_writer(INPUT.as_bytes(), &mut sink, OutputFormat::Text).unwrap_err();
+        assert_eq!(err.to_string(), "disk full");
+    }
+
+    #[test]
+    fn test_invalid_utf8_aborts() {
+        let input: &[u8] = b"1 + 2\n\xff\n3\n";
+        let err = evaluate_to_writer(input, Vec::new(), OutputFormat::Text).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,281 @@
+// Reproducible inputs shared by the benchmarks and the scalability tests, so
+// both measure exactly the same text. Every workload is generated from a seed
+// and a size; the same pair always gives the same input, on every platform.
+
+const OPERATORS: [char; 4] = ['+', '-', '*', '/'];
+const WHITESPACE: [char; 4] = [' ', ' ', '\t', '\n'];
+// Each of these makes the tokenizer or the parser reject the input.
+const GARBAGE: [&str; 6] = ["$", "x", "1..2", "é", "×", "2 3"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Workload {
+    // `size` short expressions of a few tokens, as statements of a script.
+    Short,
+    // One expression of `size` single-digit terms without spaces.
+    FlatChain,
+    // A single literal inside `size` levels of parentheses, each closed by
+    // another term.
+    DeepNesting,
+    // `size` literals of up to 15 significant digits.
+    NumberHeavy,
+    // `size` terms separated by long runs of mixed whitespace.
+    WhitespaceHeavy,
+    // `size` terms of which about a quarter, and always the last, are invalid.
+    ErrorHeavy,
+}
+
+// What parsing a generated input is meant to give.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Expected {
+    Expression,
+    // Every statement of `parse_all` parses.
+    Script,
+    Error,
+}
+
+impl Workload {
+    pub const ALL: [Workload; 6] = [
+        Workload::Short,
+        Workload::FlatChain,
+        Workload::DeepNesting,
+        Workload::NumberHeavy,
+        Workload::WhitespaceHeavy,
+        Workload::ErrorHeavy,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Workload::Short => "short",
+            Workload::FlatChain => "flat chain",
+            Workload::DeepNesting => "deep nesting",
+            Workload::NumberHeavy => "number-heavy",
+            Workload::WhitespaceHeavy => "whitespace-heavy",
+            Workload::ErrorHeavy => "error-heavy",
+        }
+    }
+
+    pub fn expected(&self) -> Expected {
+        match self {
+            Workload::Short => Expected::Script,
+            Workload::ErrorHeavy => Expected::Error,
+            _ => Expected::Expression,
+        }
+    }
+
+    // Sizes of zero are treated as one, so every input is non-empty.
+    pub fn generate(&self, seed: u64, size: usize) -> String {
+        let mut rng = Rng::new(seed);
+        let size = size.max(1);
+
+        match self {
+            Workload::Short => short(&mut rng, size),
+            Workload::FlatChain => join(&mut rng, size, |rng, out| {
+                out.push(digit(rng));
+            }),
+            Workload::DeepNesting => deep_nesting(&mut rng, size),
+            Workload::NumberHeavy => join(&mut rng, size, |rng, out| {
+                push_number(rng, out);
+            }),
+            Workload::WhitespaceHeavy => whitespace_heavy(&mut rng, size),
+            Workload::ErrorHeavy => error_heavy(&mut rng, size),
+        }
+    }
+}
+
+// SplitMix64: tiny, fast and good enough to vary the inputs; it is not meant
+// to be random in any statistical sense.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+
+    fn pick<T: Copy>(&mut self, items: &[T]) -> T {
+        items[self.below(items.len())]
+    }
+}
+
+// Non-zero, so that divisions never divide by zero when evaluated.
+fn digit(rng: &mut Rng) -> char {
+    char::from(b'1' + rng.below(9) as u8)
+}
+
+fn push_number(rng: &mut Rng, out: &mut String) {
+    out.push(digit(rng));
+    let digits = rng.below(15);
+    let point = rng.below(digits + 1);
+    for i in 0..digits {
+        if i == point {
+            out.push('.');
+        }
+        out.push(char::from(b'0' + rng.below(10) as u8));
+    }
+}
+
+// `terms` terms written by `term`, joined by random binary operators.
+fn join(rng: &mut Rng, terms: usize, mut term: impl FnMut(&mut Rng, &mut String)) -> String {
+    let mut out = String::with_capacity(terms * 4);
+    term(rng, &mut out);
+    for _ in 1..terms {
+        out.push(rng.pick(&OPERATORS));
+        term(rng, &mut out);
+    }
+    out
+}
+
+fn short(rng: &mut Rng, count: usize) -> String {
+    let mut out = String::with_capacity(count * 16);
+    for i in 0..count {
+        if i > 0 {
+            out.push_str(";\n");
+        }
+
+        let group = rng.below(2) == 0;
+        if group {
+            out.push('(');
+        }
+        out.push(digit(rng));
+        for _ in 0..1 + rng.below(2) {
+            out.push(' ');
+            out.push(rng.pick(&OPERATORS));
+            out.push(' ');
+            out.push(digit(rng));
+        }
+        if group {
+            out.push(')');
+            out.push_str(" * ");
+            out.push(digit(rng));
+        }
+    }
+    out
+}
+
+fn deep_nesting(rng: &mut Rng, depth: usize) -> String {
+    let mut out = String::with_capacity(depth * 4 + 1);
+    out.extend(std::iter::repeat_n('(', depth));
+    out.push(digit(rng));
+    for _ in 0..depth {
+        out.push(')');
+        out.push(rng.pick(&OPERATORS));
+        out.push(digit(rng));
+    }
+    out
+}
+
+fn whitespace_heavy(rng: &mut Rng, terms: usize) -> String {
+    fn gap(rng: &mut Rng, out: &mut String) {
+        for _ in 0..1 + rng.below(32) {
+            out.push(rng.pick(&WHITESPACE));
+        }
+    }
+
+    let mut out = String::with_capacity(terms * 40);
+    gap(rng, &mut out);
+    out.push(digit(rng));
+    for _ in 1..terms {
+        gap(rng, &mut out);
+        out.push(rng.pick(&OPERATORS));
+        gap(rng, &mut out);
+        out.push(digit(rng));
+    }
+    gap(rng, &mut out);
+    out
+}
+
+fn error_heavy(rng: &mut Rng, terms: usize) -> String {
+    let mut remaining = terms;
+    join(rng, terms, |rng, out| {
+        remaining -= 1;
+        if remaining == 0 || rng.below(4) == 0 {
+            out.push_str(rng.pick(&GARBAGE));
+        } else {
+            out.push(digit(rng));
+        }
+    })
+}
+
+// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math_expression_parser::{MathExpression, parse_all};
+    use crate::math_expression_tokenizer::{MathExpressionTokenizer, TokenizerTraits};
+
+    const SIZES: [usize; 5] = [0, 1, 2, 17, 1000];
+
+    fn check(workload: Workload, seed: u64, size: usize) {
+        let input = workload.generate(seed, size);
+        let context = format!("{} (seed {seed}, size {size}): {input:?}", workload.name());
+
+        match workload.expected() {
+            Expected::Expression => {
+                assert!(input.parse::<MathExpression>().is_ok(), "{context}");
+            }
+            Expected::Script => {
+                let results = parse_all(&input);
+                assert_eq!(results.len(), size.max(1), "{context}");
+                assert!(results.iter().all(Result::is_ok), "{context}");
+            }
+            Expected::Error => {
+                assert!(input.parse::<MathExpression>().is_err(), "{context}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_every_workload_parses_as_labeled() {
+        for workload in Workload::ALL {
+            for seed in 0..20 {
+                for size in SIZES {
+                    check(workload, seed, size);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_generation_is_reproducible() {
+        for workload in Workload::ALL {
+            assert_eq!(workload.generate(7, 100), workload.generate(7, 100));
+            assert_ne!(
+                workload.generate(7, 100),
+                workload.generate(8, 100),
+                "{}",
+                workload.name()
+            );
+        }
+        // Pinned, so a change to the generators is noticed.
+        assert_eq!(Workload::FlatChain.generate(1, 5), "6/4/4+1-1");
+    }
+
+    #[test]
+    fn test_sizes() {
+        let count_tokens = |input: String| {
+            let mut tokenizer = MathExpressionTokenizer::new(input).unwrap();
+            let mut count = 0;
+            while tokenizer.has_token() {
+                tokenizer.next_token().unwrap();
+                count += 1;
+            }
+            count
+        };
+
+        assert_eq!(count_tokens(Workload::FlatChain.generate(3, 50)), 99);
+        assert_eq!(count_tokens(Workload::WhitespaceHeavy.generate(3, 50)), 99);
+        assert_eq!(count_tokens(Workload::NumberHeavy.generate(3, 50)), 99);
+        assert_eq!(count_tokens(Workload::DeepNesting.generate(3, 50)), 201);
+    }
+}
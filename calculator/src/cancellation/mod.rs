@@ -0,0 +1,78 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Cheap, clonable cancellation flag. Cancelling a token also cancels every
+// child created from it, but cancelling a child leaves its parent untouched.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    node: Arc<CancellationNode>,
+}
+
+#[derive(Debug, Default)]
+struct CancellationNode {
+    cancelled: AtomicBool,
+    parent: Option<Arc<CancellationNode>>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn child_token(&self) -> Self {
+        Self {
+            node: Arc::new(CancellationNode {
+                cancelled: AtomicBool::new(false),
+                parent: Some(Arc::clone(&self.node)),
+            }),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.node.cancelled.store(true, Ordering::Release);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        let mut node = Some(&self.node);
+        while let Some(current) = node {
+            if current.cancelled.load(Ordering::Acquire) {
+                return true;
+            }
+            node = current.parent.as_ref();
+        }
+
+        false
+    }
+}
+
+// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel_is_shared_between_clones() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+
+        assert!(!clone.is_cancelled());
+        token.cancel();
+        assert!(clone.is_cancelled());
+    }
+
+    #[test]
+    fn test_child_tokens() {
+        let parent = CancellationToken::new();
+        let child = parent.child_token();
+        let grandchild = child.child_token();
+
+        child.cancel();
+        assert!(!parent.is_cancelled());
+        assert!(child.is_cancelled());
+        assert!(grandchild.is_cancelled());
+
+        let other_child = parent.child_token();
+        parent.cancel();
+        assert!(other_child.is_cancelled());
+    }
+}
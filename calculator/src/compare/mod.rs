@@ -0,0 +1,57 @@
+use crate::math_expression_tokenizer::{MathExpressionTokenizer, MathExpressionTokenizerError};
+
+// Whether `a` and `b` tokenize to the same tokens, so they differ at most in
+// spacing and in how literals are written (`1.50` and `1.5` are the same
+// digit). Both inputs are tokenized to the end even after a difference, so an
+// invalid input is always reported as an error rather than as different.
+pub fn same_token_stream(a: &str, b: &str) -> Result<bool, MathExpressionTokenizerError> {
+    let mut lhs = MathExpressionTokenizer::new(a.to_string())?;
+    let mut rhs = MathExpressionTokenizer::new(b.to_string())?;
+    let mut same = true;
+
+    loop {
+        match (lhs.next().transpose()?, rhs.next().transpose()?) {
+            (None, None) => return Ok(same),
+            (Some((_, lhs)), Some((_, rhs))) => same &= lhs == rhs,
+            _ => same = false,
+        }
+    }
+}
+
+// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::position::Position;
+
+    #[test]
+    fn test_spacing_does_not_matter() {
+        assert_eq!(same_token_stream("1+2*3", " 1 +  2\t* 3\n"), Ok(true));
+        assert_eq!(same_token_stream("(1.50)", "( 1.5 )"), Ok(true));
+        assert_eq!(same_token_stream(".5", "0.5"), Ok(true));
+    }
+
+    #[test]
+    fn test_different_tokens() {
+        assert_eq!(same_token_stream("1 + 2 * 3", "2 * 3 + 1"), Ok(false));
+        assert_eq!(same_token_stream("1 - 2", "2 - 1"), Ok(false));
+        assert_eq!(same_token_stream("1 + 2", "1 + 2 + 3"), Ok(false));
+        // Parentheses are tokens too, even redundant ones.
+        assert_eq!(same_token_stream("(1 + 2)", "1 + 2"), Ok(false));
+    }
+
+    #[test]
+    fn test_invalid_input_is_an_error() {
+        let invalid = Err(MathExpressionTokenizerError::InvalidToken {
+            idx: Position::new(6, 6),
+            ch: '$',
+            suggestion: None,
+        });
+
+        assert_eq!(same_token_stream("1 + 2", "1 + 2 $"), invalid);
+        assert_eq!(same_token_stream("1 + 2 $", "1 + 2"), invalid);
+        // The inputs already differ before the error.
+        assert_eq!(same_token_stream("3", "1 + 2 $"), invalid);
+        assert!(same_token_stream("", "1").is_err());
+    }
+}
@@ -0,0 +1,102 @@
+use crate::math_expression_parser::MathExpression;
+use crate::math_expression_tokenizer::Token;
+
+// Weights of the breakdown in `CostEstimate::total`. Operations are what an
+// evaluation spends its time on; deep nesting costs stack space on top.
+const OPERATION_WEIGHT: u64 = 4;
+const DEPTH_WEIGHT: u64 = 2;
+
+// Up-front estimate of how expensive an expression is, e.g. for admission
+// control before evaluating user input. Totals of different expressions are
+// comparable, and adding to an expression never lowers its total.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CostEstimate {
+    pub tokens: u64,
+    pub operations: u64,
+    pub max_depth: u64,
+}
+
+impl CostEstimate {
+    pub fn total(&self) -> u64 {
+        self.tokens
+            .saturating_add(self.operations.saturating_mul(OPERATION_WEIGHT))
+            .saturating_add(self.max_depth.saturating_mul(DEPTH_WEIGHT))
+    }
+}
+
+pub fn estimate_cost(expr: &MathExpression) -> CostEstimate {
+    let mut estimate = CostEstimate::default();
+    let mut depth = 0u64;
+
+    for token in expr.tokens() {
+        estimate.tokens += 1;
+        match token {
+            Token::Operator(_) | Token::UnaryOperator(_) => estimate.operations += 1,
+            Token::OpenBrace => {
+                depth += 1;
+                estimate.max_depth = estimate.max_depth.max(depth);
+            }
+            Token::CloseBrace => depth -= 1,
+            Token::Digit(_) => {}
+        }
+    }
+
+    estimate
+}
+
+// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn cost(source: &str) -> CostEstimate {
+        estimate_cost(&source.parse().unwrap())
+    }
+
+    #[test]
+    fn test_breakdown() {
+        assert_eq!(
+            cost("-(1 + (2 * 3))"),
+            CostEstimate {
+                tokens: 10,
+                operations: 3,
+                max_depth: 2,
+            }
+        );
+        assert_eq!(cost("-(1 + (2 * 3))").total(), 10 + 3 * 4 + 2 * 2);
+        assert_eq!(cost("1").total(), 1);
+    }
+
+    #[test]
+    fn test_total_saturates() {
+        let estimate = CostEstimate {
+            tokens: u64::MAX,
+            operations: u64::MAX,
+            max_depth: 1,
+        };
+        assert_eq!(estimate.total(), u64::MAX);
+    }
+
+    proptest! {
+        #[test]
+        fn test_adding_never_lowers_cost(
+            lhs in "[0-9]{1,3}( [+*/-] [0-9]{1,3}){0,6}",
+            op in "[+*/-]",
+            rhs in "[0-9]{1,3}",
+        ) {
+            let base = cost(&lhs).total();
+            let extended = [
+                format!("{lhs} {op} {rhs}"),
+                format!("{rhs} {op} {lhs}"),
+                format!("({lhs}) {op} {rhs}"),
+                format!("-({lhs})"),
+            ];
+
+            for source in extended {
+                prop_assert!(cost(&source).total() > base, "{}", source);
+            }
+        }
+    }
+}
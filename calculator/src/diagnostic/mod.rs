@@ -0,0 +1,607 @@
+use crate::error_code::ErrorCode;
+use crate::math_expression_parser::{MathExpression, MathExpressionParserError};
+use crate::math_expression_tokenizer::{
+    MathExpressionTokenizer, MathExpressionTokenizerError, TokenizerTraits,
+};
+use crate::position::Span;
+
+use std::fmt::{self, Write};
+
+// Number of characters shown on each side of the error in a `SourcedError`.
+pub const EXCERPT_RADIUS: usize = 30;
+
+// Number of diagnostics `parse_with_diagnostics` reports before it only counts
+// the rest. Runs merged into one diagnostic count once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiagnosticsLimit(pub usize);
+
+impl Default for DiagnosticsLimit {
+    fn default() -> Self {
+        Self(100)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Label {
+    pub span: Span,
+    pub note: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Diagnostic {
+    pub code: ErrorCode,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub help: Option<String>,
+}
+
+impl Label {
+    pub fn new(span: impl Into<Span>, note: impl Into<String>) -> Self {
+        Self {
+            span: span.into(),
+            note: note.into(),
+        }
+    }
+
+    // Span of the character starting at `idx`, or an empty span at the end of input.
+    pub(crate) fn at_char(source: &str, idx: usize, note: impl Into<String>) -> Self {
+        let width = source
+            .get(idx..)
+            .and_then(|rest| rest.chars().next())
+            .map_or(0, char::len_utf8);
+
+        Self::new(idx..idx + width, note)
+    }
+}
+
+impl Diagnostic {
+    // Renders the diagnostic with every label underlined below its source line.
+    // Labels are drawn one per row, in order, so several labels on the same
+    // line never overlap.
+    pub fn render(&self, source: &str) -> String {
+        let lines = line_starts(source);
+        let gutter = lines.len().to_string().len();
+        let mut out = format!("error[{}]: {}\n", self.code, self.message);
+        let mut printed_line = None;
+
+        for label in &self.labels {
+            // Hand-made spans may cut through a char, or start on a line
+            // break; they are moved back to the char's start or the end of
+            // the line's text instead of panicking.
+            let start = source.floor_char_boundary(label.span.start);
+            let line = lines.partition_point(|&line_start| line_start <= start) - 1;
+            let line_start = lines[line];
+            let line_text = source[line_start..]
+                .split('\n')
+                .next()
+                .unwrap_or_default()
+                .trim_end_matches('\r');
+            let line_end = line_start + line_text.len();
+            let start = start.min(line_end);
+
+            if printed_line != Some(line) {
+                let _ = writeln!(out, "{:>gutter$} | {}", line + 1, line_text);
+                printed_line = Some(line);
+            }
+
+            let column = source[line_start..start].chars().count();
+            let end = source.floor_char_boundary(label.span.end.clamp(start, line_end));
+            let width = source[start..end].chars().count().max(1);
+
+            let _ = writeln!(
+                out,
+                "{:gutter$} | {}{} {}",
+                "",
+                " ".repeat(column),
+                "^".repeat(width),
+                label.note
+            );
+        }
+
+        if let Some(help) = &self.help {
+            let _ = writeln!(out, "{:gutter$} = help: {}", "", help);
+        }
+
+        out
+    }
+}
+
+// Parses `source`, reporting every invalid character or literal when the
+// tokenizer rejects it instead of only the first. A run of the same invalid
+// character is reported once with a span covering the run. Once `limit`
+// diagnostics are collected the rest are only counted, and a final E0401
+// diagnostic says how many were left out. Parser errors end parsing, so there
+// is at most one of them.
+pub fn parse_with_diagnostics(
+    source: &str,
+    limit: DiagnosticsLimit,
+) -> Result<MathExpression, Vec<Diagnostic>> {
+    let mut tokenizer = match source.parse::<MathExpression>() {
+        Ok(expression) => return Ok(expression),
+        Err(MathExpressionParserError::Tokenizer(_)) => {
+            match MathExpressionTokenizer::new(source.to_string()) {
+                Ok(tokenizer) => tokenizer,
+                Err(err) => return Err(vec![err.to_diagnostic(source)]),
+            }
+        }
+        Err(err) => return Err(vec![err.to_diagnostic(source)]),
+    };
+
+    let mut collector = Collector {
+        source,
+        limit: limit.0,
+        diagnostics: Vec::new(),
+        suppressed: 0,
+        run: None,
+    };
+    while tokenizer.has_token() {
+        if let Err(err) = tokenizer.next_token() {
+            collector.add(err, tokenizer.curr_index());
+        }
+    }
+
+    Err(collector.finish())
+}
+
+struct Collector<'a> {
+    source: &'a str,
+    limit: usize,
+    diagnostics: Vec<Diagnostic>,
+    suppressed: usize,
+    // The current run of identical errors, its end and its length.
+    run: Option<(MathExpressionTokenizerError, usize, usize)>,
+}
+
+impl Collector<'_> {
+    fn add(&mut self, err: MathExpressionTokenizerError, end: usize) {
+        if let Some((first, run_end, len)) = &mut self.run
+            && continues_run(first, *run_end, &err)
+        {
+            *run_end = end;
+            *len += 1;
+            return;
+        }
+
+        self.flush();
+        self.run = Some((err, end, 1));
+    }
+
+    fn flush(&mut self) {
+        let Some((err, end, len)) = self.run.take() else {
+            return;
+        };
+        if self.diagnostics.len() >= self.limit {
+            self.suppressed += 1;
+            return;
+        }
+
+        let mut diagnostic = err.to_diagnostic(self.source);
+        if len > 1
+            && let Some(label) = diagnostic.labels.first_mut()
+        {
+            label.span.end = end;
+            label.note = format!("{len} invalid characters");
+        }
+        self.diagnostics.push(diagnostic);
+    }
+
+    fn finish(mut self) -> Vec<Diagnostic> {
+        self.flush();
+        if self.suppressed > 0 {
+            self.diagnostics.push(Diagnostic {
+                code: ErrorCode::DiagnosticsTooManyErrors,
+                message: format!(
+                    "Too many errors: {} reported, {} more suppressed",
+                    self.diagnostics.len(),
+                    self.suppressed
+                ),
+                labels: vec![],
+                help: Some("fix the errors above and parse again".to_string()),
+            });
+        }
+
+        self.diagnostics
+    }
+}
+
+// Whether `next` repeats the invalid character of a run ending at `end`.
+fn continues_run(
+    first: &MathExpressionTokenizerError,
+    end: usize,
+    next: &MathExpressionTokenizerError,
+) -> bool {
+    match (first, next) {
+        (
+            MathExpressionTokenizerError::InvalidToken { ch, suggestion, .. },
+            MathExpressionTokenizerError::InvalidToken {
+                idx,
+                ch: next_ch,
+                suggestion: next_suggestion,
+            },
+        ) => idx.byte == end && ch == next_ch && suggestion == next_suggestion,
+        _ => false,
+    }
+}
+
+// A parser error together with a one-line excerpt of the input around the
+// error position. Control characters are escaped so the excerpt never spans
+// several lines, and the caret column accounts for the escapes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourcedError {
+    error: MathExpressionParserError,
+    excerpt: Option<(String, usize)>,
+}
+
+impl SourcedError {
+    pub fn new(error: MathExpressionParserError, source: &str) -> Self {
+        let excerpt = error.idx().map(|idx| excerpt(source, idx.byte));
+        Self { error, excerpt }
+    }
+
+    pub fn error(&self) -> &MathExpressionParserError {
+        &self.error
+    }
+
+    pub fn into_inner(self) -> MathExpressionParserError {
+        self.error
+    }
+}
+
+impl fmt::Display for SourcedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)?;
+        if let Some((excerpt, column)) = &self.excerpt {
+            write!(f, "\n  {excerpt}\n  {:column$}^", "")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for SourcedError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+// Returns the escaped window around byte `idx` and the caret column in it.
+fn excerpt(source: &str, idx: usize) -> (String, usize) {
+    let chars: Vec<(usize, char)> = source.char_indices().collect();
+    let at = chars.partition_point(|&(byte, _)| byte < idx);
+    let start = at.saturating_sub(EXCERPT_RADIUS);
+    let end = (at + EXCERPT_RADIUS).min(chars.len());
+
+    let mut out = String::new();
+    let mut column = None;
+    if start > 0 {
+        out.push_str("...");
+    }
+
+    for (i, &(_, ch)) in chars[start..end].iter().enumerate() {
+        if start + i == at {
+            column = Some(out.chars().count());
+        }
+        if ch.is_control() {
+            out.extend(ch.escape_default());
+        } else {
+            out.push(ch);
+        }
+    }
+
+    let column = column.unwrap_or_else(|| out.chars().count());
+    if end < chars.len() {
+        out.push_str("...");
+    }
+
+    (out, column)
+}
+
+fn line_starts(source: &str) -> Vec<usize> {
+    std::iter::once(0)
+        .chain(source.match_indices('\n').map(|(idx, _)| idx + 1))
+        .collect()
+}
+
+// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math_expression_parser::{MathExpressionParser, MathExpressionParserError};
+    use crate::math_expression_tokenizer::MathExpressionTokenizer;
+
+    fn parse_error(source: &str) -> MathExpressionParserError {
+        let tokenizer = MathExpressionTokenizer::new(source.to_string()).unwrap();
+        MathExpressionParser::new().parse(tokenizer).unwrap_err()
+    }
+
+    #[test]
+    fn test_unclosed_brace_has_two_labels() {
+        let source = "(1 + 2";
+        let diagnostic = parse_error(source).to_diagnostic(source);
+
+        assert_eq!(diagnostic.code, ErrorCode::ParserUnclosedBrace);
+        assert_eq!(
+            diagnostic.labels,
+            vec![
+                Label::new(0..1, "this '(' is never closed"),
+                Label::new(6..6, "expected ')' before the end of input"),
+            ]
+        );
+        assert_eq!(
+            diagnostic.render(source),
+            "error[E0202]: Unclosed brace at position 1\n\
+             1 | (1 + 2\n  \
+               | ^ this '(' is never closed\n  \
+               |       ^ expected ')' before the end of input\n  \
+               = help: add ')' to close the group\n"
+        );
+    }
+
+    #[test]
+    fn test_unmatched_close_brace_points_at_last_group() {
+        let source = "(1 + 2))";
+        let diagnostic = parse_error(source).to_diagnostic(source);
+
+        assert_eq!(diagnostic.code, ErrorCode::ParserUnmatchedCloseBrace);
+        assert_eq!(
+            diagnostic.render(source),
+            "error[E0205]: Unmatched closing brace at position 8\n\
+             1 | (1 + 2))\n  \
+               |        ^ unmatched ')'\n  \
+               | ^ the most recently closed group starts here\n  \
+               = help: remove this ')' or add a matching '('\n"
+        );
+    }
+
+    #[test]
+    fn test_unmatched_close_brace_without_group() {
+        let source = "1)";
+        assert_eq!(
+            parse_error(source).to_diagnostic(source).render(source),
+            "error[E0205]: Unmatched closing brace at position 2\n\
+             1 | 1)\n  \
+               |  ^ unmatched ')'\n  \
+               = help: remove this ')' or add a matching '('\n"
+        );
+    }
+
+    #[test]
+    fn test_labels_on_multiple_lines() {
+        let source = "(1 +\n 2 * (3\n";
+        let diagnostic = Diagnostic {
+            code: ErrorCode::ParserUnclosedBrace,
+            message: "two open braces".to_string(),
+            labels: vec![Label::new(0..1, "outer"), Label::new(10..11, "inner")],
+            help: None,
+        };
+
+        assert_eq!(
+            diagnostic.render(source),
+            "error[E0202]: two open braces\n\
+             1 | (1 +\n  \
+               | ^ outer\n\
+             2 |  2 * (3\n  \
+               |      ^ inner\n"
+        );
+    }
+
+    #[test]
+    fn test_label_on_crlf_line_break() {
+        let source = "(1 +\r\n 2";
+        let diagnostic = Diagnostic {
+            code: ErrorCode::ParserInvalidExpression,
+            message: "line break".to_string(),
+            labels: vec![Label::new(4..5, "on '\\r'"), Label::new(5..6, "on '\\n'")],
+            help: None,
+        };
+
+        assert_eq!(
+            diagnostic.render(source),
+            "error[E0201]: line break\n\
+             1 | (1 +\n  \
+               |     ^ on '\\r'\n  \
+               |     ^ on '\\n'\n"
+        );
+    }
+
+    #[test]
+    fn test_invalid_token_label_covers_multibyte_char() {
+        let source = "1 + é";
+        let diagnostic = parse_error(source).to_diagnostic(source);
+
+        assert_eq!(diagnostic.code, ErrorCode::TokenizerInvalidToken);
+        assert_eq!(
+            diagnostic.labels,
+            vec![Label::new(4..6, "invalid character")]
+        );
+        assert_eq!(
+            diagnostic.render(source),
+            "error[E0102]: Found invalid token 'é' at position 5\n\
+             1 | 1 + é\n  \
+               |     ^ invalid character\n"
+        );
+
+        // A label cutting through 'é' is drawn from its start.
+        let inside = Diagnostic {
+            labels: vec![Label::new(5..6, "inside")],
+            ..diagnostic
+        };
+        assert!(inside.render(source).ends_with("|     ^ inside\n"));
+    }
+
+    #[test]
+    fn test_messages_count_positions_from_one() {
+        for source in ["$", "* 1", ")", ".", "..", "(1", "1 $"] {
+            let error = parse_error(source);
+            let start = if source == "1 $" {
+                "position 3"
+            } else {
+                "position 1"
+            };
+
+            for output in [
+                error.to_string(),
+                error.clone().with_source(source).to_string(),
+                error.to_diagnostic(source).render(source),
+            ] {
+                assert!(output.contains(start), "{source}: {output}");
+                assert!(!output.contains("position 0"), "{source}: {output}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_end_of_input_position_is_one_past_last_char() {
+        assert_eq!(
+            parse_error("1 +").to_string(),
+            "Expression cannot end with operator '+' at position 4"
+        );
+        assert_eq!(
+            parse_error("\u{3000}1 +").to_string(),
+            "Expression cannot end with operator '+' at position 5"
+        );
+    }
+
+    fn diagnostics(source: &str, limit: usize) -> Vec<Diagnostic> {
+        parse_with_diagnostics(source, DiagnosticsLimit(limit)).unwrap_err()
+    }
+
+    #[test]
+    fn test_diagnostics_on_valid_input_and_parser_errors() {
+        assert_eq!(
+            parse_with_diagnostics("(1 + 2) * 3", DiagnosticsLimit::default()),
+            Ok("(1 + 2) * 3".parse().unwrap())
+        );
+
+        let source = "(1 + 2";
+        assert_eq!(
+            diagnostics(source, 100),
+            vec![parse_error(source).to_diagnostic(source)]
+        );
+    }
+
+    #[test]
+    fn test_diagnostics_report_every_invalid_character() {
+        let source = "1 $ 2 # 3";
+        let found = diagnostics(source, 100);
+
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0], parse_error(source).to_diagnostic(source));
+        assert_eq!(found[1].labels, vec![Label::new(6..7, "invalid character")]);
+    }
+
+    #[test]
+    fn test_diagnostics_merge_runs() {
+        let source = format!("1 + {} + #", "@".repeat(50));
+        let found = diagnostics(&source, 100);
+
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].code, ErrorCode::TokenizerInvalidToken);
+        assert_eq!(
+            found[0].labels,
+            vec![Label::new(4..54, "50 invalid characters")]
+        );
+        assert_eq!(
+            found[1].labels,
+            vec![Label::new(57..58, "invalid character")]
+        );
+    }
+
+    #[test]
+    fn test_diagnostics_limit() {
+        let source = "@$".repeat(50_000);
+        let found = diagnostics(&source, 100);
+
+        assert_eq!(found.len(), 101);
+        assert!(
+            found[..100]
+                .iter()
+                .all(|d| d.code == ErrorCode::TokenizerInvalidToken)
+        );
+        assert_eq!(found[100].code, ErrorCode::DiagnosticsTooManyErrors);
+        assert_eq!(
+            found[100].message,
+            "Too many errors: 100 reported, 99900 more suppressed"
+        );
+        assert!(found[100].labels.is_empty());
+
+        assert_eq!(diagnostics(&"@".repeat(100_000), 100).len(), 1);
+        assert_eq!(diagnostics("$ #", 1).len(), 2);
+    }
+
+    fn sourced(source: &str) -> String {
+        parse_error(source).with_source(source).to_string()
+    }
+
+    #[test]
+    fn test_sourced_error_short_input() {
+        assert_eq!(
+            sourced("1 + * 2"),
+            "Operator '*' cannot follow operator '+' at position 5\n  1 + * 2\n      ^"
+        );
+    }
+
+    #[test]
+    fn test_sourced_error_at_start() {
+        assert_eq!(
+            sourced("* 2"),
+            "Expression cannot start with operator '*' at position 1\n  * 2\n  ^"
+        );
+    }
+
+    #[test]
+    fn test_sourced_error_at_end() {
+        assert_eq!(
+            sourced("1 +"),
+            "Expression cannot end with operator '+' at position 4\n  1 +\n     ^"
+        );
+    }
+
+    #[test]
+    fn test_sourced_error_escapes_control_characters() {
+        assert_eq!(
+            sourced("1 +\n\t* 2"),
+            "Operator '*' cannot follow operator '+' at position 6\n  1 +\\n\\t* 2\n         ^"
+        );
+    }
+
+    #[test]
+    fn test_sourced_error_windows_long_input() {
+        let source = format!("{}*{}1", "1+".repeat(125), "1+".repeat(124));
+        assert_eq!(source.len(), 500);
+
+        let window = format!(
+            "{}*{}",
+            "1+".repeat(15),
+            "1+".repeat(15).trim_end_matches('+')
+        );
+        let expected = format!(
+            "Operator '*' cannot follow operator '+' at position 251\n  ...{window}...\n  {:33}^",
+            ""
+        );
+        assert_eq!(sourced(&source), expected);
+    }
+
+    #[test]
+    fn test_sourced_error_without_position() {
+        let error = MathExpressionParserError::EmptyExpression.with_source("");
+        assert_eq!(error.to_string(), "Expression has no tokens");
+        assert_eq!(
+            error.into_inner(),
+            MathExpressionParserError::EmptyExpression
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serialize_diagnostic() {
+        let source = "(1";
+        let json = serde_json::to_string(&parse_error(source).to_diagnostic(source)).unwrap();
+
+        assert_eq!(
+            json,
+            r#"{"code":"E0202","message":"Unclosed brace at position 1","labels":[{"span":{"start":0,"end":1},"note":"this '(' is never closed"},{"span":{"start":2,"end":2},"note":"expected ')' before the end of input"}],"help":"add ')' to close the group"}"#
+        );
+    }
+}
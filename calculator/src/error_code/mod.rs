@@ -0,0 +1,358 @@
+use std::fmt;
+
+// Stable identifiers for every error variant of the crate. A code is never
+// reused once released: retired variants keep their number reserved.
+// Tokenizer errors use the 01xx range, parser errors 02xx, errors of the
+// binary expression encoding 03xx, diagnostics that are not errors of their
+// own 04xx and evaluation errors 05xx.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[repr(u16)]
+pub enum ErrorCode {
+    TokenizerInvalidArgument = 101,
+    TokenizerInvalidToken = 102,
+    TokenizerNoToken = 103,
+    TokenizerPrecisionLoss = 104,
+    TokenizerMalformedNumber = 105,
+    TokenizerConsecutiveOperators = 106,
+    TokenizerTrailingInput = 107,
+    ParserInvalidExpression = 201,
+    ParserUnclosedBrace = 202,
+    ParserCancelled = 203,
+    ParserPositionOverflow = 204,
+    ParserUnmatchedCloseBrace = 205,
+    ParserEmptyExpression = 206,
+    ParserDeadlineExceeded = 207,
+    ParserNotANumber = 208,
+    DecodeTruncated = 301,
+    DecodeUnsupportedVersion = 302,
+    DecodeInvalidTag = 303,
+    DecodeInvalidVarint = 304,
+    DecodeIo = 305,
+    DiagnosticsTooManyErrors = 401,
+    EvaluatorDivisionByZero = 501,
+    EvaluatorResultConversion = 502,
+}
+
+impl ErrorCode {
+    pub const ALL: &'static [ErrorCode] = &[
+        ErrorCode::TokenizerInvalidArgument,
+        ErrorCode::TokenizerInvalidToken,
+        ErrorCode::TokenizerNoToken,
+        ErrorCode::TokenizerPrecisionLoss,
+        ErrorCode::TokenizerMalformedNumber,
+        ErrorCode::TokenizerConsecutiveOperators,
+        ErrorCode::TokenizerTrailingInput,
+        ErrorCode::ParserInvalidExpression,
+        ErrorCode::ParserUnclosedBrace,
+        ErrorCode::ParserCancelled,
+        ErrorCode::ParserPositionOverflow,
+        ErrorCode::ParserUnmatchedCloseBrace,
+        ErrorCode::ParserEmptyExpression,
+        ErrorCode::ParserDeadlineExceeded,
+        ErrorCode::ParserNotANumber,
+        ErrorCode::DecodeTruncated,
+        ErrorCode::DecodeUnsupportedVersion,
+        ErrorCode::DecodeInvalidTag,
+        ErrorCode::DecodeInvalidVarint,
+        ErrorCode::DecodeIo,
+        ErrorCode::DiagnosticsTooManyErrors,
+        ErrorCode::EvaluatorDivisionByZero,
+        ErrorCode::EvaluatorResultConversion,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::TokenizerInvalidArgument => "E0101",
+            ErrorCode::TokenizerInvalidToken => "E0102",
+            ErrorCode::TokenizerNoToken => "E0103",
+            ErrorCode::TokenizerPrecisionLoss => "E0104",
+            ErrorCode::TokenizerMalformedNumber => "E0105",
+            ErrorCode::TokenizerConsecutiveOperators => "E0106",
+            ErrorCode::TokenizerTrailingInput => "E0107",
+            ErrorCode::ParserInvalidExpression => "E0201",
+            ErrorCode::ParserUnclosedBrace => "E0202",
+            ErrorCode::ParserCancelled => "E0203",
+            ErrorCode::ParserPositionOverflow => "E0204",
+            ErrorCode::ParserUnmatchedCloseBrace => "E0205",
+            ErrorCode::ParserEmptyExpression => "E0206",
+            ErrorCode::ParserDeadlineExceeded => "E0207",
+            ErrorCode::ParserNotANumber => "E0208",
+            ErrorCode::DecodeTruncated => "E0301",
+            ErrorCode::DecodeUnsupportedVersion => "E0302",
+            ErrorCode::DecodeInvalidTag => "E0303",
+            ErrorCode::DecodeInvalidVarint => "E0304",
+            ErrorCode::DecodeIo => "E0305",
+            ErrorCode::DiagnosticsTooManyErrors => "E0401",
+            ErrorCode::EvaluatorDivisionByZero => "E0501",
+            ErrorCode::EvaluatorResultConversion => "E0502",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            ErrorCode::TokenizerInvalidArgument => "The tokenizer input is not acceptable",
+            ErrorCode::TokenizerInvalidToken => "A character does not start any valid token",
+            ErrorCode::TokenizerNoToken => "A token was requested after the end of input",
+            ErrorCode::TokenizerPrecisionLoss => "A numeric literal cannot be represented exactly",
+            ErrorCode::TokenizerMalformedNumber => {
+                "A numeric literal has a misplaced decimal point"
+            }
+            ErrorCode::TokenizerConsecutiveOperators => {
+                "A binary operator directly follows another operator"
+            }
+            ErrorCode::TokenizerTrailingInput => "Input continues after the expected single token",
+            ErrorCode::ParserInvalidExpression => "A token cannot appear at its position",
+            ErrorCode::ParserUnclosedBrace => "An open brace is never closed",
+            ErrorCode::ParserCancelled => "Parsing was cancelled through a cancellation token",
+            ErrorCode::ParserPositionOverflow => "A token position does not fit into 32 bits",
+            ErrorCode::ParserUnmatchedCloseBrace => "A close brace has no matching open brace",
+            ErrorCode::ParserEmptyExpression => "The tokenizer produced no tokens",
+            ErrorCode::ParserDeadlineExceeded => "Parsing did not finish before its deadline",
+            ErrorCode::ParserNotANumber => "A digit token holds NaN",
+            ErrorCode::DecodeTruncated => "An encoded expression ends prematurely",
+            ErrorCode::DecodeUnsupportedVersion => {
+                "An encoded expression has an unknown format version"
+            }
+            ErrorCode::DecodeInvalidTag => "An encoded token has an unknown tag",
+            ErrorCode::DecodeInvalidVarint => "An encoded integer is out of range",
+            ErrorCode::DecodeIo => "Reading an encoded expression failed",
+            ErrorCode::DiagnosticsTooManyErrors => {
+                "Further diagnostics were suppressed after reaching the limit"
+            }
+            ErrorCode::EvaluatorDivisionByZero => {
+                "The right operand of a division or remainder is zero"
+            }
+            ErrorCode::EvaluatorResultConversion => {
+                "A result does not convert to the requested type"
+            }
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for ErrorCode {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math_expression_evaluator::MathExpressionEvaluatorError;
+    use crate::math_expression_parser::MathExpressionParserError;
+    use crate::math_expression_parser::encoding::DecodeError;
+    use crate::math_expression_tokenizer::{ArgumentError, MathExpressionTokenizerError};
+    use crate::position::Position;
+    use std::collections::HashSet;
+
+    const TABLE_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/ERROR_CODES.md");
+
+    fn tokenizer_errors() -> Vec<MathExpressionTokenizerError> {
+        vec![
+            MathExpressionTokenizerError::InvalidArgument {
+                reason: ArgumentError::Empty,
+            },
+            MathExpressionTokenizerError::InvalidToken {
+                idx: Position::new(0, 0),
+                ch: '$',
+                suggestion: None,
+            },
+            MathExpressionTokenizerError::NoToken,
+            MathExpressionTokenizerError::PrecisionLoss {
+                idx: Position::new(0, 0),
+                literal: String::new(),
+            },
+            MathExpressionTokenizerError::MalformedNumber {
+                idx: Position::new(0, 0),
+            },
+            MathExpressionTokenizerError::ConsecutiveOperators {
+                first_idx: Position::new(0, 0),
+                second_idx: Position::new(1, 1),
+            },
+            MathExpressionTokenizerError::TrailingInput {
+                idx: Position::new(0, 0),
+            },
+        ]
+    }
+
+    fn parser_errors() -> Vec<MathExpressionParserError> {
+        vec![
+            MathExpressionParserError::InvalidExpression {
+                idx: Position::new(0, 0),
+                found: None,
+                after: None,
+            },
+            MathExpressionParserError::UnclosedBrace {
+                idx: Position::new(0, 0),
+            },
+            MathExpressionParserError::Cancelled {
+                idx: Position::new(0, 0),
+            },
+            MathExpressionParserError::PositionOverflow {
+                idx: Position::new(0, 0),
+            },
+            MathExpressionParserError::NotANumber {
+                idx: Position::new(0, 0),
+            },
+            MathExpressionParserError::UnmatchedCloseBrace {
+                idx: Position::new(0, 0),
+                last_group: None,
+            },
+            MathExpressionParserError::EmptyExpression,
+            MathExpressionParserError::DeadlineExceeded {
+                idx: Position::new(0, 0),
+                consumed_tokens: 0,
+            },
+        ]
+    }
+
+    fn decode_errors() -> Vec<DecodeError> {
+        vec![
+            DecodeError::Truncated,
+            DecodeError::UnsupportedVersion { version: 0 },
+            DecodeError::InvalidTag { tag: 0 },
+            DecodeError::InvalidVarint,
+            DecodeError::Io {
+                kind: std::io::ErrorKind::Other,
+            },
+        ]
+    }
+
+    fn evaluator_errors() -> Vec<MathExpressionEvaluatorError> {
+        vec![
+            MathExpressionEvaluatorError::DivisionByZero {
+                idx: Position::new(0, 0),
+            },
+            MathExpressionEvaluatorError::ResultConversion { value: 0.0 },
+        ]
+    }
+
+    // Fails to compile when a variant is added without extending the samples above.
+    fn is_sampled(
+        tokenizer: &MathExpressionTokenizerError,
+        parser: &MathExpressionParserError,
+        decode: &DecodeError,
+        evaluator: &MathExpressionEvaluatorError,
+    ) {
+        match tokenizer {
+            MathExpressionTokenizerError::InvalidArgument { .. }
+            | MathExpressionTokenizerError::InvalidToken { .. }
+            | MathExpressionTokenizerError::NoToken
+            | MathExpressionTokenizerError::PrecisionLoss { .. }
+            | MathExpressionTokenizerError::MalformedNumber { .. }
+            | MathExpressionTokenizerError::ConsecutiveOperators { .. }
+            | MathExpressionTokenizerError::TrailingInput { .. } => {}
+        }
+        match parser {
+            MathExpressionParserError::Tokenizer(_)
+            | MathExpressionParserError::InvalidExpression { .. }
+            | MathExpressionParserError::UnclosedBrace { .. }
+            | MathExpressionParserError::Cancelled { .. }
+            | MathExpressionParserError::PositionOverflow { .. }
+            | MathExpressionParserError::NotANumber { .. }
+            | MathExpressionParserError::UnmatchedCloseBrace { .. }
+            | MathExpressionParserError::EmptyExpression
+            | MathExpressionParserError::DeadlineExceeded { .. } => {}
+        }
+        match decode {
+            DecodeError::Truncated
+            | DecodeError::UnsupportedVersion { .. }
+            | DecodeError::InvalidTag { .. }
+            | DecodeError::InvalidVarint
+            | DecodeError::Io { .. }
+            | DecodeError::InvalidExpression(_) => {}
+        }
+        match evaluator {
+            MathExpressionEvaluatorError::DivisionByZero { .. }
+            | MathExpressionEvaluatorError::ResultConversion { .. } => {}
+        }
+    }
+
+    fn generate_table() -> String {
+        let mut table = String::from(
+            "# Error codes\n\n\
+             Generated by `cargo test error_code`; set `UPDATE_ERROR_CODES=1` to regenerate.\n\n\
+             | Code | Name | Description |\n\
+             |------|------|-------------|\n",
+        );
+
+        for code in ErrorCode::ALL {
+            table.push_str(&format!(
+                "| {} | {:?} | {} |\n",
+                code.as_str(),
+                code,
+                code.description()
+            ));
+        }
+
+        table
+    }
+
+    #[test]
+    fn test_every_variant_has_unique_code() {
+        is_sampled(
+            &MathExpressionTokenizerError::NoToken,
+            &MathExpressionParserError::EmptyExpression,
+            &DecodeError::Truncated,
+            &MathExpressionEvaluatorError::DivisionByZero {
+                idx: Position::new(0, 0),
+            },
+        );
+
+        let mut codes: Vec<ErrorCode> = tokenizer_errors().iter().map(|e| e.code()).collect();
+        codes.extend(parser_errors().iter().map(|e| e.code()));
+        codes.extend(decode_errors().iter().map(|e| e.code()));
+        codes.extend(evaluator_errors().iter().map(|e| e.code()));
+        // Only used for the summary of `parse_with_diagnostics`.
+        codes.push(ErrorCode::DiagnosticsTooManyErrors);
+
+        let unique: HashSet<_> = codes.iter().collect();
+        assert_eq!(unique.len(), codes.len());
+        assert_eq!(unique.len(), ErrorCode::ALL.len());
+    }
+
+    #[test]
+    fn test_wrapped_tokenizer_error_keeps_code() {
+        for err in tokenizer_errors() {
+            let code = err.code();
+            assert_eq!(MathExpressionParserError::Tokenizer(err).code(), code);
+        }
+        for err in parser_errors() {
+            let code = err.code();
+            assert_eq!(DecodeError::InvalidExpression(err).code(), code);
+        }
+    }
+
+    #[test]
+    fn test_code_strings_match_discriminants() {
+        let strings: HashSet<_> = ErrorCode::ALL.iter().map(|c| c.as_str()).collect();
+        assert_eq!(strings.len(), ErrorCode::ALL.len());
+
+        for code in ErrorCode::ALL {
+            assert_eq!(code.as_str(), format!("E{:04}", *code as u16));
+        }
+    }
+
+    #[test]
+    fn test_error_code_table_in_sync() {
+        let table = generate_table();
+
+        if std::env::var_os("UPDATE_ERROR_CODES").is_some() {
+            std::fs::write(TABLE_PATH, &table).unwrap();
+        }
+
+        let documented = std::fs::read_to_string(TABLE_PATH).unwrap();
+        assert_eq!(
+            documented, table,
+            "ERROR_CODES.md is stale, rerun with UPDATE_ERROR_CODES=1"
+        );
+    }
+}
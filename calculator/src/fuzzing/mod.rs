@@ -0,0 +1,301 @@
+use crate::math_expression_parser::{
+    MathExpression, MathExpressionParser, MathExpressionParserError,
+};
+use crate::math_expression_tokenizer::{
+    MathExpressionTokenizer, MathExpressionTokenizerError, Token, TokenizerTraits,
+};
+use crate::position::Position;
+
+// Entry points for cargo-fuzz (see `fuzz/`). Every function panics only when
+// an internal invariant is broken, so any crash found by the fuzzer is a bug.
+
+pub fn fuzz_tokenize(data: &[u8]) {
+    let expr = String::from_utf8_lossy(data).into_owned();
+    let Ok(mut tokenizer) = MathExpressionTokenizer::new(expr.clone()) else {
+        assert!(expr.trim().is_empty());
+        return;
+    };
+
+    let mut last_idx = None;
+    while tokenizer.has_token() {
+        match tokenizer.next_token() {
+            Ok((idx, token)) => {
+                assert_position(&expr, idx);
+                assert!(last_idx.is_none_or(|last| last < idx));
+                assert_token_matches(&expr, idx, &token);
+                last_idx = Some(idx);
+            }
+            Err(err) => {
+                assert_tokenizer_error(&expr, &err);
+                break;
+            }
+        }
+    }
+}
+
+pub fn fuzz_parse(data: &[u8]) {
+    let expr = String::from_utf8_lossy(data).into_owned();
+    if let Err(err) = parse(&expr) {
+        assert_parser_error(&expr, &err);
+    }
+}
+
+pub fn fuzz_roundtrip(data: &[u8]) {
+    let expr = String::from_utf8_lossy(data).into_owned();
+    let Ok(parsed) = parse_expression(&expr) else {
+        return;
+    };
+
+    let printed = parsed.to_string();
+    let reparsed = parse_expression(&printed)
+        .unwrap_or_else(|err| panic!("'{printed}' printed from '{expr}' failed: {err}"));
+    assert_eq!(
+        parsed.tokens(),
+        reparsed.tokens(),
+        "'{printed}' printed from '{expr}'"
+    );
+}
+
+fn parse(expr: &str) -> Result<Vec<Token>, MathExpressionParserError> {
+    Ok(parse_expression(expr)?.tokens().to_vec())
+}
+
+fn parse_expression(expr: &str) -> Result<MathExpression, MathExpressionParserError> {
+    let tokenizer = MathExpressionTokenizer::new(expr.to_string())?;
+    MathExpressionParser::new().parse(tokenizer)
+}
+
+fn assert_position(expr: &str, idx: usize) {
+    assert!(idx <= expr.len(), "index {idx} out of bounds in '{expr}'");
+    assert!(
+        expr.is_char_boundary(idx),
+        "index {idx} is not a char boundary in '{expr}'"
+    );
+}
+
+fn assert_token_matches(expr: &str, idx: usize, token: &Token) {
+    let ch = expr[idx..].chars().next().unwrap();
+    match token {
+        Token::OpenBrace => assert_eq!(ch, '('),
+        Token::CloseBrace => assert_eq!(ch, ')'),
+        Token::Operator(op) | Token::UnaryOperator(op) => assert_eq!(ch, *op),
+        Token::Digit(_) => assert!(ch.is_ascii_digit() || ch == '.'),
+    }
+}
+
+// Error positions must agree with the input in both coordinates.
+fn assert_error_position(expr: &str, idx: Position) {
+    assert_position(expr, idx.byte);
+    assert_eq!(
+        idx.char,
+        expr[..idx.byte].chars().count(),
+        "char offset of {idx:?} in '{expr}'"
+    );
+}
+
+fn assert_tokenizer_error(expr: &str, err: &MathExpressionTokenizerError) {
+    match err {
+        MathExpressionTokenizerError::InvalidToken { idx, ch, .. } => {
+            assert_error_position(expr, *idx);
+            assert!(expr[idx.byte..].starts_with(*ch));
+        }
+        MathExpressionTokenizerError::InvalidArgument { .. } => assert!(expr.trim().is_empty()),
+        MathExpressionTokenizerError::NoToken => panic!("NoToken leaked for '{expr}'"),
+        MathExpressionTokenizerError::MalformedNumber { idx } => {
+            assert_error_position(expr, *idx);
+            assert!(expr[idx.byte..].starts_with('.'));
+        }
+        MathExpressionTokenizerError::PrecisionLoss { .. }
+        | MathExpressionTokenizerError::ConsecutiveOperators { .. } => {
+            panic!("strict check reported without strict config for '{expr}': {err}")
+        }
+        MathExpressionTokenizerError::TrailingInput { .. } => {
+            panic!("single token check reported while tokenizing '{expr}'")
+        }
+    }
+}
+
+fn assert_parser_error(expr: &str, err: &MathExpressionParserError) {
+    match err {
+        MathExpressionParserError::Tokenizer(err) => assert_tokenizer_error(expr, err),
+        MathExpressionParserError::InvalidExpression { idx, found, .. } => {
+            assert_error_position(expr, *idx);
+            if let Some(token) = found {
+                assert_token_matches(expr, idx.byte, token);
+            }
+        }
+        MathExpressionParserError::UnclosedBrace { idx } => {
+            assert_error_position(expr, *idx);
+            assert!(expr[idx.byte..].starts_with('('));
+        }
+        MathExpressionParserError::UnmatchedCloseBrace { idx, last_group } => {
+            assert_error_position(expr, *idx);
+            assert!(expr[idx.byte..].starts_with(')'));
+            if let Some(group) = last_group {
+                assert_error_position(expr, *group);
+                assert!(group < idx && expr[group.byte..].starts_with('('));
+            }
+        }
+        MathExpressionParserError::EmptyExpression => panic!("empty expression for '{expr}'"),
+        MathExpressionParserError::Cancelled { .. } => panic!("cancelled without a token"),
+        MathExpressionParserError::DeadlineExceeded { .. } => {
+            panic!("deadline exceeded without a deadline")
+        }
+        MathExpressionParserError::PositionOverflow { .. } => {
+            panic!("position overflow in '{expr}'")
+        }
+        MathExpressionParserError::NotANumber { .. } => panic!("NaN digit in '{expr}'"),
+    }
+}
+
+// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::{DiagnosticsLimit, parse_with_diagnostics};
+    use crate::math_expression_parser::{ParserOptions, parse_all};
+    use proptest::prelude::*;
+    use std::panic;
+
+    // Inputs that exercised byte-indexing paths or previously broke an invariant.
+    const REGRESSIONS: &[&[u8]] = &[
+        b"",
+        b" ",
+        b"\xff",
+        b"1\xff",
+        b"(\xf0\x9f\x98\x80",
+        "é+1".as_bytes(),
+        "1 + ñ".as_bytes(),
+        "\u{301}".as_bytes(),
+        b"1 $ 2",
+        b"..",
+        b"1..2",
+        b"((((",
+        b"))))",
+        b"1 +",
+    ];
+
+    #[test]
+    fn test_regression_inputs() {
+        for data in REGRESSIONS {
+            fuzz_tokenize(data);
+            fuzz_parse(data);
+            fuzz_roundtrip(data);
+        }
+
+        let overflowing = "9".repeat(400);
+        fuzz_tokenize(overflowing.as_bytes());
+        fuzz_roundtrip(overflowing.as_bytes());
+    }
+
+    // Everything the public API does with an untrusted `&str`, for checking
+    // that none of it panics. Unlike the fuzz targets this asserts nothing
+    // about the results.
+    fn use_untrusted(source: &str) {
+        let Ok(mut tokenizer) = MathExpressionTokenizer::new(source.to_owned()) else {
+            return;
+        };
+        // Recovering from errors reaches the paths that skip bad input.
+        while tokenizer.has_token() {
+            let _ = tokenizer.next_token();
+        }
+
+        let relaxed = ParserOptions::default()
+            .auto_close_parens(true)
+            .allow_incomplete(true);
+        for options in [ParserOptions::default(), relaxed] {
+            let parser = MathExpressionParser::new().with_options(options);
+            let tokenizer = || MathExpressionTokenizer::new(source.to_owned()).unwrap();
+            let _ = parser.parse_lenient(tokenizer());
+            let _ = parser.parse_partial(&mut tokenizer());
+            if let Ok(parsed) = parser.parse(tokenizer()) {
+                let _ = parsed.to_string();
+                let _ = parsed.canonical_key();
+                let _ = parsed.to_ast();
+                let _ = crate::evaluate(&parsed, source);
+            }
+        }
+
+        let _ = parse_all(source);
+        if let Err(diagnostics) = parse_with_diagnostics(source, DiagnosticsLimit::default()) {
+            for diagnostic in diagnostics {
+                let _ = diagnostic.render(source);
+            }
+        }
+    }
+
+    fn assert_no_panic(source: &str) {
+        let result = panic::catch_unwind(|| use_untrusted(source));
+        assert!(result.is_ok(), "panicked on {source:?}");
+    }
+
+    // Valid UTF-8 that looks broken: lone combining marks, joiners, a BOM,
+    // unassigned and private-use code points, and the largest code point.
+    const UNUSUAL: &[&str] = &[
+        "\u{301}",
+        "1\u{301}",
+        "\u{301}+1",
+        "(\u{20dd}",
+        "1 +\u{200d}2",
+        "\u{feff}1+1",
+        "1\u{fe0f}",
+        "\u{e000}",
+        "\u{10ffff}",
+        "\u{378}",
+        "1.\u{301}",
+        "1 \u{2028} 2",
+        "9\u{0}",
+        "🙂🙂",
+    ];
+
+    #[test]
+    fn test_no_panic_on_corpus() {
+        let regressions = REGRESSIONS
+            .iter()
+            .map(|data| String::from_utf8_lossy(data).into_owned());
+        for source in regressions.chain(UNUSUAL.iter().map(|s| s.to_string())) {
+            assert_no_panic(&source);
+        }
+        assert_no_panic(&"9".repeat(400));
+        assert_no_panic(&"(".repeat(10_000));
+    }
+
+    #[test]
+    fn test_parse_reports_tokenizer_errors() {
+        let err = parse("1 $ 2").unwrap_err();
+        assert_eq!(
+            err,
+            MathExpressionParserError::Tokenizer(MathExpressionTokenizerError::InvalidToken {
+                idx: Position::new(2, 2),
+                ch: '$',
+                suggestion: None,
+            })
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn test_fuzz_arbitrary_bytes(data in proptest::collection::vec(any::<u8>(), 0..64)) {
+            fuzz_tokenize(&data);
+            fuzz_parse(&data);
+            fuzz_roundtrip(&data);
+        }
+
+        #[test]
+        fn test_no_panic_on_any_string(source in any::<String>()) {
+            assert_no_panic(&source);
+        }
+
+        #[test]
+        fn test_no_panic_on_combining_marks(source in r"[0-9+\-*/(). \u{300}-\u{36f}\u{200d}\u{feff}]{0,32}") {
+            assert_no_panic(&source);
+        }
+
+        #[test]
+        fn test_fuzz_expression_soup(s in r"[0-9+\-*/%^(). é]{0,32}") {
+            fuzz_tokenize(s.as_bytes());
+            fuzz_parse(s.as_bytes());
+            fuzz_roundtrip(s.as_bytes());
+        }
+    }
+}
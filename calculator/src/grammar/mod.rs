@@ -0,0 +1,168 @@
+use crate::math_expression_parser::{ParserOptions, UNARY_OPERATORS};
+use crate::math_expression_tokenizer::{OPERATORS, TokenizerConfig};
+
+use std::fmt::Write;
+
+// Literal forms accepted by the tokenizer: the decimal ones of the
+// `MalformedNumber` help, the prefixed integers and digit separators.
+const LITERAL_FORMS: [&str; 8] = ["5", "0.5", ".5", "5.", "2.5e-3", "0xFF", "0b1010", "1_000"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct OperatorDescription {
+    pub symbol: char,
+    pub unary: bool,
+}
+
+// The syntax accepted under a tokenizer configuration and parser options,
+// built from the tables the tokenizer and parser consult, so it cannot drift
+// from what they accept. With the `serde` feature it serializes to JSON.
+// Precedence is not part of parsing, which only checks the token sequence;
+// see `evaluate` for how operators bind.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct GrammarDescription {
+    pub operators: Vec<OperatorDescription>,
+    pub literal_forms: Vec<&'static str>,
+    pub options: Vec<(&'static str, bool)>,
+}
+
+pub fn describe_grammar(config: &TokenizerConfig, options: &ParserOptions) -> GrammarDescription {
+    let TokenizerConfig {
+        reject_precision_loss,
+        reject_consecutive_operators,
+    } = *config;
+    let ParserOptions {
+        auto_close_parens,
+        allow_incomplete,
+    } = *options;
+
+    GrammarDescription {
+        operators: OPERATORS
+            .iter()
+            .map(|&symbol| OperatorDescription {
+                symbol,
+                unary: UNARY_OPERATORS.contains(&symbol),
+            })
+            .collect(),
+        literal_forms: LITERAL_FORMS.to_vec(),
+        options: vec![
+            ("reject_precision_loss", reject_precision_loss),
+            ("reject_consecutive_operators", reject_consecutive_operators),
+            ("auto_close_parens", auto_close_parens),
+            ("allow_incomplete", allow_incomplete),
+        ],
+    }
+}
+
+impl GrammarDescription {
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::from("# Expression grammar\n\n## Operators\n\n");
+        out.push_str("| Operator | Binary | Unary |\n|---|---|---|\n");
+        for operator in &self.operators {
+            let unary = if operator.unary { "yes" } else { "no" };
+            let _ = writeln!(out, "| `{}` | yes | {unary} |", operator.symbol);
+        }
+
+        out.push_str("\n## Literals\n\n");
+        for form in &self.literal_forms {
+            let _ = writeln!(out, "- `{form}`");
+        }
+        out.push_str(
+            "\nParentheses `(` and `)` group sub-expressions. Whitespace between \
+             tokens is ignored.\n\n## Options\n\n",
+        );
+        for (name, enabled) in &self.options {
+            let state = if *enabled { "on" } else { "off" };
+            let _ = writeln!(out, "- `{name}`: {state}");
+        }
+
+        out
+    }
+}
+
+// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math_expression_parser::MathExpression;
+    use crate::math_expression_tokenizer::Token;
+
+    use std::collections::BTreeSet;
+
+    fn described() -> GrammarDescription {
+        describe_grammar(&TokenizerConfig::default(), &ParserOptions::default())
+    }
+
+    // Every character that tokenizes to an operator on its own.
+    fn probe_operators() -> BTreeSet<char> {
+        (0..=0x3000)
+            .filter_map(char::from_u32)
+            .filter(|ch| matches!(ch.to_string().parse(), Ok(Token::Operator(op)) if op == *ch))
+            .collect()
+    }
+
+    #[test]
+    fn test_operators_match_the_tokenizer() {
+        let operators: BTreeSet<char> = described()
+            .operators
+            .iter()
+            .map(|operator| operator.symbol)
+            .collect();
+
+        assert_eq!(operators, probe_operators());
+    }
+
+    #[test]
+    fn test_unary_operators_match_the_parser() {
+        for operator in described().operators {
+            let unary = format!("{}1", operator.symbol).parse::<MathExpression>();
+            assert_eq!(unary.is_ok(), operator.unary, "{}", operator.symbol);
+        }
+    }
+
+    #[test]
+    fn test_literal_forms_parse() {
+        for form in described().literal_forms {
+            assert!(matches!(form.parse(), Ok(Token::Digit(_))), "{form}");
+        }
+    }
+
+    #[test]
+    fn test_options_reflect_configuration() {
+        let grammar = describe_grammar(
+            &TokenizerConfig::strict(),
+            &ParserOptions::default().auto_close_parens(true),
+        );
+
+        assert_eq!(
+            grammar.options,
+            vec![
+                ("reject_precision_loss", true),
+                ("reject_consecutive_operators", true),
+                ("auto_close_parens", true),
+                ("allow_incomplete", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_markdown() {
+        let markdown = described().to_markdown();
+
+        assert!(markdown.starts_with("# Expression grammar\n"));
+        assert!(markdown.contains("| `-` | yes | yes |\n"));
+        assert!(markdown.contains("| `*` | yes | no |\n"));
+        assert!(markdown.contains("- `.5`\n"));
+        assert!(markdown.ends_with("- `allow_incomplete`: off\n"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serialize_grammar() {
+        let json = serde_json::to_string(&described()).unwrap();
+
+        assert!(json.starts_with(r#"{"operators":[{"symbol":"+","unary":true},"#));
+        assert!(json.ends_with(r#"["auto_close_parens",false],["allow_incomplete",false]]}"#));
+    }
+}
@@ -0,0 +1,183 @@
+use crate::math_expression_tokenizer::{MathExpressionTokenizer, Token, TokenizerTraits};
+use crate::position::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum TokenClass {
+    Number,
+    Operator,
+    Paren,
+    Whitespace,
+    Invalid,
+}
+
+// Classifies every byte of `expr` in one tokenizer pass. The spans tile the
+// input: they are non-empty, in order, and each starts where the previous
+// one ends. The tokenizer skips past each error, so an invalid character or
+// malformed literal becomes an `Invalid` span and the rest is still
+// classified.
+pub fn highlight_spans(expr: &str) -> Vec<(Span, TokenClass)> {
+    let mut tokenizer = match MathExpressionTokenizer::new(expr.to_string()) {
+        Ok(tokenizer) => tokenizer,
+        Err(_) if expr.is_empty() => return Vec::new(),
+        Err(_) if whitespace_len(expr) == expr.len() => {
+            return vec![(Span::new(0, expr.len()), TokenClass::Whitespace)];
+        }
+        Err(_) => return vec![(Span::new(0, expr.len()), TokenClass::Invalid)],
+    };
+
+    let mut spans = Vec::new();
+    while tokenizer.has_token() {
+        let before = tokenizer.curr_index();
+        let start = before + whitespace_len(&expr[before..]);
+        if start > before {
+            spans.push((Span::new(before, start), TokenClass::Whitespace));
+        }
+
+        spans.push(match tokenizer.next_spanned() {
+            Ok((span, Token::Digit(_))) => (span, TokenClass::Number),
+            Ok((span, Token::Operator(_) | Token::UnaryOperator(_))) => {
+                (span, TokenClass::Operator)
+            }
+            Ok((span, Token::OpenBrace | Token::CloseBrace)) => (span, TokenClass::Paren),
+            Err(_) => (
+                Span::new(start, tokenizer.curr_index()),
+                TokenClass::Invalid,
+            ),
+        });
+    }
+
+    let end = tokenizer.curr_index();
+    if end < expr.len() {
+        spans.push((Span::new(end, expr.len()), TokenClass::Whitespace));
+    }
+
+    spans
+}
+
+// Renders `expr` with ANSI colors per token class, e.g. for echoing input
+// next to an error in a terminal. Whitespace is left uncolored.
+pub fn highlight_ansi(expr: &str) -> String {
+    const RESET: &str = "\x1b[0m";
+
+    let mut out = String::with_capacity(expr.len() * 2);
+    for (span, class) in highlight_spans(expr) {
+        let color = match class {
+            TokenClass::Number => "\x1b[36m",
+            TokenClass::Operator => "\x1b[33m",
+            TokenClass::Paren => "\x1b[35m",
+            TokenClass::Invalid => "\x1b[4;31m",
+            TokenClass::Whitespace => {
+                out.push_str(span.slice(expr));
+                continue;
+            }
+        };
+
+        out.push_str(color);
+        out.push_str(span.slice(expr));
+        out.push_str(RESET);
+    }
+
+    out
+}
+
+// Same set as the tokenizer skips.
+fn whitespace_len(s: &str) -> usize {
+    s.find(|ch: char| !ch.is_whitespace()).unwrap_or(s.len())
+}
+
+// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn classes(expr: &str) -> Vec<(&str, TokenClass)> {
+        highlight_spans(expr)
+            .into_iter()
+            .map(|(span, class)| (span.slice(expr), class))
+            .collect()
+    }
+
+    fn assert_tiles(expr: &str) {
+        let spans = highlight_spans(expr);
+        let mut end = 0;
+        for (span, _) in &spans {
+            assert_eq!(span.start, end, "gap or overlap in {expr:?}: {spans:?}");
+            assert!(span.end > span.start, "empty span in {expr:?}: {spans:?}");
+            assert!(expr.is_char_boundary(span.end));
+            end = span.end;
+        }
+        assert_eq!(end, expr.len(), "{expr:?}: {spans:?}");
+    }
+
+    #[test]
+    fn test_classifies_tokens() {
+        use TokenClass::*;
+
+        assert_eq!(
+            classes(" (12.5+ -3)* x "),
+            vec![
+                (" ", Whitespace),
+                ("(", Paren),
+                ("12.5", Number),
+                ("+", Operator),
+                (" ", Whitespace),
+                ("-", Operator),
+                ("3", Number),
+                (")", Paren),
+                ("*", Operator),
+                (" ", Whitespace),
+                ("x", Invalid),
+                (" ", Whitespace),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_regions_do_not_stop_highlighting() {
+        use TokenClass::*;
+
+        assert_eq!(
+            classes("1.2.3 + é\u{3000}2"),
+            vec![
+                ("1.2.3", Invalid),
+                (" ", Whitespace),
+                ("+", Operator),
+                (" ", Whitespace),
+                ("é", Invalid),
+                ("\u{3000}", Whitespace),
+                ("2", Number),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_empty_and_blank_input() {
+        assert_eq!(highlight_spans(""), vec![]);
+        assert_eq!(
+            highlight_spans(" \t\n"),
+            vec![(Span::new(0, 3), TokenClass::Whitespace)]
+        );
+    }
+
+    #[test]
+    fn test_ansi_rendering() {
+        assert_eq!(
+            highlight_ansi("(1 $"),
+            "\x1b[35m(\x1b[0m\x1b[36m1\x1b[0m \x1b[4;31m$\x1b[0m"
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn test_spans_tile_any_input(expr in "\\PC{0,24}") {
+            assert_tiles(&expr);
+        }
+
+        #[test]
+        fn test_spans_tile_expression_like_input(expr in "[0-9+\\-*/(). \t\u{3000}é$]{0,32}") {
+            assert_tiles(&expr);
+        }
+    }
+}
@@ -1,2 +1,43 @@
+// Updates the profiling counters of the current thread. Without the
+// `profiling` feature the whole statement is compiled out.
+macro_rules! profile {
+    ($stats:ident => $update:expr) => {
+        #[cfg(feature = "profiling")]
+        $crate::profiling::record(|$stats| $update);
+    };
+}
+
+pub mod batch;
+pub mod bench_corpus;
+pub mod cancellation;
+pub mod compare;
+pub mod cost;
+pub mod diagnostic;
+pub mod error_code;
+pub mod fuzzing;
+pub mod grammar;
+pub mod highlight;
+pub mod math_expression_evaluator;
+pub mod math_expression_parser;
 pub mod math_expression_tokenizer;
-pub mod math_expression_parser;
\ No newline at end of file
+pub mod position;
+#[cfg(feature = "profiling")]
+pub mod profiling;
+pub mod routing;
+pub mod spreadsheet;
+pub mod template;
+
+pub use math_expression_evaluator::{
+    AuditEntry, CalcError, Evaluator, MathExpressionEvaluatorError, evaluate, evaluate_audited,
+    evaluate_into, evaluate_tree, evaluate_with_decimals, evaluate_with_error_bound, format_result,
+    parse_and_evaluate, parse_and_evaluate_audited,
+};
+pub use math_expression_parser::{
+    Expr, ExprArena, ExpressionBuilder, MathExpression, MathExpressionParser,
+    MathExpressionParserError, ParseFailure, ParseWarning, ParserOptions, TreeNode, WarningKind,
+};
+pub use math_expression_tokenizer::{
+    ArgumentError, MathExpressionTokenizer, MathExpressionTokenizerError, PeekableTokenizer, Token,
+    TokenError, TokenizerConfig, TokenizerTraits, TokenizerWarning,
+};
+pub use position::{IndexBase, Position, Span};
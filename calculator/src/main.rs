@@ -1,5 +1 @@
-use calculator::math_expression_parser;
-use calculator::math_expression_tokenizer;
-
-fn main() {
-}
+fn main() {}
@@ -0,0 +1,179 @@
+use super::{Arithmetic, MathExpressionEvaluatorError, ZeroDivisor};
+use crate::math_expression_parser::MathExpression;
+use crate::math_expression_parser::precedence::{Fold, fold};
+use crate::math_expression_tokenizer::{Token, scan};
+
+// Same as `evaluate`, together with the number of decimal places the result
+// is written with if it follows the literals it was computed from, as a
+// calculator would show it: `0.10 + 0.20` is `0.30` and `2.5 * 2` is `5.0`.
+// A literal has the decimal places it was written with in `source`, counting
+// its exponent, so `1.5e1` has none and `0x10` has none either. Then:
+//
+//   '+', '-', '%'   the most decimal places of the operands
+//   '*'             the sum of those of the operands, as in exact products
+//   '/', '^'        none that follows, so the result is `None`
+//
+// Unary operators and groups keep the places of their operand, and `None`
+// spreads through every operator. Pass the places to `format_result`.
+pub fn evaluate_with_decimals(
+    expr: &MathExpression,
+    source: &str,
+) -> Result<(f64, Option<usize>), MathExpressionEvaluatorError> {
+    let literals = expr
+        .positioned_tokens()
+        .filter(|(_, token)| matches!(token, Token::Digit(_)))
+        .map(|(idx, _)| literal_decimals(source, idx))
+        .collect();
+
+    let mut folder = Decimals {
+        literals,
+        next_literal: 0,
+    };
+    fold(expr, &mut folder).map_err(|zero_divisor| zero_divisor.in_source(source))
+}
+
+// `value` with `decimals` places, or with the fewest digits that read back as
+// `value` when there are none to follow or it is not finite.
+pub fn format_result(value: f64, decimals: Option<usize>) -> String {
+    match decimals {
+        Some(decimals) if value.is_finite() => format!("{value:.decimals$}"),
+        _ => value.to_string(),
+    }
+}
+
+struct Decimals {
+    // Decimal places of every literal, in the order they are folded.
+    literals: Vec<Option<usize>>,
+    next_literal: usize,
+}
+
+impl Fold for Decimals {
+    type Value = (f64, Option<usize>);
+    type Error = ZeroDivisor;
+
+    fn number(&mut self, number: f64) -> Self::Value {
+        let decimals = self.literals.get(self.next_literal).copied().flatten();
+        self.next_literal += 1;
+        (number, decimals)
+    }
+
+    fn unary(&mut self, op: char, (operand, decimals): Self::Value) -> Self::Value {
+        (Arithmetic.unary(op, operand), decimals)
+    }
+
+    fn binary(
+        &mut self,
+        op: char,
+        idx: usize,
+        (lhs, lhs_decimals): Self::Value,
+        (rhs, rhs_decimals): Self::Value,
+    ) -> Result<Self::Value, ZeroDivisor> {
+        let value = Arithmetic.binary(op, idx, lhs, rhs)?;
+        let decimals = lhs_decimals
+            .zip(rhs_decimals)
+            .and_then(|(lhs, rhs)| match op {
+                '+' | '-' | '%' => Some(lhs.max(rhs)),
+                '*' => lhs.checked_add(rhs),
+                _ => None,
+            });
+        Ok((value, decimals))
+    }
+
+    fn group(&mut self, inner: Self::Value) -> Self::Value {
+        inner
+    }
+}
+
+// Decimal places of the literal at byte `idx` of `source`, or `None` if there
+// is no literal there, as for a source that does not match the expression.
+fn literal_decimals(source: &str, idx: usize) -> Option<usize> {
+    let rest = source.get(idx..)?;
+    let literal = &rest[..scan::literal_len(rest)];
+    if literal.is_empty() {
+        return None;
+    }
+    if scan::radix_prefix(literal).is_some() {
+        return Some(0);
+    }
+
+    let literal = scan::without_separators(literal);
+    let (mantissa, exponent) = literal.split_once(['e', 'E']).unwrap_or((&literal, "0"));
+    let fraction = mantissa
+        .split_once('.')
+        .map_or(0, |(_, fraction)| fraction.len());
+    // Exponents too large for `i64` overflow or underflow the literal anyway.
+    let exponent = exponent.parse::<i64>().ok()?;
+
+    let decimals = i64::try_from(fraction).ok()?.saturating_sub(exponent);
+    Some(usize::try_from(decimals).unwrap_or(0))
+}
+
+// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math_expression_parser::tests::expression;
+    use proptest::prelude::*;
+
+    fn shown(source: &str) -> String {
+        let (value, decimals) = evaluate_with_decimals(&source.parse().unwrap(), source).unwrap();
+        format_result(value, decimals)
+    }
+
+    #[test]
+    fn test_results_follow_the_literals() {
+        assert_eq!(shown("0.10 + 0.20"), "0.30");
+        assert_eq!(shown("2.5 * 2"), "5.0");
+        assert_eq!(shown("1.25 * 0.2"), "0.250");
+        assert_eq!(shown("3 - 1.50"), "1.50");
+        assert_eq!(shown("-(0.5 + 1)"), "-1.5");
+        assert_eq!(shown("7.50 % 2"), "1.50");
+        assert_eq!(shown("42"), "42");
+    }
+
+    #[test]
+    fn test_literal_forms() {
+        assert_eq!(shown("1.5e2 + 0.25"), "150.25");
+        assert_eq!(shown("2.50E-1 + 0"), "0.250");
+        assert_eq!(shown("1.5e1 * 3"), "45");
+        assert_eq!(shown("0x10 * 1.5"), "24.0");
+        assert_eq!(shown("1_000.50 - 0.5"), "1000.00");
+        assert_eq!(shown(".5 + 5."), "5.5");
+    }
+
+    #[test]
+    fn test_division_and_powers_are_shown_in_full() {
+        assert_eq!(shown("1 / 3"), "0.3333333333333333");
+        assert_eq!(shown("1.0 / 4"), "0.25");
+        assert_eq!(shown("2 ^ 0.5 + 1.00"), (2f64.sqrt() + 1.0).to_string());
+        assert_eq!(shown("1.5 * 2 ^ 2"), "6");
+    }
+
+    #[test]
+    fn test_switched_off() {
+        let source = "0.10 + 0.20";
+        let (value, _) = evaluate_with_decimals(&source.parse().unwrap(), source).unwrap();
+        assert_eq!(format_result(value, None), "0.30000000000000004");
+        assert_eq!(format_result(f64::INFINITY, Some(2)), "inf");
+    }
+
+    #[test]
+    fn test_mismatched_source() {
+        let parsed: MathExpression = "1.5 + 2.25".parse().unwrap();
+        assert_eq!(evaluate_with_decimals(&parsed, "x"), Ok((3.75, None)));
+    }
+
+    proptest! {
+        #[test]
+        fn test_value_matches_evaluate(source in expression()) {
+            let parsed = source.parse().unwrap();
+            let value = evaluate_with_decimals(&parsed, &source).map(|(value, _)| value);
+            match (value, crate::evaluate(&parsed, &source)) {
+                (Ok(value), Ok(expected)) => prop_assert!(
+                    value.to_bits() == expected.to_bits() || (value.is_nan() && expected.is_nan())
+                ),
+                (value, expected) => prop_assert_eq!(value, expected),
+            }
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use super::{Arithmetic, MathExpressionEvaluatorError, ZeroDivisor};
+use crate::math_expression_parser::MathExpression;
+use crate::math_expression_parser::precedence::{Fold, fold};
+
+// Same as `evaluate`, together with an upper bound on the absolute rounding
+// error of the result. The bound follows a first-order model: every literal
+// that is not a small integer and every operation is off by up to half an
+// ulp of its value, and the errors of the operands are carried through each
+// operation as its derivatives dictate. Subtracting near-equal values keeps
+// the absolute error of the operands while the result shrinks, so
+// cancellation shows up as a bound that is large next to the result, e.g. 3
+// for `1e16 + 1 - 1e16`, which gives 0 instead of 1.
+//
+// Where the model breaks down the bound is infinite: for non-finite results,
+// a divisor or base whose error may reach zero, and a remainder whose
+// quotient may be off by one.
+pub fn evaluate_with_error_bound(
+    expr: &MathExpression,
+    source: &str,
+) -> Result<(f64, f64), MathExpressionEvaluatorError> {
+    fold(expr, &mut Bounded)
+        .map(|value| (value.value, value.error))
+        .map_err(|zero_divisor| zero_divisor.in_source(source))
+}
+
+// Integers up to this size are exact as `f64`, whatever literal they were
+// written as.
+const EXACT_INTEGERS: f64 = 9_007_199_254_740_992.0;
+
+#[derive(Debug, Clone, Copy)]
+struct Bound {
+    value: f64,
+    error: f64,
+}
+
+struct Bounded;
+
+impl Fold for Bounded {
+    type Value = Bound;
+    type Error = ZeroDivisor;
+
+    fn number(&mut self, number: f64) -> Bound {
+        let exact = number.fract() == 0.0 && number.abs() <= EXACT_INTEGERS;
+        Bound {
+            value: number,
+            error: if exact { 0.0 } else { half_ulp(number) },
+        }
+    }
+
+    fn unary(&mut self, op: char, operand: Bound) -> Bound {
+        Bound {
+            value: Arithmetic.unary(op, operand.value),
+            error: operand.error,
+        }
+    }
+
+    fn binary(
+        &mut self,
+        op: char,
+        idx: usize,
+        lhs: Bound,
+        rhs: Bound,
+    ) -> Result<Bound, ZeroDivisor> {
+        let value = Arithmetic.binary(op, idx, lhs.value, rhs.value)?;
+        let (a, b) = (lhs.value.abs(), rhs.value.abs());
+        let (ea, eb) = (lhs.error, rhs.error);
+
+        let propagated = match op {
+            '+' | '-' => ea + eb,
+            '*' => a * eb + b * ea + ea * eb,
+            '/' if eb < b => (ea + value.abs() * eb) / (b - eb),
+            '/' => f64::INFINITY,
+            // The remainder itself is exact. It moves with the dividend, and
+            // with the divisor times the quotient, unless the quotient may
+            // change, which makes it jump by up to the divisor.
+            '%' => {
+                let quotient = (lhs.value / rhs.value).trunc().abs();
+                let shift = ea + quotient * eb;
+                let room = value.abs().min(b - value.abs());
+                if shift < room { shift } else { b + eb }
+            }
+            // d(a^b) = b a^(b-1) da + ln(a) a^b db
+            '^' if ea < a || ea == 0.0 => {
+                let from_base = if ea == 0.0 { 0.0 } else { b * ea / (a - ea) };
+                let from_exponent = if eb == 0.0 { 0.0 } else { a.ln().abs() * eb };
+                value.abs() * (from_base + from_exponent)
+            }
+            _ => f64::INFINITY,
+        };
+        // `powf` is not correctly rounded; allow it a whole ulp.
+        let rounding = if op == '^' { 2.0 } else { 1.0 } * half_ulp(value);
+
+        // NaN comes from an infinite derivative at a zero result, e.g. for
+        // `0 ^ 0.5` with an error in the exponent.
+        let error = if value.is_finite() && !propagated.is_nan() {
+            propagated + rounding
+        } else {
+            f64::INFINITY
+        };
+        Ok(Bound { value, error })
+    }
+
+    fn group(&mut self, inner: Bound) -> Bound {
+        inner
+    }
+}
+
+// Half the distance from `value` to the next `f64` away from zero, the most
+// rounding to nearest can change a result of this size.
+fn half_ulp(value: f64) -> f64 {
+    let value = value.abs();
+    if !value.is_finite() {
+        return f64::INFINITY;
+    }
+    if value == f64::MAX {
+        return (value - f64::from_bits(value.to_bits() - 1)) / 2.0;
+    }
+    (f64::from_bits(value.to_bits() + 1) - value) / 2.0
+}
+
+// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math_expression_parser::tests::expression;
+    use crate::position::Position;
+    use proptest::prelude::*;
+
+    fn bound(source: &str) -> (f64, f64) {
+        evaluate_with_error_bound(&source.parse().unwrap(), source).unwrap()
+    }
+
+    #[test]
+    fn test_exact_integer_arithmetic_has_a_tiny_bound() {
+        let (value, error) = bound("1 + 2 * 3 - 4");
+        assert_eq!(value, 3.0);
+        // Only the half ulps the model charges every operation.
+        assert!(error <= 3.0 * half_ulp(6.0), "{error}");
+        assert_eq!(bound("42"), (42.0, 0.0));
+    }
+
+    // The true errors are worked out by hand from the decimal literals, which
+    // the tests cannot do with `f64` in general.
+    #[test]
+    fn test_bound_covers_the_true_error() {
+        let cases = [
+            // Exact result 1.
+            ("1e16 + 1 - 1e16", 1.0),
+            // Exact result 0.
+            ("0.1 + 0.2 - 0.3", 0.1 + 0.2 - 0.3),
+            (
+                "2 ^ 0.5 * 2 ^ 0.5 - 2",
+                (2f64.sqrt() * 2f64.sqrt() - 2.0).abs(),
+            ),
+            // Exact result 1e-15.
+            ("(1 + 1e-15) - 1", (1.0 + 1e-15) - 1.0 - 1e-15),
+            // Exact result 1, and 1 / 3 * 3 happens to round back to 1.
+            ("1 / 3 * 3", 0.0),
+            // Exact result 0.1, printed as 0.1 but not equal to it.
+            ("0.7 - 0.6", (0.7 - 0.6 - 0.1f64).abs()),
+        ];
+
+        for (source, true_error) in cases {
+            let (value, error) = bound(source);
+            assert!(
+                error >= true_error.abs(),
+                "{source}: bound {error} below true error {true_error}"
+            );
+            // Benign cases stay within a few ulps of the result.
+            if value.abs() > 1e-3 {
+                assert!(
+                    error <= 1e-14 * value.abs(),
+                    "{source}: bound {error} for {value}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_cancellation_gives_a_large_bound() {
+        let (value, error) = bound("1e16 + 1 - 1e16");
+        assert_eq!(value, 0.0);
+        // Half an ulp of 1e16 for each literal and for the sum.
+        assert_eq!(error, 3.0);
+
+        let (value, error) = bound("(0.1 + 0.2 - 0.3) * 1e17");
+        assert!(error > value.abs() / 10.0, "{value} {error}");
+    }
+
+    #[test]
+    fn test_unbounded_cases() {
+        assert_eq!(bound("1e308 * 10").1, f64::INFINITY);
+        assert_eq!(bound("1 / (0.1 + 0.2 - 0.3)").1, f64::INFINITY);
+        assert_eq!(bound("1e16 % 0.1").1, 0.1 + half_ulp(0.1));
+        assert!(bound("(-8) ^ 0.5").1.is_infinite());
+        assert_eq!(bound("0 ^ 2"), (0.0, half_ulp(0.0) * 2.0));
+        assert_eq!(bound("0 ^ 0.1").1, f64::INFINITY);
+        assert_eq!(
+            evaluate_with_error_bound(&"1 / 0".parse().unwrap(), "1 / 0"),
+            Err(MathExpressionEvaluatorError::DivisionByZero {
+                idx: Position::new(2, 2)
+            })
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn test_value_matches_evaluate(source in expression()) {
+            let parsed = source.parse().unwrap();
+            match (evaluate_with_error_bound(&parsed, &source), crate::evaluate(&parsed, &source)) {
+                (Ok((value, error)), Ok(expected)) => {
+                    prop_assert!(
+                        value.to_bits() == expected.to_bits() || (value.is_nan() && expected.is_nan())
+                    );
+                    prop_assert!(error >= 0.0, "{}", error);
+                }
+                (value, expected) => prop_assert_eq!(value.map(|_| ()), expected.map(|_| ())),
+            }
+        }
+    }
+}
@@ -0,0 +1,773 @@
+mod decimals;
+mod error_bound;
+
+pub use decimals::{evaluate_with_decimals, format_result};
+pub use error_bound::evaluate_with_error_bound;
+
+use crate::diagnostic::{Diagnostic, Label};
+use crate::error_code::ErrorCode;
+use crate::math_expression_parser::precedence::{Fold, FoldStacks, fold, fold_with};
+use crate::math_expression_parser::{
+    BraceStack, MathExpression, MathExpressionParserError, Next, TreeNode, check_end, fold_tree,
+    next_checked,
+};
+use crate::math_expression_tokenizer::TokenizerTraits;
+use crate::position::Position;
+
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq, Error)]
+#[non_exhaustive]
+pub enum MathExpressionEvaluatorError {
+    // `idx` is the position of the '/' or '%' in the source.
+    #[error("Division by zero at position {idx}")]
+    DivisionByZero { idx: Position },
+    // `value` is the result `evaluate_into` could not convert.
+    #[error("Result {value} cannot be converted to the requested type")]
+    ResultConversion { value: f64 },
+}
+
+impl MathExpressionEvaluatorError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            MathExpressionEvaluatorError::DivisionByZero { .. } => {
+                ErrorCode::EvaluatorDivisionByZero
+            }
+            MathExpressionEvaluatorError::ResultConversion { .. } => {
+                ErrorCode::EvaluatorResultConversion
+            }
+        }
+    }
+
+    pub fn to_diagnostic(&self, source: &str) -> Diagnostic {
+        let (labels, help) = match self {
+            MathExpressionEvaluatorError::DivisionByZero { idx } => (
+                vec![Label::at_char(
+                    source,
+                    idx.byte,
+                    "the right operand is zero",
+                )],
+                Some("make sure the divisor is not zero".to_string()),
+            ),
+            MathExpressionEvaluatorError::ResultConversion { .. } => (
+                vec![],
+                Some("the result is out of the range or precision of the type".to_string()),
+            ),
+        };
+
+        Diagnostic {
+            code: self.code(),
+            message: self.to_string(),
+            labels,
+            help,
+        }
+    }
+}
+
+// Errors of `parse_and_evaluate`.
+#[derive(Debug, Clone, PartialEq, Error)]
+#[non_exhaustive]
+pub enum CalcError {
+    #[error("Parser error: {0}")]
+    Parser(#[from] MathExpressionParserError),
+    #[error("Evaluator error: {0}")]
+    Evaluator(#[from] MathExpressionEvaluatorError),
+}
+
+impl CalcError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            CalcError::Parser(err) => err.code(),
+            CalcError::Evaluator(err) => err.code(),
+        }
+    }
+
+    pub fn to_diagnostic(&self, source: &str) -> Diagnostic {
+        match self {
+            CalcError::Parser(err) => err.to_diagnostic(source),
+            CalcError::Evaluator(err) => err.to_diagnostic(source),
+        }
+    }
+}
+
+// Computes the value of `expr` with the usual precedence: '^' before '*', '/'
+// and '%' before '+' and '-', and parenthesised groups first. Overflow gives
+// an infinity and a power without a real value (`(-8) ^ 0.5`) gives NaN, as in
+// `f64` arithmetic; only dividing by zero, or taking a remainder by zero, is
+// an error.
+//
+// '%' is the remainder of truncating division, as `f64::rem`: it has the sign
+// of the dividend, so `-7 % 3` is -1 and `7 % -3` is 1.
+//
+// The operations are carried out exactly as written, one `f64` operation per
+// operator, left to right among operators of equal precedence (right to left
+// for '^'). Nothing is reassociated or folded ahead of time, so the result is
+// the same on every platform; `evaluate_audited` logs the operations.
+//
+// `source` is the text `expr` was parsed from, or its printed form for a
+// built expression; error positions are resolved in it.
+pub fn evaluate(expr: &MathExpression, source: &str) -> Result<f64, MathExpressionEvaluatorError> {
+    fold(expr, &mut Arithmetic).map_err(|zero_divisor| zero_divisor.in_source(source))
+}
+
+// Same as `evaluate`, converting the result to `T`, e.g. a fixed-point type.
+// Whether a value converts, and how it is rounded, is up to `T::try_from`.
+pub fn evaluate_into<T: TryFrom<f64>>(
+    expr: &MathExpression,
+    source: &str,
+) -> Result<T, MathExpressionEvaluatorError> {
+    let value = evaluate(expr, source)?;
+    T::try_from(value).map_err(|_| MathExpressionEvaluatorError::ResultConversion { value })
+}
+
+// One binary operation of an evaluation. Operands and result are kept as raw
+// bits, so logs of two evaluations can be compared exactly, NaN included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AuditEntry {
+    pub op: char,
+    pub lhs_bits: u64,
+    pub rhs_bits: u64,
+    pub result_bits: u64,
+}
+
+// Same as `evaluate`, appending every binary operation to `log` in the order
+// it is carried out. Unary operators only flip or keep the sign and are not
+// logged. On division by zero `log` ends with the last operation that
+// succeeded.
+pub fn evaluate_audited(
+    expr: &MathExpression,
+    source: &str,
+    log: &mut Vec<AuditEntry>,
+) -> Result<f64, MathExpressionEvaluatorError> {
+    fold(expr, &mut Audited { log }).map_err(|zero_divisor| zero_divisor.in_source(source))
+}
+
+// Evaluates a tree like `evaluate` evaluates its expression, whether the tree
+// is boxed or in an `ExprArena`. Trees carry no positions to report a
+// division by zero at, so that gives `None`.
+pub fn evaluate_tree(root: impl TreeNode) -> Option<f64> {
+    fold_tree(root, &mut Arithmetic).ok()
+}
+
+// Evaluates expressions one after another like `evaluate`, keeping the stacks
+// it would allocate for every expression. They are emptied before each use,
+// so an evaluation that failed halfway leaves nothing behind for the next.
+pub struct Evaluator {
+    stacks: FoldStacks<f64>,
+}
+
+impl Default for Evaluator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Evaluator {
+    pub fn new() -> Self {
+        Self {
+            stacks: FoldStacks::new(),
+        }
+    }
+
+    pub fn evaluate(
+        &mut self,
+        expr: &MathExpression,
+        source: &str,
+    ) -> Result<f64, MathExpressionEvaluatorError> {
+        fold_with(expr, &mut Arithmetic, &mut self.stacks)
+            .map_err(|zero_divisor| zero_divisor.in_source(source))
+    }
+}
+
+// Parses and evaluates in a single pass over the tokens, without storing
+// them, so memory grows with the nesting depth of the expression rather than
+// its length. The result and the errors, with their positions, are those of
+// parsing with a default `MathExpressionParser` and calling `evaluate`: a
+// division by zero is only reported once the rest of the input parses.
+pub fn parse_and_evaluate<Tokenizer: TokenizerTraits>(
+    tokenizer: Tokenizer,
+) -> Result<f64, CalcError> {
+    parse_and_fold(tokenizer, &mut Arithmetic)
+}
+
+// Same as `parse_and_evaluate`, logging the operations as `evaluate_audited`.
+pub fn parse_and_evaluate_audited<Tokenizer: TokenizerTraits>(
+    tokenizer: Tokenizer,
+    log: &mut Vec<AuditEntry>,
+) -> Result<f64, CalcError> {
+    parse_and_fold(tokenizer, &mut Audited { log })
+}
+
+fn parse_and_fold<Tokenizer, F>(mut tokenizer: Tokenizer, folder: &mut F) -> Result<f64, CalcError>
+where
+    Tokenizer: TokenizerTraits,
+    F: Fold<Value = f64, Error = ZeroDivisor>,
+{
+    let mut last = None;
+    let mut braces = BraceStack::new();
+    let mut last_group = None;
+    let mut stacks = FoldStacks::new();
+    let mut zero_divisor = None;
+
+    while tokenizer.has_token() {
+        let Next::Take(idx, token) = next_checked(
+            &mut tokenizer,
+            last.as_ref(),
+            &mut braces,
+            &mut last_group,
+            false,
+        )?
+        else {
+            unreachable!("parsing stopped early without being asked to");
+        };
+
+        // A parse stores positions as `u32` and fails on larger ones.
+        if u32::try_from(idx).is_err() {
+            return Err(MathExpressionParserError::PositionOverflow {
+                idx: tokenizer.position_of(idx),
+            }
+            .into());
+        }
+
+        // A failed fold is not continued, but the input is still checked.
+        if zero_divisor.is_none() {
+            zero_divisor = stacks.push(folder, idx, &token).err();
+        }
+        last = Some(token);
+    }
+
+    check_end(&tokenizer, last.as_ref(), &braces, false)?;
+
+    let value = match zero_divisor {
+        Some(zero_divisor) => Err(zero_divisor),
+        None => stacks.finish(folder),
+    };
+    value.map_err(|ZeroDivisor(idx)| {
+        MathExpressionEvaluatorError::DivisionByZero {
+            idx: tokenizer.position_of(idx),
+        }
+        .into()
+    })
+}
+
+// The right operand of the '/' or '%' at this byte offset is zero.
+struct ZeroDivisor(usize);
+
+impl ZeroDivisor {
+    // Moved back to a char boundary, so a source that does not match the
+    // expression gives a wrong position rather than a panic.
+    fn in_source(self, source: &str) -> MathExpressionEvaluatorError {
+        let idx = source.floor_char_boundary(self.0);
+        MathExpressionEvaluatorError::DivisionByZero {
+            idx: Position::in_source(source, idx),
+        }
+    }
+}
+
+struct Arithmetic;
+
+impl Fold for Arithmetic {
+    type Value = f64;
+    type Error = ZeroDivisor;
+
+    fn number(&mut self, number: f64) -> f64 {
+        number
+    }
+
+    fn unary(&mut self, op: char, operand: f64) -> f64 {
+        match op {
+            '-' => -operand,
+            '+' => operand,
+            op => unreachable!("parser accepted unary operator '{op}'"),
+        }
+    }
+
+    fn binary(&mut self, op: char, idx: usize, lhs: f64, rhs: f64) -> Result<f64, ZeroDivisor> {
+        match op {
+            '+' => Ok(lhs + rhs),
+            '-' => Ok(lhs - rhs),
+            '*' => Ok(lhs * rhs),
+            '/' | '%' if rhs == 0.0 => Err(ZeroDivisor(idx)),
+            '/' => Ok(lhs / rhs),
+            '%' => Ok(lhs % rhs),
+            '^' => Ok(lhs.powf(rhs)),
+            op => unreachable!("parser accepted operator '{op}'"),
+        }
+    }
+
+    fn group(&mut self, inner: f64) -> f64 {
+        inner
+    }
+}
+
+// `Arithmetic`, logging the binary operations.
+struct Audited<'a> {
+    log: &'a mut Vec<AuditEntry>,
+}
+
+impl Fold for Audited<'_> {
+    type Value = f64;
+    type Error = ZeroDivisor;
+
+    fn number(&mut self, number: f64) -> f64 {
+        Arithmetic.number(number)
+    }
+
+    fn unary(&mut self, op: char, operand: f64) -> f64 {
+        Arithmetic.unary(op, operand)
+    }
+
+    fn binary(&mut self, op: char, idx: usize, lhs: f64, rhs: f64) -> Result<f64, ZeroDivisor> {
+        let result = Arithmetic.binary(op, idx, lhs, rhs)?;
+        self.log.push(AuditEntry {
+            op,
+            lhs_bits: lhs.to_bits(),
+            rhs_bits: rhs.to_bits(),
+            result_bits: result.to_bits(),
+        });
+        Ok(result)
+    }
+
+    fn group(&mut self, inner: f64) -> f64 {
+        Arithmetic.group(inner)
+    }
+}
+
+// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math_expression_parser::MathExpressionParser;
+    use crate::math_expression_parser::tests::expression;
+    use crate::math_expression_tokenizer::MathExpressionTokenizer;
+    use proptest::prelude::*;
+
+    fn at(idx: usize) -> Position {
+        Position::new(idx, idx)
+    }
+
+    fn eval(source: &str) -> Result<f64, MathExpressionEvaluatorError> {
+        evaluate(&source.parse().unwrap(), source)
+    }
+
+    fn tokenizer(source: &str) -> MathExpressionTokenizer {
+        MathExpressionTokenizer::new(source.to_owned()).unwrap()
+    }
+
+    // Parsing and evaluating one after the other.
+    fn two_phase(source: &str) -> Result<f64, CalcError> {
+        let parsed = MathExpressionParser::new().parse(tokenizer(source))?;
+        Ok(evaluate(&parsed, source)?)
+    }
+
+    fn assert_same(source: &str) {
+        match (parse_and_evaluate(tokenizer(source)), two_phase(source)) {
+            (Ok(value), Ok(expected)) => assert!(
+                value.to_bits() == expected.to_bits() || (value.is_nan() && expected.is_nan()),
+                "{source:?}: {value} != {expected}"
+            ),
+            (result, expected) => assert_eq!(result, expected, "{source:?}"),
+        }
+    }
+
+    #[test]
+    fn test_precedence() {
+        assert_eq!(eval("1 + 2 * 3"), Ok(7.0));
+        assert_eq!(eval("2 * 3 + 1"), Ok(7.0));
+        assert_eq!(eval("1 + 6 / 3 - 2"), Ok(1.0));
+        assert_eq!(eval("(1 + 2) * 3"), Ok(9.0));
+        assert_eq!(eval("2.5"), Ok(2.5));
+    }
+
+    #[test]
+    fn test_left_associativity() {
+        assert_eq!(eval("10 - 4 - 3"), Ok(3.0));
+        assert_eq!(eval("10 - (4 - 3)"), Ok(9.0));
+        assert_eq!(eval("64 / 4 / 2"), Ok(8.0));
+        assert_eq!(eval("64 / (4 / 2)"), Ok(32.0));
+        assert_eq!(eval("8 / 2 * 4"), Ok(16.0));
+        assert_eq!(eval("1 - 2 + 3"), Ok(2.0));
+    }
+
+    #[test]
+    fn test_unary_operators() {
+        assert_eq!(eval("-2 * 3"), Ok(-6.0));
+        assert_eq!(eval("2 * -3"), Ok(-6.0));
+        assert_eq!(eval("- -4"), Ok(4.0));
+        assert_eq!(eval("+5 - -(2 + 1)"), Ok(8.0));
+        assert_eq!(eval("1 - -1 * 2"), Ok(3.0));
+    }
+
+    #[test]
+    fn test_remainder() {
+        assert_eq!(eval("17 % 5"), Ok(2.0));
+        assert_eq!(eval("5.5 % 2"), Ok(1.5));
+        assert_eq!(eval("1 + 7 % 4 * 2"), Ok(7.0));
+        assert_eq!(eval("20 % 6 % 4"), Ok(2.0));
+        assert_eq!(eval("2 ^ 5 % 10"), Ok(2.0));
+    }
+
+    #[test]
+    fn test_remainder_has_the_sign_of_the_dividend() {
+        assert_eq!(eval("-7 % 3"), Ok(-1.0));
+        assert_eq!(eval("7 % -3"), Ok(1.0));
+        assert_eq!(eval("-7 % -3"), Ok(-1.0));
+        assert_eq!(eval("(0 - 6) % 3"), Ok(-0.0));
+    }
+
+    #[test]
+    fn test_exponents() {
+        assert_eq!(eval("2 ^ 10"), Ok(1024.0));
+        assert_eq!(eval("2 ^ 3 ^ 2"), Ok(512.0));
+        assert_eq!(eval("(2 ^ 3) ^ 2"), Ok(64.0));
+        assert_eq!(eval("(1 + 2) ^ 2"), Ok(9.0));
+        assert_eq!(eval("2 ^ (1 + 2) * 3"), Ok(24.0));
+        assert_eq!(eval("3 * 2 ^ 2"), Ok(12.0));
+        assert_eq!(eval("4 ^ 0.5"), Ok(2.0));
+        assert_eq!(eval("2 ^ -1"), Ok(0.5));
+        assert_eq!(eval("0 ^ -1"), Ok(f64::INFINITY));
+        assert!(eval("(-8) ^ 0.5").unwrap().is_nan());
+    }
+
+    #[test]
+    fn test_exponents_bind_tighter_than_unary_operators() {
+        assert_eq!(eval("-2 ^ 2"), Ok(-4.0));
+        assert_eq!(eval("(-2) ^ 2"), Ok(4.0));
+        assert_eq!(eval("-2 ^ 2 * 3"), Ok(-12.0));
+        assert_eq!(eval("2 ^ -2 ^ 2"), Ok(0.0625));
+    }
+
+    #[test]
+    fn test_nested_groups() {
+        assert_eq!(eval("((1+2)*3)/4"), Ok(2.25));
+        assert_eq!(eval("(((((7)))))"), Ok(7.0));
+        assert_eq!(eval("2 * (3 + (4 - (5 * (6 / 3))))"), Ok(-6.0));
+
+        let depth = 100_000;
+        let deep = format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+        assert_eq!(eval(&deep), Ok(1.0));
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        assert_eq!(
+            eval("1 / 0"),
+            Err(MathExpressionEvaluatorError::DivisionByZero { idx: at(2) })
+        );
+        assert_eq!(
+            eval("4 + 1 / (2 - 2)"),
+            Err(MathExpressionEvaluatorError::DivisionByZero { idx: at(6) })
+        );
+        assert_eq!(
+            eval("1 / -0"),
+            Err(MathExpressionEvaluatorError::DivisionByZero { idx: at(2) })
+        );
+        assert_eq!(eval("0 / 1"), Ok(0.0));
+        assert_eq!(
+            eval("5 % 0"),
+            Err(MathExpressionEvaluatorError::DivisionByZero { idx: at(2) })
+        );
+        assert_eq!(
+            eval("1 + 5 % (1 - 1)"),
+            Err(MathExpressionEvaluatorError::DivisionByZero { idx: at(6) })
+        );
+    }
+
+    #[test]
+    fn test_division_by_zero_position_counts_chars() {
+        let source = "\u{3000}1\u{3000}/ 0";
+        let err = eval(source).unwrap_err();
+
+        assert_eq!(
+            err,
+            MathExpressionEvaluatorError::DivisionByZero {
+                idx: Position::new(7, 3)
+            }
+        );
+        assert_eq!(err.to_string(), "Division by zero at position 4");
+        assert_eq!(
+            err.to_diagnostic(source).render(source),
+            "error[E0501]: Division by zero at position 4\n\
+             1 | \u{3000}1\u{3000}/ 0\n  \
+               |    ^ the right operand is zero\n  \
+               = help: make sure the divisor is not zero\n"
+        );
+    }
+
+    // Dollar amounts in whole cents, rounded to the nearest cent.
+    #[derive(Debug, PartialEq)]
+    struct Cents(i64);
+
+    impl TryFrom<f64> for Cents {
+        type Error = ();
+
+        fn try_from(dollars: f64) -> Result<Self, ()> {
+            let cents = (dollars * 100.0).round();
+            // `i64::MAX as f64` rounds up to 2^63, which is already too large.
+            if cents.is_finite() && cents.abs() < i64::MAX as f64 {
+                Ok(Cents(cents as i64))
+            } else {
+                Err(())
+            }
+        }
+    }
+
+    fn cents(source: &str) -> Result<Cents, MathExpressionEvaluatorError> {
+        evaluate_into(&source.parse().unwrap(), source)
+    }
+
+    #[test]
+    fn test_evaluate_into() {
+        assert_eq!(cents("12.5 * 2 + 0.25"), Ok(Cents(2525)));
+        assert_eq!(cents("-(3 - 1.01)"), Ok(Cents(-199)));
+
+        // Rounded by the conversion.
+        assert_eq!(cents("0.1 + 0.2"), Ok(Cents(30)));
+        assert_eq!(cents("10 / 3"), Ok(Cents(333)));
+        assert_eq!(cents("0.005 * 3"), Ok(Cents(2)));
+
+        assert_eq!(
+            cents("1e17 * 2"),
+            Err(MathExpressionEvaluatorError::ResultConversion { value: 2e17 })
+        );
+        assert_eq!(
+            cents("2 ^ 1024"),
+            Err(MathExpressionEvaluatorError::ResultConversion {
+                value: f64::INFINITY
+            })
+        );
+        assert_eq!(
+            cents("1 / 0"),
+            Err(MathExpressionEvaluatorError::DivisionByZero { idx: at(2) })
+        );
+
+        let err = cents("1e17 * 2").unwrap_err();
+        assert_eq!(err.code(), ErrorCode::EvaluatorResultConversion);
+        assert_eq!(
+            err.to_string(),
+            "Result 200000000000000000 cannot be converted to the requested type"
+        );
+    }
+
+    #[test]
+    fn test_evaluator_after_a_failed_evaluation() {
+        let mut evaluator = Evaluator::new();
+        // Fails with '(' and a '+' waiting and 2 and 3 on the stacks.
+        let failing = "2 + (3 * (4 / 0))";
+        assert_eq!(
+            evaluator.evaluate(&failing.parse().unwrap(), failing),
+            Err(MathExpressionEvaluatorError::DivisionByZero { idx: at(12) })
+        );
+
+        for (source, expected) in [("1 - 5", -4.0), ("7", 7.0), ("(2 ^ 3) * 2", 16.0)] {
+            let parsed = source.parse().unwrap();
+            assert_eq!(evaluator.evaluate(&parsed, source), Ok(expected));
+            assert_eq!(evaluator.evaluate(&parsed, source), eval(source));
+        }
+    }
+
+    #[test]
+    fn test_parse_and_evaluate() {
+        assert_eq!(parse_and_evaluate(tokenizer("((1+2)*3)/4")), Ok(2.25));
+        assert_eq!(parse_and_evaluate(tokenizer("2 ^ 3 ^ 2 - -1")), Ok(513.0));
+        assert_eq!(
+            parse_and_evaluate(tokenizer("4 + 1 / (2 - 2)")),
+            Err(CalcError::Evaluator(
+                MathExpressionEvaluatorError::DivisionByZero { idx: at(6) }
+            ))
+        );
+        assert_eq!(
+            parse_and_evaluate(tokenizer("1 +")).map_err(|err| err.code()),
+            Err(ErrorCode::ParserInvalidExpression)
+        );
+
+        for source in [
+            "1 + 2 * 3",
+            "1 $ 2",
+            "(1",
+            "1)",
+            "()",
+            "\u{3000}1\u{3000}% 0",
+        ] {
+            assert_same(source);
+        }
+    }
+
+    #[test]
+    fn test_parse_errors_come_before_division_by_zero() {
+        for source in ["1 / 0 + (", "1 / 0 2", "5 % 0 * )", "1 / 0 $"] {
+            assert!(matches!(two_phase(source), Err(CalcError::Parser(_))));
+            assert_same(source);
+        }
+    }
+
+    #[test]
+    fn test_parse_and_evaluate_long_chain() {
+        let length = 100_000;
+        let chain = format!("1{}", "+1".repeat(length));
+        assert_eq!(
+            parse_and_evaluate(tokenizer(&chain)),
+            Ok(length as f64 + 1.0)
+        );
+
+        let nested = format!("{}1{}", "(".repeat(length), ")".repeat(length));
+        assert_eq!(parse_and_evaluate(tokenizer(&nested)), Ok(1.0));
+    }
+
+    fn entry(op: char, lhs: f64, rhs: f64, result: f64) -> AuditEntry {
+        AuditEntry {
+            op,
+            lhs_bits: lhs.to_bits(),
+            rhs_bits: rhs.to_bits(),
+            result_bits: result.to_bits(),
+        }
+    }
+
+    fn audit(source: &str) -> (Result<f64, MathExpressionEvaluatorError>, Vec<AuditEntry>) {
+        let mut log = Vec::new();
+        let value = evaluate_audited(&source.parse().unwrap(), source, &mut log);
+        (value, log)
+    }
+
+    // Evaluating after parsing and while parsing carries out the same
+    // operations on the same bits.
+    fn assert_same_audit(source: &str) {
+        let (value, log) = audit(source);
+        let mut streamed_log = Vec::new();
+        let streamed = parse_and_evaluate_audited(tokenizer(source), &mut streamed_log);
+
+        assert_eq!(streamed_log, log, "{source:?}");
+        match (streamed, value) {
+            (Ok(streamed), Ok(value)) => {
+                assert_eq!(streamed.to_bits(), value.to_bits(), "{source:?}");
+            }
+            (streamed, value) => assert_eq!(streamed, value.map_err(CalcError::from), "{source:?}"),
+        }
+    }
+
+    #[test]
+    fn test_audit_log_follows_evaluation_order() {
+        assert_eq!(
+            audit("1 + 2 * 3"),
+            (
+                Ok(7.0),
+                vec![entry('*', 2.0, 3.0, 6.0), entry('+', 1.0, 6.0, 7.0)]
+            )
+        );
+        // Left to right, so the rounding of `0.1 + 0.2` comes first.
+        assert_eq!(
+            audit("0.1 + 0.2 + 0.3"),
+            (
+                Ok(0.1 + 0.2 + 0.3),
+                vec![
+                    entry('+', 0.1, 0.2, 0.1 + 0.2),
+                    entry('+', 0.1 + 0.2, 0.3, 0.1 + 0.2 + 0.3)
+                ]
+            )
+        );
+        // Right to left for '^'; unary operators are not logged.
+        assert_eq!(
+            audit("-2 ^ 3 ^ 2"),
+            (
+                Ok(-512.0),
+                vec![entry('^', 3.0, 2.0, 9.0), entry('^', 2.0, 9.0, 512.0)]
+            )
+        );
+        assert_eq!(audit("(((4)))"), (Ok(4.0), vec![]));
+    }
+
+    #[test]
+    fn test_audit_log_stops_at_division_by_zero() {
+        assert_eq!(
+            audit("1 + 2 + 3 / (1 - 1)"),
+            (
+                Err(MathExpressionEvaluatorError::DivisionByZero { idx: at(10) }),
+                vec![entry('+', 1.0, 2.0, 3.0), entry('-', 1.0, 1.0, 0.0)]
+            )
+        );
+    }
+
+    #[test]
+    fn test_audit_logs_match_on_corpus() {
+        use crate::bench_corpus::Workload;
+
+        for source in [
+            "0.1 + 0.2 - 0.3",
+            "1e16 + 1 - 1e16",
+            "(1 / 3) * 3 % 0.7",
+            "2 ^ 0.5 ^ 2 * -1.5",
+            "(-8) ^ (1 / 3)",
+            "1e308 * 10 - 1e308 * 10",
+            "1 + 2 / (3 - 3) * 4",
+        ] {
+            assert_same_audit(source);
+        }
+
+        for workload in [
+            Workload::FlatChain,
+            Workload::DeepNesting,
+            Workload::NumberHeavy,
+            Workload::WhitespaceHeavy,
+        ] {
+            for seed in 0..4 {
+                assert_same_audit(&workload.generate(seed, 500));
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_audit_logs_match(source in expression()) {
+            assert_same_audit(&source);
+        }
+
+        #[test]
+        fn test_remainder_matches_f64(lhs in -1e6f64..1e6, rhs in -1e6f64..1e6) {
+            prop_assume!(rhs != 0.0);
+            let value = eval(&format!("{lhs} % {rhs}")).unwrap();
+            prop_assert_eq!(value.to_bits(), (lhs % rhs).to_bits());
+        }
+
+        #[test]
+        fn test_parse_and_evaluate_matches_two_phase(source in expression()) {
+            assert_same(&source);
+        }
+
+        #[test]
+        fn test_evaluator_matches_evaluate(sources in proptest::collection::vec(expression(), 1..8)) {
+            let mut evaluator = Evaluator::new();
+            for source in sources {
+                let parsed = source.parse().unwrap();
+                let (value, expected) = (evaluator.evaluate(&parsed, &source), eval(&source));
+                match (value, expected) {
+                    (Ok(value), Ok(expected)) => prop_assert!(
+                        value.to_bits() == expected.to_bits() || (value.is_nan() && expected.is_nan())
+                    ),
+                    (value, expected) => prop_assert_eq!(value, expected),
+                }
+            }
+        }
+
+        #[test]
+        fn test_parse_and_evaluate_matches_on_any_input(
+            source in "[0-9+*/%^()$ .-]{0,20}",
+        ) {
+            prop_assume!(!source.trim().is_empty());
+            assert_same(&source);
+        }
+
+        #[test]
+        fn test_groups_do_not_change_the_value(source in expression()) {
+            let expected = eval(&source);
+            let grouped = eval(&format!("({source})"));
+            match (expected, grouped) {
+                (Ok(expected), Ok(grouped)) => {
+                    prop_assert_eq!(expected.to_bits(), grouped.to_bits());
+                }
+                (expected, grouped) => {
+                    prop_assert_eq!(expected.map_err(|err| err.code()), grouped.map_err(|err| err.code()));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use super::ast::{ExprNode, TreeNode};
+use super::precedence::{Fold, FoldStacks, fold_with};
+use super::{MathExpression, MathExpressionParser, MathExpressionParserError};
+use crate::math_expression_tokenizer::TokenizerTraits;
+
+use std::convert::Infallible;
+use std::fmt;
+
+// Trees side by side in one buffer, for building many of them without an
+// allocation per node. Nodes refer to their children by index and are only
+// freed all at once, by `clear` or by dropping the arena, so trees of any
+// depth are dropped without recursion. Clearing keeps the buffers: an arena
+// reused for expressions of similar size stops allocating.
+pub struct ExprArena {
+    nodes: Vec<ExprNode<ExprId>>,
+    stacks: FoldStacks<ExprId>,
+}
+
+// A tree in an `ExprArena`, valid in the arena that built it until it is
+// cleared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ExprId(usize);
+
+// A node of a tree in an `ExprArena`, borrowing the arena.
+#[derive(Clone, Copy)]
+pub struct ArenaExpr<'a> {
+    arena: &'a ExprArena,
+    id: ExprId,
+}
+
+impl Default for ExprArena {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExprArena {
+    pub fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            stacks: FoldStacks::new(),
+        }
+    }
+
+    // The number of nodes of all trees in the arena.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    // Removes every tree, keeping the allocations.
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+    }
+
+    // The tree `id` stands for. Panics if `id` was not given by this arena
+    // since it was last cleared.
+    pub fn get(&self, id: ExprId) -> ArenaExpr<'_> {
+        assert!(id.0 < self.nodes.len(), "{id:?} is not in the arena");
+        ArenaExpr { arena: self, id }
+    }
+}
+
+impl ArenaExpr<'_> {
+    pub fn id(self) -> ExprId {
+        self.id
+    }
+}
+
+// Formatted as the nodes of the tree, recursing like `Expr`'s `Debug`.
+impl fmt::Debug for ArenaExpr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.node().fmt(f)
+    }
+}
+
+impl<'a> TreeNode for ArenaExpr<'a> {
+    fn node(self) -> ExprNode<ArenaExpr<'a>> {
+        let at = |id| ArenaExpr {
+            arena: self.arena,
+            id,
+        };
+        match self.arena.nodes[self.id.0] {
+            ExprNode::Number(number) => ExprNode::Number(number),
+            ExprNode::UnaryOp { op, operand } => ExprNode::UnaryOp {
+                op,
+                operand: at(operand),
+            },
+            ExprNode::BinaryOp { op, lhs, rhs } => ExprNode::BinaryOp {
+                op,
+                lhs: at(lhs),
+                rhs: at(rhs),
+            },
+            ExprNode::Group(inner) => ExprNode::Group(at(inner)),
+        }
+    }
+}
+
+struct ArenaBuilder<'n> {
+    nodes: &'n mut Vec<ExprNode<ExprId>>,
+}
+
+impl ArenaBuilder<'_> {
+    fn add(&mut self, node: ExprNode<ExprId>) -> ExprId {
+        self.nodes.push(node);
+        ExprId(self.nodes.len() - 1)
+    }
+}
+
+impl Fold for ArenaBuilder<'_> {
+    type Value = ExprId;
+    type Error = Infallible;
+
+    fn number(&mut self, number: f64) -> ExprId {
+        self.add(ExprNode::Number(number))
+    }
+
+    fn unary(&mut self, op: char, operand: ExprId) -> ExprId {
+        self.add(ExprNode::UnaryOp { op, operand })
+    }
+
+    fn binary(
+        &mut self,
+        op: char,
+        _idx: usize,
+        lhs: ExprId,
+        rhs: ExprId,
+    ) -> Result<ExprId, Infallible> {
+        Ok(self.add(ExprNode::BinaryOp { op, lhs, rhs }))
+    }
+
+    fn group(&mut self, inner: ExprId) -> ExprId {
+        self.add(ExprNode::Group(inner))
+    }
+}
+
+impl MathExpression {
+    // Same as `to_ast`, building the tree in `arena`.
+    pub fn to_ast_in(&self, arena: &mut ExprArena) -> ExprId {
+        let ExprArena { nodes, stacks } = arena;
+        let Ok(root) = fold_with(self, &mut ArenaBuilder { nodes }, stacks);
+        root
+    }
+}
+
+impl MathExpressionParser {
+    // Same as `parse_ast`, building the tree in `arena`. Parsing still
+    // collects the tokens first; only the tree is in the arena.
+    pub fn parse_ast_in<Tokenizer: TokenizerTraits>(
+        &self,
+        tokenizer: Tokenizer,
+        arena: &mut ExprArena,
+    ) -> Result<ExprId, MathExpressionParserError> {
+        self.parse(tokenizer).map(|parsed| parsed.to_ast_in(arena))
+    }
+}
+
+// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math_expression_parser::tests::expression;
+    use crate::math_expression_tokenizer::MathExpressionTokenizer;
+    use crate::{evaluate, evaluate_tree};
+    use proptest::prelude::*;
+
+    fn parse_in(source: &str, arena: &mut ExprArena) -> ExprId {
+        MathExpressionParser::new()
+            .parse_ast_in(
+                MathExpressionTokenizer::new(source.to_owned()).unwrap(),
+                arena,
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn test_trees_print_like_boxed_ones() {
+        let mut arena = ExprArena::new();
+        for source in ["1+2*3", "-2 ^ 2 * -(1 - 3)", "((4))", "8-4-2", "2^3^2"] {
+            let parsed: MathExpression = source.parse().unwrap();
+            let id = parsed.to_ast_in(&mut arena);
+            assert_eq!(
+                arena.get(id).to_pretty_tree(),
+                parsed.to_ast().to_pretty_tree(),
+                "{source:?}"
+            );
+            assert_eq!(
+                arena.get(id).to_pretty_tree_with(true),
+                parsed.to_ast().to_pretty_tree_with(true)
+            );
+        }
+    }
+
+    #[test]
+    fn test_arena_holds_several_trees() {
+        let mut arena = ExprArena::new();
+        let sum = parse_in("1 + 2", &mut arena);
+        let product = parse_in("(3) * 4", &mut arena);
+        assert_eq!(arena.len(), 7);
+
+        assert_eq!(evaluate_tree(arena.get(sum)), Some(3.0));
+        assert_eq!(evaluate_tree(arena.get(product)), Some(12.0));
+        assert_eq!(arena.get(product).id(), product);
+        assert!(matches!(
+            arena.get(product).node(),
+            ExprNode::BinaryOp { op: '*', .. }
+        ));
+
+        assert_eq!(
+            format!("{:?}", arena.get(sum)),
+            "BinaryOp { op: '+', lhs: Number(1.0), rhs: Number(2.0) }"
+        );
+
+        arena.clear();
+        assert!(arena.is_empty());
+        let id = parse_in("5", &mut arena);
+        assert_eq!(evaluate_tree(arena.get(id)), Some(5.0));
+    }
+
+    #[test]
+    #[should_panic(expected = "is not in the arena")]
+    fn test_ids_from_before_a_clear_panic() {
+        let mut arena = ExprArena::new();
+        let id = parse_in("1 + 2", &mut arena);
+        arena.clear();
+        arena.get(id);
+    }
+
+    #[test]
+    fn test_errors_match_the_boxed_parser() {
+        let mut arena = ExprArena::new();
+        for source in ["1+", "(1", "1)", "()", "1 2", "*1", "2*(3+)"] {
+            let boxed = MathExpressionParser::new()
+                .parse_ast(MathExpressionTokenizer::new(source.to_owned()).unwrap());
+            let in_arena = MathExpressionParser::new().parse_ast_in(
+                MathExpressionTokenizer::new(source.to_owned()).unwrap(),
+                &mut arena,
+            );
+            assert_eq!(in_arena.err(), boxed.err(), "{source:?}");
+        }
+        assert!(arena.is_empty());
+    }
+
+    #[test]
+    fn test_deep_trees_evaluate_without_overflow() {
+        let depth = 100_000;
+        let sources = [
+            format!("{}1{}", "(".repeat(depth), ")".repeat(depth)),
+            format!("{}1", "-".repeat(depth)),
+            format!("1{}", "+1".repeat(depth)),
+        ];
+        let mut arena = ExprArena::new();
+        let ids: Vec<ExprId> = sources
+            .iter()
+            .map(|source| parse_in(source, &mut arena))
+            .collect();
+
+        // Far too small to recurse through any of them.
+        std::thread::Builder::new()
+            .stack_size(64 * 1024)
+            .spawn(move || {
+                let values: Vec<_> = ids.iter().map(|id| evaluate_tree(arena.get(*id))).collect();
+                assert_eq!(values, [Some(1.0), Some(1.0), Some(depth as f64 + 1.0)]);
+                drop(arena);
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    proptest! {
+        #[test]
+        fn test_both_trees_agree_with_the_evaluator(source in expression()) {
+            let parsed: MathExpression = source.parse().unwrap();
+            let mut arena = ExprArena::new();
+            let id = parsed.to_ast_in(&mut arena);
+            let boxed = parsed.to_ast();
+
+            let expected = evaluate(&parsed, &source).ok();
+            for value in [evaluate_tree(arena.get(id)), evaluate_tree(&boxed)] {
+                prop_assert!(
+                    value.map(f64::to_bits) == expected.map(f64::to_bits)
+                        || value.is_some_and(f64::is_nan) && expected.is_some_and(f64::is_nan),
+                    "{:?} != {:?}", value, expected
+                );
+            }
+        }
+    }
+}
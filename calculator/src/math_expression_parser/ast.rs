@@ -0,0 +1,517 @@
+use super::precedence::{Fold, fold};
+use super::{MathExpression, MathExpressionParser, MathExpressionParserError};
+use crate::math_expression_tokenizer::{TokenizerTraits, write_number};
+
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::mem;
+
+// An expression as a tree, with precedence and associativity resolved: the
+// operands of every operator are the subtrees it applies to. Parentheses are
+// kept as `Group` nodes, so the tree still says what was written.
+//
+// Trees as deep as the input is long are built and dropped without
+// recursion. Cloning, comparing and formatting them with `Debug` do recurse,
+// which is fine for anything a person would write.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Expr {
+    Number(f64),
+    // '+' or '-' in front of an operand.
+    UnaryOp {
+        op: char,
+        operand: Box<Expr>,
+    },
+    // One of '+', '-', '*', '/', '%' and '^'.
+    BinaryOp {
+        op: char,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+    Group(Box<Expr>),
+}
+
+impl Expr {
+    // One node per line, children below their parent:
+    //
+    //   +
+    //   ├── 1
+    //   └── *
+    //       ├── 2
+    //       └── 3
+    //
+    // Binary operators are shown as themselves, unary ones as `unary -`,
+    // groups as `()` and numbers as printed in expressions. The output ends
+    // with a newline and only depends on the tree. Nodes carry no positions,
+    // so there are no spans to show.
+    pub fn to_pretty_tree(&self) -> String {
+        TreeNode::to_pretty_tree(self)
+    }
+
+    // Same as `to_pretty_tree`, or with `compact` each level indented by two
+    // spaces instead, without box-drawing characters, for logs.
+    pub fn to_pretty_tree_with(&self, compact: bool) -> String {
+        TreeNode::to_pretty_tree_with(self, compact)
+    }
+
+    // Moves the children of `self` to `stack`, leaving leaves in their place.
+    fn take_children(&mut self, stack: &mut Vec<Expr>) {
+        let leaf = || Expr::Number(0.0);
+        match self {
+            Expr::Number(_) => {}
+            Expr::UnaryOp { operand, .. } | Expr::Group(operand) => {
+                stack.push(mem::replace(&mut **operand, leaf()));
+            }
+            Expr::BinaryOp { lhs, rhs, .. } => {
+                stack.push(mem::replace(&mut **lhs, leaf()));
+                stack.push(mem::replace(&mut **rhs, leaf()));
+            }
+        }
+    }
+}
+
+// One node of a tree, with its children as `Child`: borrowed `Expr`s for
+// boxed trees and `ArenaExpr`s for trees in an `ExprArena`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum ExprNode<Child> {
+    Number(f64),
+    UnaryOp { op: char, operand: Child },
+    BinaryOp { op: char, lhs: Child, rhs: Child },
+    Group(Child),
+}
+
+// A node of a tree however it is stored, so printing and evaluation work the
+// same on boxed trees and on trees in an arena.
+pub trait TreeNode: Copy {
+    fn node(self) -> ExprNode<Self>;
+
+    // See `Expr::to_pretty_tree`.
+    fn to_pretty_tree(self) -> String {
+        self.to_pretty_tree_with(false)
+    }
+
+    // See `Expr::to_pretty_tree_with`.
+    fn to_pretty_tree_with(self, compact: bool) -> String {
+        let mut out = String::new();
+        // Nodes still to print, with what goes before their own line and
+        // before the lines of their descendants.
+        let mut stack = vec![(self, String::new(), String::new())];
+
+        while let Some((node, prefix, indent)) = stack.pop() {
+            out.push_str(&prefix);
+            let (one, two);
+            let children: &[Self] = match node.node() {
+                ExprNode::Number(number) => {
+                    let _ = write_number(&mut out, number);
+                    &[]
+                }
+                ExprNode::UnaryOp { op, operand } => {
+                    let _ = write!(out, "unary {op}");
+                    one = [operand];
+                    &one
+                }
+                ExprNode::BinaryOp { op, lhs, rhs } => {
+                    out.push(op);
+                    two = [lhs, rhs];
+                    &two
+                }
+                ExprNode::Group(inner) => {
+                    out.push_str("()");
+                    one = [inner];
+                    &one
+                }
+            };
+            out.push('\n');
+
+            for (i, child) in children.iter().enumerate().rev() {
+                let (branch, rest) = match i + 1 == children.len() {
+                    _ if compact => ("  ", "  "),
+                    true => ("└── ", "    "),
+                    false => ("├── ", "│   "),
+                };
+                stack.push((
+                    *child,
+                    format!("{indent}{branch}"),
+                    format!("{indent}{rest}"),
+                ));
+            }
+        }
+
+        out
+    }
+}
+
+impl<'e> TreeNode for &'e Expr {
+    fn node(self) -> ExprNode<&'e Expr> {
+        match self {
+            Expr::Number(number) => ExprNode::Number(*number),
+            Expr::UnaryOp { op, operand } => ExprNode::UnaryOp {
+                op: *op,
+                operand: &**operand,
+            },
+            Expr::BinaryOp { op, lhs, rhs } => ExprNode::BinaryOp {
+                op: *op,
+                lhs: &**lhs,
+                rhs: &**rhs,
+            },
+            Expr::Group(inner) => ExprNode::Group(&**inner),
+        }
+    }
+}
+
+// Folds the tree under `root` as `fold` folds an expression, children before
+// their parent and left to right, without recursion. Trees carry no
+// positions, so binary operators are folded at byte 0.
+pub(crate) fn fold_tree<N: TreeNode, F: Fold>(
+    root: N,
+    folder: &mut F,
+) -> Result<F::Value, F::Error> {
+    let mut nodes = vec![(root, false)];
+    let mut values = Vec::new();
+    let pop = |values: &mut Vec<F::Value>| match values.pop() {
+        Some(value) => value,
+        None => unreachable!("a node was folded before its children"),
+    };
+
+    // Every node is seen twice: first to queue its children, then, once they
+    // are folded, to fold it.
+    while let Some((node, children_folded)) = nodes.pop() {
+        let node_view = node.node();
+        if !children_folded {
+            nodes.push((node, true));
+            match node_view {
+                ExprNode::Number(_) => {}
+                ExprNode::UnaryOp { operand: child, .. } | ExprNode::Group(child) => {
+                    nodes.push((child, false));
+                }
+                ExprNode::BinaryOp { lhs, rhs, .. } => {
+                    nodes.push((rhs, false));
+                    nodes.push((lhs, false));
+                }
+            }
+            continue;
+        }
+
+        let value = match node_view {
+            ExprNode::Number(number) => folder.number(number),
+            ExprNode::UnaryOp { op, .. } => {
+                let operand = pop(&mut values);
+                folder.unary(op, operand)
+            }
+            ExprNode::BinaryOp { op, .. } => {
+                let rhs = pop(&mut values);
+                let lhs = pop(&mut values);
+                folder.binary(op, 0, lhs, rhs)?
+            }
+            ExprNode::Group(_) => {
+                let inner = pop(&mut values);
+                folder.group(inner)
+            }
+        };
+        values.push(value);
+    }
+
+    Ok(pop(&mut values))
+}
+
+// The derived drop would recurse once per level and overflow the stack on
+// deeply nested input, so the nodes are detached onto a heap stack first.
+impl Drop for Expr {
+    fn drop(&mut self) {
+        let mut stack = Vec::new();
+        self.take_children(&mut stack);
+        while let Some(mut node) = stack.pop() {
+            node.take_children(&mut stack);
+        }
+    }
+}
+
+struct TreeBuilder;
+
+impl Fold for TreeBuilder {
+    type Value = Expr;
+    type Error = Infallible;
+
+    fn number(&mut self, number: f64) -> Expr {
+        Expr::Number(number)
+    }
+
+    fn unary(&mut self, op: char, operand: Expr) -> Expr {
+        Expr::UnaryOp {
+            op,
+            operand: Box::new(operand),
+        }
+    }
+
+    fn binary(&mut self, op: char, _idx: usize, lhs: Expr, rhs: Expr) -> Result<Expr, Infallible> {
+        Ok(Expr::BinaryOp {
+            op,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+        })
+    }
+
+    fn group(&mut self, inner: Expr) -> Expr {
+        Expr::Group(Box::new(inner))
+    }
+}
+
+impl MathExpression {
+    // The tree of an already parsed expression.
+    pub fn to_ast(&self) -> Expr {
+        let Ok(tree) = fold(self, &mut TreeBuilder);
+        tree
+    }
+}
+
+impl MathExpressionParser {
+    // Same as `parse`, giving the tree instead of the token list. The input is
+    // validated by the same code, so it fails with the same errors at the same
+    // positions, and honours the same options.
+    pub fn parse_ast<Tokenizer: TokenizerTraits>(
+        &self,
+        tokenizer: Tokenizer,
+    ) -> Result<Expr, MathExpressionParserError> {
+        self.parse(tokenizer).map(|parsed| parsed.to_ast())
+    }
+}
+
+// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math_expression_parser::tests::expression;
+    use crate::math_expression_tokenizer::MathExpressionTokenizer;
+    use proptest::prelude::*;
+
+    fn ast(source: &str) -> Result<Expr, MathExpressionParserError> {
+        MathExpressionParser::new()
+            .parse_ast(MathExpressionTokenizer::new(source.to_owned()).unwrap())
+    }
+
+    fn num(number: f64) -> Expr {
+        Expr::Number(number)
+    }
+
+    fn bin(op: char, lhs: Expr, rhs: Expr) -> Expr {
+        Expr::BinaryOp {
+            op,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+        }
+    }
+
+    fn neg(operand: Expr) -> Expr {
+        Expr::UnaryOp {
+            op: '-',
+            operand: Box::new(operand),
+        }
+    }
+
+    fn group(inner: Expr) -> Expr {
+        Expr::Group(Box::new(inner))
+    }
+
+    #[test]
+    fn test_precedence_shapes_the_tree() {
+        assert_eq!(
+            ast("1+2*3"),
+            Ok(bin('+', num(1.0), bin('*', num(2.0), num(3.0))))
+        );
+        assert_eq!(
+            ast("(1+2)*3"),
+            Ok(bin('*', group(bin('+', num(1.0), num(2.0))), num(3.0)))
+        );
+        assert_eq!(
+            ast("2*3-1"),
+            Ok(bin('-', bin('*', num(2.0), num(3.0)), num(1.0)))
+        );
+        assert_eq!(ast("4"), Ok(num(4.0)));
+        assert_eq!(ast("((4))"), Ok(group(group(num(4.0)))));
+    }
+
+    #[test]
+    fn test_left_associativity() {
+        assert_eq!(
+            ast("8-4-2"),
+            Ok(bin('-', bin('-', num(8.0), num(4.0)), num(2.0)))
+        );
+        assert_eq!(
+            ast("8/4*2"),
+            Ok(bin('*', bin('/', num(8.0), num(4.0)), num(2.0)))
+        );
+    }
+
+    #[test]
+    fn test_exponents_are_right_associative() {
+        assert_eq!(
+            ast("2^3^2"),
+            Ok(bin('^', num(2.0), bin('^', num(3.0), num(2.0))))
+        );
+        assert_eq!(
+            ast("(1+2)^2*3"),
+            Ok(bin(
+                '*',
+                bin('^', group(bin('+', num(1.0), num(2.0))), num(2.0)),
+                num(3.0)
+            ))
+        );
+        assert_eq!(ast("-2^2"), Ok(neg(bin('^', num(2.0), num(2.0)))));
+    }
+
+    #[test]
+    fn test_unary_operators() {
+        assert_eq!(ast("-2*3"), Ok(bin('*', neg(num(2.0)), num(3.0))));
+        assert_eq!(
+            ast("2*-(1+1)"),
+            Ok(bin('*', num(2.0), neg(group(bin('+', num(1.0), num(1.0))))))
+        );
+        assert_eq!(ast("- -1"), Ok(neg(neg(num(1.0)))));
+    }
+
+    #[test]
+    fn test_errors_match_the_flat_parser() {
+        for source in ["1+", "(1", "1)", "()", "1 2", "*1", "2*(3+)"] {
+            let flat = MathExpressionParser::new()
+                .parse(MathExpressionTokenizer::new(source.to_owned()).unwrap());
+            assert_eq!(ast(source).err(), flat.err(), "{source:?}");
+        }
+    }
+
+    #[test]
+    fn test_deep_trees_drop_without_overflow() {
+        let depth = 100_000;
+        let trees = [
+            // Groups nested in groups.
+            ast(&format!("{}1{}", "(".repeat(depth), ")".repeat(depth))).unwrap(),
+            // A chain of unary operators, nesting to the right.
+            ast(&format!("{}1", "-".repeat(depth))).unwrap(),
+            // A long chain of terms, nesting to the left.
+            ast(&format!("1{}", "+1".repeat(depth))).unwrap(),
+        ];
+
+        // Far too small for a recursive drop of any of them.
+        std::thread::Builder::new()
+            .stack_size(64 * 1024)
+            .spawn(move || drop(trees))
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_builder_trees_drop_without_overflow() {
+        use crate::math_expression_parser::ExpressionBuilder;
+
+        let depth = 100_000;
+        // `1 - 1 - ... - 1`, every operator the left operand of the next.
+        let mut left = ExpressionBuilder::new();
+        left.number(1.0).unwrap();
+        for _ in 0..depth {
+            left.op('-').unwrap().number(1.0).unwrap();
+        }
+        // `1 - (1 - (... - (1)))`, every group the right operand.
+        let mut right = ExpressionBuilder::new();
+        for _ in 0..depth {
+            right.number(1.0).unwrap().op('-').unwrap().open().unwrap();
+        }
+        right.number(1.0).unwrap();
+        for _ in 0..depth {
+            right.close().unwrap();
+        }
+        // `2 ^ 1 ^ ... ^ 1`, right-associative without groups.
+        let mut powers = ExpressionBuilder::new();
+        powers.number(2.0).unwrap();
+        for _ in 0..depth {
+            powers.op('^').unwrap().number(1.0).unwrap();
+        }
+
+        let trees = [left, right, powers].map(|builder| builder.build().unwrap().to_ast());
+        assert!(
+            matches!(&trees[0], Expr::BinaryOp { lhs, .. } if matches!(**lhs, Expr::BinaryOp { .. }))
+        );
+        assert!(matches!(&trees[1], Expr::BinaryOp { rhs, .. } if matches!(**rhs, Expr::Group(_))));
+        assert!(
+            matches!(&trees[2], Expr::BinaryOp { rhs, .. } if matches!(**rhs, Expr::BinaryOp { .. }))
+        );
+
+        // Far too small for a recursive drop of any of them.
+        std::thread::Builder::new()
+            .stack_size(64 * 1024)
+            .spawn(move || drop(trees))
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_deep_trees_build_and_evaluate_on_a_default_stack() {
+        let depth = 100_000;
+        // There is no depth limit to disable; nesting is only bounded by the
+        // input length.
+        let sources = [
+            format!("{}1{}", "(".repeat(depth), ")".repeat(depth)),
+            format!("{}1", "-".repeat(depth)),
+            format!("1{}", "-1".repeat(depth)),
+            format!("2{}", "^1".repeat(depth)),
+        ];
+
+        let values = std::thread::spawn(move || {
+            sources
+                .iter()
+                .map(|source| {
+                    let tree = ast(source).unwrap();
+                    crate::evaluate_tree(&tree)
+                })
+                .collect::<Vec<_>>()
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(
+            values,
+            [Some(1.0), Some(1.0), Some(1.0 - depth as f64), Some(2.0)]
+        );
+    }
+
+    // Small enough for recursion, and independent of the evaluator's code.
+    fn eval(expr: &Expr) -> f64 {
+        match expr {
+            Expr::Number(number) => *number,
+            Expr::UnaryOp { op: '-', operand } => -eval(operand),
+            Expr::UnaryOp { operand, .. } | Expr::Group(operand) => eval(operand),
+            Expr::BinaryOp { op, lhs, rhs } => {
+                let (lhs, rhs) = (eval(lhs), eval(rhs));
+                match op {
+                    '+' => lhs + rhs,
+                    '-' => lhs - rhs,
+                    '*' => lhs * rhs,
+                    '/' => lhs / rhs,
+                    '%' => lhs % rhs,
+                    _ => lhs.powf(rhs),
+                }
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_tree_agrees_with_the_evaluator(source in expression()) {
+            let parsed: MathExpression = source.parse().unwrap();
+            if let Ok(expected) = crate::evaluate(&parsed, &source) {
+                let value = eval(&parsed.to_ast());
+                prop_assert!(
+                    value.to_bits() == expected.to_bits() || (value.is_nan() && expected.is_nan()),
+                    "{} != {}", value, expected
+                );
+            }
+        }
+
+        #[test]
+        fn test_errors_match_on_any_input(source in "[0-9+*/()-][0-9+*/() .-]{0,15}") {
+            let flat = MathExpressionParser::new().parse(MathExpressionTokenizer::new(source.clone()).unwrap());
+            prop_assert_eq!(ast(&source).err(), flat.err());
+        }
+    }
+}
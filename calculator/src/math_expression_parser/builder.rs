@@ -0,0 +1,304 @@
+use std::fmt;
+
+use super::{
+    BraceStack, MathExpression, MathExpressionParserError, can_end_with, classify, ends_operand,
+    may_follow,
+};
+use crate::math_expression_tokenizer::{
+    MathExpressionTokenizerError, OPERATORS, Token, suggest, write_number,
+};
+use crate::position::Position;
+
+// Builds a `MathExpression` token by token, checking every step against the
+// parser's rules, so no string is formatted and parsed again. Token positions
+// are the offsets the tokens have in the printed (`Display`) form, which is
+// also what error positions refer to.
+#[derive(Debug, Clone)]
+pub struct ExpressionBuilder {
+    expression: MathExpression,
+    braces: BraceStack,
+    last_group: Option<usize>,
+    printed_len: usize,
+}
+
+impl Default for ExpressionBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExpressionBuilder {
+    pub fn new() -> Self {
+        Self {
+            expression: MathExpression::empty(),
+            braces: BraceStack::new(),
+            last_group: None,
+            printed_len: 0,
+        }
+    }
+
+    pub fn number(&mut self, number: f64) -> Result<&mut Self, MathExpressionParserError> {
+        let token = Token::digit(number).map_err(|_| MathExpressionParserError::NotANumber {
+            idx: self.position(self.printed_len),
+        })?;
+        self.push(token)
+    }
+
+    // '+' and '-' are unary where an operand is expected, as in parsed input.
+    pub fn op(&mut self, op: char) -> Result<&mut Self, MathExpressionParserError> {
+        if !OPERATORS.contains(&op) {
+            return Err(MathExpressionTokenizerError::InvalidToken {
+                idx: self.position(self.printed_len),
+                ch: op,
+                suggestion: suggest(op, None, None),
+            }
+            .into());
+        }
+
+        self.push(Token::Operator(op))
+    }
+
+    pub fn open(&mut self) -> Result<&mut Self, MathExpressionParserError> {
+        self.push(Token::OpenBrace)
+    }
+
+    pub fn close(&mut self) -> Result<&mut Self, MathExpressionParserError> {
+        self.push(Token::CloseBrace)
+    }
+
+    // Fails like the parser would at the end of input: on an empty or
+    // unfinished expression, or on an unclosed brace.
+    pub fn build(&self) -> Result<MathExpression, MathExpressionParserError> {
+        let Some(last) = self.expression.tokens.last() else {
+            return Err(MathExpressionParserError::EmptyExpression);
+        };
+
+        if !can_end_with(last) {
+            return Err(MathExpressionParserError::InvalidExpression {
+                idx: self.position(self.printed_len),
+                found: None,
+                after: Some(last.clone()),
+            });
+        }
+
+        if let Some(&open) = self.braces.first() {
+            return Err(MathExpressionParserError::UnclosedBrace {
+                idx: self.position(open),
+            });
+        }
+
+        Ok(self.expression.clone())
+    }
+
+    fn push(&mut self, token: Token) -> Result<&mut Self, MathExpressionParserError> {
+        let ends_operand = ends_operand(&self.expression.tokens);
+        let token = classify(token, ends_operand);
+        // Binary operators are printed with a space on each side.
+        let idx = match token {
+            Token::Operator(_) if ends_operand => self.printed_len + 1,
+            _ => self.printed_len,
+        };
+
+        if token == Token::CloseBrace && self.braces.is_empty() {
+            return Err(MathExpressionParserError::UnmatchedCloseBrace {
+                idx: self.position(idx),
+                last_group: self.last_group.map(|group| self.position(group)),
+            });
+        }
+
+        if !may_follow(&token, ends_operand) {
+            return Err(MathExpressionParserError::InvalidExpression {
+                idx: self.position(idx),
+                found: Some(token),
+                after: self.expression.tokens.last().cloned(),
+            });
+        }
+
+        let width = match token {
+            Token::Digit(number) => printed_width(number),
+            Token::Operator(_) => 3,
+            Token::UnaryOperator(_) | Token::OpenBrace | Token::CloseBrace => 1,
+        };
+
+        match token {
+            Token::OpenBrace => self.braces.push(idx),
+            Token::CloseBrace => self.last_group = self.braces.pop(),
+            Token::Digit(_) | Token::Operator(_) | Token::UnaryOperator(_) => {}
+        }
+
+        self.expression.push(idx, token).map_err(|_| {
+            MathExpressionParserError::PositionOverflow {
+                idx: self.position(idx),
+            }
+        })?;
+        self.printed_len += width;
+
+        Ok(self)
+    }
+
+    // The printed form is ASCII, so byte and char offsets are equal.
+    fn position(&self, idx: usize) -> Position {
+        Position::new(idx, idx)
+    }
+}
+
+// Length of the printed number, counted without allocating.
+fn printed_width(number: f64) -> usize {
+    struct Counter(usize);
+
+    impl fmt::Write for Counter {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            self.0 += s.len();
+            Ok(())
+        }
+    }
+
+    let mut counter = Counter(0);
+    let _ = write_number(&mut counter, number);
+    counter.0
+}
+
+// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error_code::ErrorCode;
+    use crate::math_expression_parser::tests::expression;
+    use proptest::prelude::*;
+
+    fn parse(source: &str) -> Result<MathExpression, MathExpressionParserError> {
+        source.parse()
+    }
+
+    // Positions differ between the source and the printed form, so only the
+    // outcome is compared.
+    fn outcome(
+        result: Result<MathExpression, MathExpressionParserError>,
+    ) -> Result<Vec<Token>, ErrorCode> {
+        result
+            .map(|parsed| parsed.tokens().to_vec())
+            .map_err(|err| err.code())
+    }
+
+    #[test]
+    fn test_build_matches_parsed() -> Result<(), MathExpressionParserError> {
+        let built = ExpressionBuilder::new()
+            .number(1.5)?
+            .op('+')?
+            .open()?
+            .number(2.0)?
+            .op('*')?
+            .op('-')?
+            .number(3.0)?
+            .close()?
+            .build()?;
+
+        assert_eq!(built.to_string(), "1.5 + (2 * -3)");
+        assert_eq!(built, parse("1.5 + (2 * -3)")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_keeps_full_precision() -> Result<(), MathExpressionParserError> {
+        let number = 0.1 + 0.2;
+        let built = ExpressionBuilder::new().number(number)?.build()?;
+
+        assert_eq!(built.tokens(), [Token::Digit(number)]);
+        Ok(())
+    }
+
+    // Feeds the tokens of `source` to a builder and returns the first error.
+    fn build_tokens(source: &str) -> Result<MathExpression, MathExpressionParserError> {
+        let mut builder = ExpressionBuilder::new();
+        for token in source.split_whitespace() {
+            match token {
+                "(" => builder.open()?,
+                ")" => builder.close()?,
+                "+" | "-" | "*" | "/" | "%" | "^" => builder.op(token.chars().next().unwrap())?,
+                number => builder.number(number.parse().unwrap())?,
+            };
+        }
+
+        builder.build()
+    }
+
+    #[test]
+    fn test_rejects_what_the_parser_rejects() {
+        for source in [
+            "1 2",
+            "* 1",
+            "1 + * 2",
+            "1 +",
+            "( )",
+            "1 ( 2 )",
+            "( 1 ) 2",
+            ")",
+            "( 1 ) )",
+            "( 1",
+            "( ( 1 ) + 2",
+            "1 ^ 2",
+        ] {
+            assert_eq!(
+                outcome(build_tokens(source)),
+                outcome(parse(source)),
+                "{source}"
+            );
+        }
+
+        assert_eq!(
+            build_tokens("1 + * 2"),
+            Err(MathExpressionParserError::InvalidExpression {
+                idx: Position::new(4, 4),
+                found: Some(Token::Operator('*')),
+                after: Some(Token::Operator('+')),
+            })
+        );
+        assert_eq!(
+            build_tokens("( 1 ) )"),
+            Err(MathExpressionParserError::UnmatchedCloseBrace {
+                idx: Position::new(3, 3),
+                last_group: Some(Position::new(0, 0)),
+            })
+        );
+
+        assert_eq!(
+            ExpressionBuilder::new().build(),
+            Err(MathExpressionParserError::EmptyExpression)
+        );
+    }
+
+    #[test]
+    fn test_rejects_nan() {
+        let mut builder = ExpressionBuilder::new();
+        builder.number(1.0).unwrap().op('+').unwrap();
+        assert_eq!(
+            builder.number(f64::NAN).err(),
+            Some(MathExpressionParserError::NotANumber {
+                idx: Position::new(4, 4)
+            })
+        );
+        assert!(builder.number(f64::INFINITY).unwrap().build().is_ok());
+    }
+
+    proptest! {
+        #[test]
+        fn test_built_matches_parsed(source in expression()) {
+            let parsed = parse(&source).unwrap();
+            let spaced = parsed
+                .tokens()
+                .iter()
+                .map(|token| match token {
+                    Token::Digit(number) => number.to_string(),
+                    Token::Operator(op) | Token::UnaryOperator(op) => op.to_string(),
+                    Token::OpenBrace => "(".to_string(),
+                    Token::CloseBrace => ")".to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+
+            let built = build_tokens(&spaced).unwrap();
+            prop_assert_eq!(built.tokens(), parsed.tokens());
+            prop_assert_eq!(built.to_string(), parsed.to_string());
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use super::MathExpression;
+use crate::math_expression_tokenizer::{Token, write_number};
+
+// What is known about a group or the whole expression while its tokens are
+// read: where it opens, how many operands and operators it holds at its own
+// level, and the last one of them.
+struct Level {
+    open: usize,
+    items: usize,
+    last: Item,
+}
+
+#[derive(Clone, Copy)]
+enum Item {
+    Other,
+    Number,
+    // A group that keeps its braces, by the index of its '(' and ')'.
+    Group(usize, usize),
+}
+
+impl MathExpression {
+    // Compact key that is equal for expressions that only differ in spacing,
+    // in the spelling of their literals or in redundant parentheses, e.g. for
+    // caching or rate limiting identical submissions. Only braces that are
+    // redundant whatever the precedence of operators are dropped: those
+    // around the whole expression, around a single number and directly around
+    // another group. The key parses back to the same expression, so different
+    // expressions never share a key.
+    //
+    // The format is stable: it is pinned by the golden tests and only changes
+    // with a major version.
+    pub fn canonical_key(&self) -> String {
+        let mut dropped = vec![false; self.tokens.len()];
+        let mut levels = vec![Level::new(0)];
+
+        for (idx, token) in self.tokens.iter().enumerate() {
+            let item = match token {
+                Token::OpenBrace => {
+                    levels.push(Level::new(idx));
+                    continue;
+                }
+                Token::CloseBrace => {
+                    let Some(group) = levels.pop() else {
+                        unreachable!("parsed expression has an unmatched ')'")
+                    };
+                    match group.single() {
+                        Some(item @ (Item::Number | Item::Group(..))) => {
+                            dropped[group.open] = true;
+                            dropped[idx] = true;
+                            item
+                        }
+                        _ => Item::Group(group.open, idx),
+                    }
+                }
+                Token::Digit(_) => Item::Number,
+                Token::Operator(_) | Token::UnaryOperator(_) => Item::Other,
+            };
+
+            let Some(level) = levels.last_mut() else {
+                unreachable!("parsed expression has an unmatched ')'")
+            };
+            level.items += 1;
+            level.last = item;
+        }
+
+        if let Some(Item::Group(open, close)) = levels.last().and_then(Level::single) {
+            dropped[open] = true;
+            dropped[close] = true;
+        }
+
+        let mut key = String::with_capacity(self.tokens.len() * 2);
+        for (token, _) in self
+            .tokens
+            .iter()
+            .zip(dropped)
+            .filter(|(_, dropped)| !dropped)
+        {
+            match token {
+                Token::Digit(number) => {
+                    let _ = write_number(&mut key, *number);
+                }
+                Token::Operator(op) | Token::UnaryOperator(op) => key.push(*op),
+                Token::OpenBrace => key.push('('),
+                Token::CloseBrace => key.push(')'),
+            }
+        }
+
+        key
+    }
+}
+
+impl Level {
+    fn new(open: usize) -> Self {
+        Self {
+            open,
+            items: 0,
+            last: Item::Other,
+        }
+    }
+
+    fn single(&self) -> Option<Item> {
+        (self.items == 1).then_some(self.last)
+    }
+}
+
+// Unit tests
+#[cfg(test)]
+mod tests {
+    use crate::math_expression_parser::MathExpression;
+    use crate::math_expression_parser::tests::expression;
+    use proptest::prelude::*;
+
+    fn key(source: &str) -> String {
+        source.parse::<MathExpression>().unwrap().canonical_key()
+    }
+
+    #[test]
+    fn test_variants_share_a_key() {
+        for source in [
+            "1+2",
+            " 1 + 2 ",
+            "(1+2)",
+            "((1 + 2))",
+            "01 + 2.0",
+            "1\t+\n(2)",
+        ] {
+            assert_eq!(key(source), "1+2", "{source}");
+        }
+        assert_eq!(key("((3)) * ((1 - 2))"), "3*(1-2)");
+        assert_eq!(key("-((4)) / (.5)"), "-4/0.5");
+    }
+
+    #[test]
+    fn test_different_formulas_differ() {
+        let keys = [
+            "1+2", "1+3", "1-2", "2+1", "(1+2)*3", "1+2*3", "-(1+2)", "(-1)+2", "-1+2",
+        ]
+        .map(key);
+        for (i, lhs) in keys.iter().enumerate() {
+            for rhs in &keys[i + 1..] {
+                assert_ne!(lhs, rhs);
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_key_parses_to_the_same_key(source in expression()) {
+            let first = key(&source);
+            prop_assert_eq!(key(&first), first.clone());
+            prop_assert_eq!(key(&format!("({source})")), first);
+        }
+    }
+}
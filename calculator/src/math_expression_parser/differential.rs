@@ -0,0 +1,175 @@
+// Differential tests: every frontend that turns text into a `MathExpression`
+// must agree with `MathExpressionParser::parse` over a
+// `MathExpressionTokenizer` on accepting or rejecting the input, on the
+// tokens, and on the error. Adding a frontend takes one entry in `FRONTENDS`.
+// Tokenizers are wrapped in a `CheckedTokenizer`, so every index they hand out
+// is checked against the source as well.
+
+use super::tests::expression;
+use super::{
+    ExpressionBuilder, MathExpression, MathExpressionParser, MathExpressionParserError, parse_all,
+};
+use crate::error_code::ErrorCode;
+use crate::math_expression_tokenizer::channel_tokenizer::{ChannelTokenizer, tokenize_into};
+use crate::math_expression_tokenizer::checked::CheckedTokenizer;
+use crate::math_expression_tokenizer::{MathExpressionTokenizer, Token, TokenizerTraits};
+use crate::position::Position;
+use proptest::prelude::*;
+use std::sync::mpsc;
+
+type Outcome = Result<Vec<Token>, (ErrorCode, Option<Position>)>;
+
+// How closely a frontend's errors must match the reference.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tolerance {
+    Exact,
+    // Positions refer to another text (e.g. the builder's printed form), so
+    // only the error code is compared.
+    CodeOnly,
+}
+
+// `None` when the frontend does not apply to the input.
+type Frontend = fn(&str) -> Option<Outcome>;
+
+const FRONTENDS: &[(&str, Tolerance, Frontend)] = &[
+    ("parse_dyn", Tolerance::Exact, parse_dyn),
+    ("parse_detailed", Tolerance::Exact, parse_detailed),
+    ("parse_into", Tolerance::Exact, parse_into),
+    ("parse_all", Tolerance::Exact, script),
+    ("channel", Tolerance::Exact, channel),
+    ("builder", Tolerance::CodeOnly, builder),
+];
+
+fn outcome(result: Result<MathExpression, MathExpressionParserError>) -> Outcome {
+    result
+        .map(|parsed| parsed.tokens().to_vec())
+        .map_err(|err| (err.code(), err.idx()))
+}
+
+fn tokenizer(
+    source: &str,
+) -> Result<CheckedTokenizer<MathExpressionTokenizer>, MathExpressionParserError> {
+    let tokenizer = MathExpressionTokenizer::new(source.to_string())?;
+    Ok(CheckedTokenizer::new(tokenizer, source))
+}
+
+fn reference(source: &str) -> Outcome {
+    outcome(tokenizer(source).and_then(|tokenizer| MathExpressionParser::new().parse(tokenizer)))
+}
+
+fn parse_dyn(source: &str) -> Option<Outcome> {
+    Some(outcome(tokenizer(source).and_then(|mut tokenizer| {
+        MathExpressionParser::new().parse_dyn(&mut tokenizer)
+    })))
+}
+
+fn parse_detailed(source: &str) -> Option<Outcome> {
+    Some(outcome(tokenizer(source).and_then(|tokenizer| {
+        MathExpressionParser::new()
+            .parse_detailed(tokenizer)
+            .map_err(|failure| failure.error)
+    })))
+}
+
+// Parses into buffers that already hold an earlier expression.
+fn parse_into(source: &str) -> Option<Outcome> {
+    let mut parser = MathExpressionParser::new();
+    let mut out = MathExpression::empty();
+    parser
+        .parse_into(tokenizer("(1 + 2) * 3").unwrap(), &mut out)
+        .unwrap();
+
+    Some(outcome(tokenizer(source).and_then(|tokenizer| {
+        parser.parse_into(tokenizer, &mut out)?;
+        Ok(out)
+    })))
+}
+
+// A script of one statement; blank scripts have no statement to compare.
+fn script(source: &str) -> Option<Outcome> {
+    let mut results = parse_all(source).into_iter();
+    let first = results.next()?;
+    assert!(results.next().is_none(), "'{source}' split into statements");
+    Some(outcome(first))
+}
+
+fn channel(source: &str) -> Option<Outcome> {
+    let (tx, rx) = mpsc::channel();
+    tokenize_into(source.to_string(), tx);
+    Some(outcome(MathExpressionParser::new().parse(
+        CheckedTokenizer::new(ChannelTokenizer::new(rx), source),
+    )))
+}
+
+// Feeds the tokens to a builder as they are read, so the first error in the
+// input wins like in the parser.
+fn builder(source: &str) -> Option<Outcome> {
+    let build = || {
+        let mut tokenizer = tokenizer(source)?;
+        let mut builder = ExpressionBuilder::new();
+        while tokenizer.has_token() {
+            match tokenizer.next_token()?.1 {
+                Token::Digit(number) => builder.number(number)?,
+                Token::Operator(op) | Token::UnaryOperator(op) => builder.op(op)?,
+                Token::OpenBrace => builder.open()?,
+                Token::CloseBrace => builder.close()?,
+            };
+        }
+        builder.build()
+    };
+
+    Some(outcome(build()))
+}
+
+fn agree(expected: &Outcome, actual: &Outcome, tolerance: Tolerance) -> bool {
+    match (expected, actual, tolerance) {
+        (Ok(expected), Ok(actual), _) => expected == actual,
+        (Err(expected), Err(actual), Tolerance::Exact) => expected == actual,
+        (Err((expected, _)), Err((actual, _)), Tolerance::CodeOnly) => expected == actual,
+        _ => false,
+    }
+}
+
+fn check(source: &str) -> Result<(), TestCaseError> {
+    let expected = reference(source);
+    for (name, tolerance, frontend) in FRONTENDS {
+        if let Some(actual) = frontend(source) {
+            prop_assert!(
+                agree(&expected, &actual, *tolerance),
+                "{name} disagrees on {source:?}\n  reference: {expected:?}\n  {name}: {actual:?}"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_frontends_agree_on_samples() {
+    for source in [
+        "(12.5 + 3) * 2",
+        "1 + ",
+        "1 + 2)",
+        "((1)",
+        "1 $ 2",
+        "1..2 + 3",
+        "",
+        "   ",
+        "- -4 * (2 / .5)",
+        "é + 1",
+    ] {
+        check(source).unwrap();
+    }
+}
+
+proptest! {
+    #[test]
+    fn test_frontends_agree_on_valid_input(source in expression()) {
+        check(&source)?;
+    }
+
+    #[test]
+    fn test_frontends_agree_on_soup(source in "[0-9+\\-*/(). é🙂]{0,64}") {
+        check(&source)?;
+    }
+}
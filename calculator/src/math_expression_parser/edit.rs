@@ -0,0 +1,272 @@
+use super::encoding::DecodedTokens;
+use super::{MathExpression, MathExpressionParser, MathExpressionParserError};
+use crate::math_expression_tokenizer::{MathExpressionTokenizerError, Token, TokenizerTraits};
+use crate::position::Position;
+
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenEdit {
+    Keep,
+    Replace(Token),
+    ReplaceMany(Vec<Token>),
+    Remove,
+}
+
+// An edit that left the expression invalid. `edit` is the index of the
+// original token whose edit is to blame: the last edit at or before the
+// point where parsing failed.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Edit of token {edit} leaves an invalid expression: {error}")]
+pub struct EditFailure {
+    #[source]
+    pub error: MathExpressionParserError,
+    pub edit: usize,
+}
+
+impl MathExpression {
+    // Rewrites the expression token by token. `f` gets the index and the
+    // token, and replacement tokens take the position of the token they
+    // replace. The result goes through the parser again, so an edit cannot
+    // silently produce an expression a parse would reject. `source` is the
+    // text the expression was parsed from; error positions are resolved in it.
+    pub fn map_tokens(
+        &self,
+        source: &str,
+        mut f: impl FnMut(usize, &Token) -> TokenEdit,
+    ) -> Result<MathExpression, EditFailure> {
+        let mut tokens = Vec::with_capacity(self.tokens.len());
+        // Index of the original token each new token comes from.
+        let mut origins = Vec::with_capacity(self.tokens.len());
+        let mut edits = Vec::new();
+
+        for (edit, (idx, token)) in self.positioned_tokens().enumerate() {
+            let replacement = match f(edit, token) {
+                TokenEdit::Keep => {
+                    tokens.push((idx, token.clone()));
+                    origins.push(edit);
+                    continue;
+                }
+                TokenEdit::Replace(token) => vec![token],
+                TokenEdit::ReplaceMany(tokens) => tokens,
+                TokenEdit::Remove => Vec::new(),
+            };
+
+            edits.push(edit);
+            for token in replacement {
+                tokens.push((idx, token));
+                origins.push(edit);
+            }
+        }
+
+        MathExpressionParser::new()
+            .parse_detailed(EditedTokens {
+                tokens: DecodedTokens::new(tokens),
+                source,
+            })
+            .map_err(|failure| {
+                let failed_at = origins
+                    .get(failure.tokens_parsed)
+                    .copied()
+                    .unwrap_or(self.tokens.len());
+                let edit = edits
+                    .iter()
+                    .rev()
+                    .find(|&&edit| edit <= failed_at)
+                    .copied()
+                    .unwrap_or(failed_at);
+
+                EditFailure {
+                    error: failure.error,
+                    edit,
+                }
+            })
+    }
+
+    // Replaces every literal equal to `value` with `replacement`.
+    pub fn replace_literal(
+        &self,
+        source: &str,
+        value: f64,
+        replacement: Token,
+    ) -> Result<MathExpression, EditFailure> {
+        let literal = Token::Digit(value);
+        self.map_tokens(source, |_, token| {
+            if *token == literal {
+                TokenEdit::Replace(replacement.clone())
+            } else {
+                TokenEdit::Keep
+            }
+        })
+    }
+}
+
+// Edited tokens at the positions of the original ones, which are in `source`.
+struct EditedTokens<'a> {
+    tokens: DecodedTokens,
+    source: &'a str,
+}
+
+impl TokenizerTraits for EditedTokens<'_> {
+    fn has_token(&self) -> bool {
+        self.tokens.has_token()
+    }
+
+    fn next_token(&mut self) -> Result<(usize, Token), MathExpressionTokenizerError> {
+        self.tokens.next_token()
+    }
+
+    fn curr_index(&self) -> usize {
+        self.tokens.curr_index()
+    }
+
+    // Moved back to a char boundary, so a source that does not match the
+    // expression gives a wrong position rather than a panic.
+    fn position_of(&self, byte: usize) -> Position {
+        Position::in_source(self.source, self.source.floor_char_boundary(byte))
+    }
+}
+
+// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> MathExpression {
+        source.parse().unwrap()
+    }
+
+    #[test]
+    fn test_keep_everything() {
+        let expr = parse("-(1 + 2) * 3");
+        assert_eq!(
+            expr.map_tokens("-(1 + 2) * 3", |_, _| TokenEdit::Keep),
+            Ok(expr)
+        );
+    }
+
+    #[test]
+    fn test_replace_literal() {
+        let source = "1 + 0.0825 * (2 - 0.0825)";
+        let expr = parse(source);
+        let edited = expr
+            .replace_literal(source, 0.0825, Token::Digit(0.09))
+            .unwrap();
+
+        assert_eq!(edited.to_string(), "1 + 0.09 * (2 - 0.09)");
+        assert_eq!(
+            edited
+                .positioned_tokens()
+                .map(|(idx, _)| idx)
+                .collect::<Vec<_>>(),
+            expr.positioned_tokens()
+                .map(|(idx, _)| idx)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_replace_many_wraps_in_parentheses() {
+        let edited = parse("2 * 3")
+            .map_tokens("2 * 3", |_, token| match token {
+                Token::Digit(2.0) => TokenEdit::ReplaceMany(vec![
+                    Token::OpenBrace,
+                    Token::Digit(1.0),
+                    Token::Operator('+'),
+                    Token::Digit(1.0),
+                    Token::CloseBrace,
+                ]),
+                _ => TokenEdit::Keep,
+            })
+            .unwrap();
+
+        assert_eq!(edited.to_string(), "(1 + 1) * 3");
+        // Replacement tokens share the position of the replaced one.
+        assert_eq!(
+            edited
+                .positioned_tokens()
+                .map(|(idx, _)| idx)
+                .collect::<Vec<_>>(),
+            [0, 0, 0, 0, 0, 2, 4]
+        );
+    }
+
+    #[test]
+    fn test_removal_that_stays_valid() {
+        let edited = parse("-3").map_tokens("-3", |edit, _| {
+            if edit == 0 {
+                TokenEdit::Remove
+            } else {
+                TokenEdit::Keep
+            }
+        });
+
+        assert_eq!(edited.map(|edited| edited.to_string()), Ok("3".to_string()));
+    }
+
+    #[test]
+    fn test_removal_that_becomes_invalid() {
+        let remove = |source: &str, removed: usize| {
+            parse(source).map_tokens(source, |edit, _| {
+                if edit == removed {
+                    TokenEdit::Remove
+                } else {
+                    TokenEdit::Keep
+                }
+            })
+        };
+
+        assert_eq!(
+            remove("1 + 2", 2),
+            Err(EditFailure {
+                error: MathExpressionParserError::InvalidExpression {
+                    idx: Position::new(3, 3),
+                    found: None,
+                    after: Some(Token::Operator('+')),
+                },
+                edit: 2,
+            })
+        );
+        assert_eq!(
+            remove("1 + 2", 1),
+            Err(EditFailure {
+                error: MathExpressionParserError::InvalidExpression {
+                    idx: Position::new(4, 4),
+                    found: Some(Token::Digit(2.0)),
+                    after: Some(Token::Digit(1.0)),
+                },
+                edit: 1,
+            })
+        );
+        assert_eq!(remove("(1) + 2", 2).unwrap_err().edit, 2);
+
+        // Positions count the chars of the source.
+        let failure = remove("\u{3000}1 + 2", 1).unwrap_err();
+        assert_eq!(failure.error.idx(), Some(Position::new(7, 5)));
+        assert_eq!(
+            failure.to_string(),
+            "Edit of token 1 leaves an invalid expression: \
+             Number '2' cannot follow number '1' at position 6"
+        );
+    }
+
+    #[test]
+    fn test_unknown_operator_is_rejected() {
+        let failure = parse("1 + 2")
+            .map_tokens("1 + 2", |edit, _| {
+                if edit == 1 {
+                    TokenEdit::Replace(Token::Operator('x'))
+                } else {
+                    TokenEdit::Keep
+                }
+            })
+            .unwrap_err();
+
+        assert_eq!(failure.edit, 1);
+        assert_eq!(
+            failure.to_string(),
+            "Edit of token 1 leaves an invalid expression: Tokenizer error: \
+             Found invalid token 'x' at position 3, did you mean '*'?"
+        );
+    }
+}
@@ -0,0 +1,153 @@
+use super::script::parse_statement;
+use super::{MathExpression, MathExpressionParser, can_end_with, classify, may_follow};
+use crate::math_expression_tokenizer::{MathExpressionTokenizer, Token, TokenizerTraits};
+use crate::position::{Position, Span};
+
+// Finds the arithmetic in free-form text, e.g. the `12.5*3` of "subtotal is
+// 12.5*3 plus shipping". Whatever the tokenizer rejects (words, punctuation)
+// splits the text into runs of tokens, and each run is searched from the left
+// for the longest complete expression. Only expressions of at least
+// `min_tokens` tokens with a binary operator are returned, so plain and signed
+// numbers do not match. Token positions refer to `text`.
+pub fn find_expressions(text: &str, min_tokens: usize) -> Vec<(Span, MathExpression)> {
+    let mut found = Vec::new();
+    let Ok(mut tokenizer) = MathExpressionTokenizer::new(text.to_string()) else {
+        return found;
+    };
+
+    let mut run = Vec::new();
+    while tokenizer.has_token() {
+        match tokenizer.next_spanned() {
+            Ok(token) => run.push(token),
+            Err(_) => search_run(text, &std::mem::take(&mut run), min_tokens, &mut found),
+        }
+    }
+    search_run(text, &run, min_tokens, &mut found);
+
+    found
+}
+
+fn search_run(
+    text: &str,
+    run: &[(Span, Token)],
+    min_tokens: usize,
+    found: &mut Vec<(Span, MathExpression)>,
+) {
+    let parser = MathExpressionParser::new();
+    let mut start = 0;
+
+    while start < run.len() {
+        let Some((len, has_binary)) = longest_expression(&run[start..]) else {
+            start += 1;
+            continue;
+        };
+
+        if len >= min_tokens && has_binary {
+            let span = run[start].0.merge(run[start + len - 1].0);
+            let offset = Position::in_source(text, span.start);
+            if let Ok(expr) = parse_statement(&parser, offset, span.slice(text)) {
+                found.push((span, expr));
+            }
+        }
+        start += len;
+    }
+}
+
+// Length of the longest prefix of `tokens` that is a complete expression, and
+// whether that prefix has a binary operator.
+fn longest_expression(tokens: &[(Span, Token)]) -> Option<(usize, bool)> {
+    let mut depth = 0usize;
+    let mut ends_operand = false;
+    let mut has_binary = false;
+    let mut longest = None;
+
+    for (len, (_, token)) in (1..).zip(tokens) {
+        let token = classify(token.clone(), ends_operand);
+        if !may_follow(&token, ends_operand) {
+            break;
+        }
+
+        match token {
+            Token::OpenBrace => depth += 1,
+            Token::CloseBrace if depth == 0 => break,
+            Token::CloseBrace => depth -= 1,
+            Token::Operator(_) => has_binary = true,
+            Token::Digit(_) | Token::UnaryOperator(_) => {}
+        }
+
+        ends_operand = can_end_with(&token);
+        if ends_operand && depth == 0 {
+            longest = Some((len, has_binary));
+        }
+    }
+
+    longest
+}
+
+// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(text: &str, min_tokens: usize) -> Vec<(&str, String)> {
+        find_expressions(text, min_tokens)
+            .into_iter()
+            .map(|(span, expr)| (span.slice(text), expr.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_one_expression() {
+        let text = "subtotal is 12.5*3 plus shipping";
+        let found = find_expressions(text, 3);
+
+        assert_eq!(found.len(), 1);
+        let (span, expr) = &found[0];
+        assert_eq!(*span, Span::new(12, 18));
+        assert_eq!(expr.to_string(), "12.5 * 3");
+        assert_eq!(
+            expr.positioned_tokens()
+                .map(|(idx, _)| idx)
+                .collect::<Vec<_>>(),
+            [12, 16, 17]
+        );
+    }
+
+    #[test]
+    fn test_two_expressions() {
+        assert_eq!(
+            find("add 1 + 2, then take (3 - 1) * 4 for the total", 3),
+            [
+                ("1 + 2", "1 + 2".to_string()),
+                ("(3 - 1) * 4", "(3 - 1) * 4".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_prefers_the_longest_run() {
+        assert_eq!(
+            find("1 + 2 * 3 - 4", 3),
+            [("1 + 2 * 3 - 4", "1 + 2 * 3 - 4".to_string())]
+        );
+        // An unclosed '(' or a second operand only cuts the run short.
+        assert_eq!(
+            find("costs (4 + 5 now", 3),
+            [("4 + 5", "4 + 5".to_string())]
+        );
+        assert_eq!(find("rooms 1 2 + 3", 3), [("2 + 3", "2 + 3".to_string())]);
+        assert_eq!(find("then 1 + 2) * 3", 3), [("1 + 2", "1 + 2".to_string())]);
+    }
+
+    #[test]
+    fn test_near_expressions_and_plain_text() {
+        assert!(find("upgrade to v1.2.3 today", 1).is_empty());
+        assert!(find("nothing to see here", 1).is_empty());
+        assert!(find("", 1).is_empty());
+        // Numbers without a binary operator are not expressions.
+        assert!(find("it is 42 degrees, or -5 at night", 1).is_empty());
+        assert!(find("a well-known fact", 1).is_empty());
+        // Too short for `min_tokens`.
+        assert!(find("take 1 + 2", 4).is_empty());
+    }
+}
@@ -0,0 +1,377 @@
+use std::io::{self, Read, Write};
+use std::iter::Peekable;
+
+use super::{MathExpression, MathExpressionParser, MathExpressionParserError};
+use crate::error_code::ErrorCode;
+use crate::math_expression_tokenizer::{MathExpressionTokenizerError, Token, TokenizerTraits};
+use crate::position::Position;
+
+use thiserror::Error;
+
+// Compact binary form of a `MathExpression`:
+//
+//   version: u8
+//   count:   varint
+//   count times: tag: u8, position delta: varint, [f64 little endian if digit]
+//
+// Varints are LEB128. Position deltas are taken from the previous token, the
+// first one from zero. Unary and binary operators share a tag: the parser that
+// revalidates decoded tokens tells them apart again.
+pub const FORMAT_VERSION: u8 = 1;
+
+const TAG_DIGIT: u8 = 0;
+const TAG_PLUS: u8 = 1;
+const TAG_MINUS: u8 = 2;
+const TAG_MULTIPLY: u8 = 3;
+const TAG_DIVIDE: u8 = 4;
+const TAG_OPEN_BRACE: u8 = 5;
+const TAG_CLOSE_BRACE: u8 = 6;
+const TAG_POWER: u8 = 7;
+const TAG_REMAINDER: u8 = 8;
+
+// Upper bound for preallocation, so a corrupted count cannot reserve gigabytes.
+const MAX_PREALLOCATED_TOKENS: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[non_exhaustive]
+pub enum DecodeError {
+    #[error("Stream ended before the expression was complete")]
+    Truncated,
+    #[error("Unsupported format version '{version}'")]
+    UnsupportedVersion { version: u8 },
+    #[error("Invalid token tag '{tag}'")]
+    InvalidTag { tag: u8 },
+    #[error("Varint does not fit into 64 bits")]
+    InvalidVarint,
+    #[error("I/O error: {kind}")]
+    Io { kind: io::ErrorKind },
+    #[error("Decoded tokens do not form a valid expression: {0}")]
+    InvalidExpression(#[from] MathExpressionParserError),
+}
+
+impl DecodeError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            DecodeError::Truncated => ErrorCode::DecodeTruncated,
+            DecodeError::UnsupportedVersion { .. } => ErrorCode::DecodeUnsupportedVersion,
+            DecodeError::InvalidTag { .. } => ErrorCode::DecodeInvalidTag,
+            DecodeError::InvalidVarint => ErrorCode::DecodeInvalidVarint,
+            DecodeError::Io { .. } => ErrorCode::DecodeIo,
+            DecodeError::InvalidExpression(err) => err.code(),
+        }
+    }
+}
+
+impl From<io::Error> for DecodeError {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::UnexpectedEof => DecodeError::Truncated,
+            kind => DecodeError::Io { kind },
+        }
+    }
+}
+
+impl MathExpression {
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        writer.write_all(&[FORMAT_VERSION])?;
+        write_varint(writer, self.tokens.len() as u64)?;
+
+        let mut previous = 0;
+        for (position, token) in self.positions.iter().zip(&self.tokens) {
+            let tag = match token {
+                Token::Digit(_) => TAG_DIGIT,
+                Token::Operator('+') | Token::UnaryOperator('+') => TAG_PLUS,
+                Token::Operator('-') | Token::UnaryOperator('-') => TAG_MINUS,
+                Token::Operator('*') => TAG_MULTIPLY,
+                Token::Operator('/') => TAG_DIVIDE,
+                Token::Operator('^') => TAG_POWER,
+                Token::Operator('%') => TAG_REMAINDER,
+                Token::Operator(op) | Token::UnaryOperator(op) => {
+                    unreachable!("tokenizer produced operator '{op}'")
+                }
+                Token::OpenBrace => TAG_OPEN_BRACE,
+                Token::CloseBrace => TAG_CLOSE_BRACE,
+            };
+
+            writer.write_all(&[tag])?;
+            write_varint(writer, u64::from(position - previous))?;
+            if let Token::Digit(number) = token {
+                writer.write_all(&number.to_le_bytes())?;
+            }
+
+            previous = *position;
+        }
+
+        Ok(())
+    }
+
+    // Decoded tokens go through the parser again, so a stream that was
+    // tampered with cannot produce an expression a parse would reject.
+    pub fn read_from(reader: &mut impl Read) -> Result<Self, DecodeError> {
+        let version = read_byte(reader)?;
+        if version != FORMAT_VERSION {
+            return Err(DecodeError::UnsupportedVersion { version });
+        }
+
+        let count = read_varint(reader)?;
+        let mut tokens = Vec::with_capacity(count.min(MAX_PREALLOCATED_TOKENS as u64) as usize);
+        let mut position = 0u64;
+
+        for _ in 0..count {
+            let tag = read_byte(reader)?;
+            position = position
+                .checked_add(read_varint(reader)?)
+                .ok_or(DecodeError::InvalidVarint)?;
+
+            let token = match tag {
+                TAG_DIGIT => {
+                    let mut bytes = [0; 8];
+                    reader.read_exact(&mut bytes)?;
+                    Token::Digit(f64::from_le_bytes(bytes))
+                }
+                TAG_PLUS => Token::Operator('+'),
+                TAG_MINUS => Token::Operator('-'),
+                TAG_MULTIPLY => Token::Operator('*'),
+                TAG_DIVIDE => Token::Operator('/'),
+                TAG_POWER => Token::Operator('^'),
+                TAG_REMAINDER => Token::Operator('%'),
+                TAG_OPEN_BRACE => Token::OpenBrace,
+                TAG_CLOSE_BRACE => Token::CloseBrace,
+                tag => return Err(DecodeError::InvalidTag { tag }),
+            };
+
+            let idx = usize::try_from(position).map_err(|_| {
+                MathExpressionParserError::PositionOverflow {
+                    idx: Position::new(usize::MAX, usize::MAX),
+                }
+            })?;
+            tokens.push((idx, token));
+        }
+
+        Ok(MathExpressionParser::new().parse(DecodedTokens::new(tokens))?)
+    }
+}
+
+// Feeds already positioned tokens to the parser, e.g. decoded or edited ones.
+pub(super) struct DecodedTokens {
+    tokens: Peekable<std::vec::IntoIter<(usize, Token)>>,
+    curr_idx: usize,
+}
+
+impl DecodedTokens {
+    pub(super) fn new(tokens: Vec<(usize, Token)>) -> Self {
+        Self {
+            tokens: tokens.into_iter().peekable(),
+            curr_idx: 0,
+        }
+    }
+}
+
+impl TokenizerTraits for DecodedTokens {
+    fn has_token(&self) -> bool {
+        self.tokens.len() > 0
+    }
+
+    fn next_token(&mut self) -> Result<(usize, Token), MathExpressionTokenizerError> {
+        let (idx, token) = self
+            .tokens
+            .next()
+            .ok_or(MathExpressionTokenizerError::NoToken)?;
+
+        self.curr_idx = self.tokens.peek().map_or(idx + 1, |(next, _)| *next);
+        Ok((idx, token))
+    }
+
+    fn curr_index(&self) -> usize {
+        self.curr_idx
+    }
+}
+
+fn write_varint(writer: &mut impl Write, mut value: u64) -> io::Result<()> {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+
+        if value == 0 {
+            return writer.write_all(&[byte]);
+        }
+        writer.write_all(&[byte | 0x80])?;
+    }
+}
+
+fn read_varint(reader: &mut impl Read) -> Result<u64, DecodeError> {
+    let mut value = 0u64;
+
+    for shift in (0..64).step_by(7) {
+        let byte = read_byte(reader)?;
+        let bits = u64::from(byte & 0x7f);
+        if shift == 63 && bits > 1 {
+            return Err(DecodeError::InvalidVarint);
+        }
+
+        value |= bits << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err(DecodeError::InvalidVarint)
+}
+
+fn read_byte(reader: &mut impl Read) -> Result<u8, DecodeError> {
+    let mut byte = [0];
+    reader.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math_expression_parser::tests::expression;
+    use crate::math_expression_tokenizer::MathExpressionTokenizer;
+    use proptest::prelude::*;
+
+    fn parse(expr: &str) -> MathExpression {
+        let tokenizer = MathExpressionTokenizer::new(expr.to_string()).unwrap();
+        MathExpressionParser::new().parse(tokenizer).unwrap()
+    }
+
+    fn encode(parsed: &MathExpression) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        parsed.write_to(&mut bytes).unwrap();
+        bytes
+    }
+
+    fn decode(mut bytes: &[u8]) -> Result<MathExpression, DecodeError> {
+        MathExpression::read_from(&mut bytes)
+    }
+
+    #[test]
+    fn test_encoded_layout() {
+        assert_eq!(
+            encode(&parse("(1 + 2)")),
+            [
+                vec![FORMAT_VERSION, 5, TAG_OPEN_BRACE, 0, TAG_DIGIT, 1],
+                1.0f64.to_le_bytes().to_vec(),
+                vec![TAG_PLUS, 2, TAG_DIGIT, 2],
+                2.0f64.to_le_bytes().to_vec(),
+                vec![TAG_CLOSE_BRACE, 1],
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn test_corrupted_streams() {
+        let valid = encode(&parse("1 + (2 * 3)"));
+
+        assert_eq!(decode(&[]), Err(DecodeError::Truncated));
+        assert_eq!(
+            decode(&[2, 0]),
+            Err(DecodeError::UnsupportedVersion { version: 2 })
+        );
+        assert_eq!(
+            decode(&[FORMAT_VERSION, 1, 42, 0]),
+            Err(DecodeError::InvalidTag { tag: 42 })
+        );
+        assert_eq!(
+            decode(&[
+                FORMAT_VERSION,
+                0xff,
+                0xff,
+                0xff,
+                0xff,
+                0xff,
+                0xff,
+                0xff,
+                0xff,
+                0xff,
+                0x7f
+            ]),
+            Err(DecodeError::InvalidVarint)
+        );
+
+        for len in 1..valid.len() {
+            assert_eq!(decode(&valid[..len]), Err(DecodeError::Truncated), "{len}");
+        }
+    }
+
+    #[test]
+    fn test_decoding_revalidates_expression() {
+        let operator_first = [FORMAT_VERSION, 1, TAG_MULTIPLY, 0];
+        assert_eq!(
+            decode(&operator_first),
+            Err(MathExpressionParserError::InvalidExpression {
+                idx: Position::new(0, 0),
+                found: Some(Token::Operator('*')),
+                after: None,
+            }
+            .into())
+        );
+
+        let mut unclosed = vec![FORMAT_VERSION, 2, TAG_OPEN_BRACE, 3, TAG_DIGIT, 1];
+        unclosed.extend(1.0f64.to_le_bytes());
+        assert_eq!(
+            decode(&unclosed),
+            Err(MathExpressionParserError::UnclosedBrace {
+                idx: Position::new(3, 3)
+            }
+            .into())
+        );
+
+        let mut far_away = vec![FORMAT_VERSION, 1, TAG_DIGIT];
+        write_varint(&mut far_away, u64::from(u32::MAX) + 1).unwrap();
+        far_away.extend(1.0f64.to_le_bytes());
+        assert_eq!(
+            decode(&far_away).unwrap_err().code(),
+            ErrorCode::ParserPositionOverflow
+        );
+
+        let mut nan = vec![FORMAT_VERSION, 1, TAG_DIGIT, 2];
+        nan.extend(f64::NAN.to_le_bytes());
+        assert_eq!(
+            decode(&nan),
+            Err(MathExpressionParserError::NotANumber {
+                idx: Position::new(2, 2)
+            }
+            .into())
+        );
+    }
+
+    #[test]
+    fn test_empty_stream_is_rejected() {
+        assert_eq!(
+            decode(&[FORMAT_VERSION, 0]),
+            Err(MathExpressionParserError::EmptyExpression.into())
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_smaller_than_json() {
+        let parsed = parse(&vec!["(12.5 + 3) * 4"; 100].join(" - "));
+        let binary = encode(&parsed).len();
+        let json = serde_json::to_vec(&parsed).unwrap().len();
+
+        // One tag byte plus a one byte delta per token, and eight bytes per
+        // literal, against a JSON object per token and a position array.
+        assert_eq!(binary, 2 + 799 * 2 + 300 * 8 + 1);
+        assert!(binary * 2 < json, "{binary} vs {json}");
+    }
+
+    proptest! {
+        #[test]
+        fn test_roundtrip(expr in expression()) {
+            let parsed = parse(&expr);
+            prop_assert_eq!(decode(&encode(&parsed)), Ok(parsed));
+        }
+
+        #[test]
+        fn test_arbitrary_bytes_never_panic(bytes in proptest::collection::vec(any::<u8>(), 0..64)) {
+            if let Ok(decoded) = decode(&bytes) {
+                prop_assert_eq!(decode(&encode(&decoded)), Ok(decoded));
+            }
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use super::script::parse_statement;
+use super::{MathExpression, MathExpressionParser, MathExpressionParserError};
+use crate::math_expression_tokenizer::{Token, scan};
+use crate::position::{Position, Span};
+
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[non_exhaustive]
+pub enum ExtractError {
+    #[error("Selection {}..{} is outside the {len}-byte source", .span.start, .span.end)]
+    OutOfBounds { span: Span, len: usize },
+    #[error("Selection boundary {byte} is inside a character")]
+    NotCharBoundary { byte: usize },
+    #[error("Selection cuts through the token at position {idx}")]
+    SplitsToken { idx: Position },
+    #[error("Selection covers no tokens")]
+    NoTokens,
+    #[error("Selection is not a complete expression: {0}")]
+    Incomplete(#[from] MathExpressionParserError),
+}
+
+// Extracts the tokens of `expr` selected by the byte range `span` of
+// `source`, the input `expr` was parsed from, as an expression of its own.
+// The span is snapped to the tokens it covers, so surrounding whitespace does
+// not matter, and the snapped span is returned with the expression. Token
+// positions and error positions refer to `source`.
+pub fn extract_subexpression(
+    expr: &MathExpression,
+    source: &str,
+    span: Span,
+) -> Result<(MathExpression, Span), ExtractError> {
+    if span.start > span.end || span.end > source.len() {
+        return Err(ExtractError::OutOfBounds {
+            span,
+            len: source.len(),
+        });
+    }
+
+    if let Some(byte) = [span.start, span.end]
+        .into_iter()
+        .find(|&byte| !source.is_char_boundary(byte))
+    {
+        return Err(ExtractError::NotCharBoundary { byte });
+    }
+
+    let mut covered: Option<(Span, &Token)> = None;
+    for (idx, token) in expr.positioned_tokens() {
+        let end = idx + token_len(source, idx, token);
+        if end <= span.start || idx >= span.end {
+            continue;
+        }
+
+        if idx < span.start || end > span.end {
+            return Err(ExtractError::SplitsToken {
+                idx: Position::in_source(source, idx),
+            });
+        }
+
+        match &mut covered {
+            Some((covered, _)) => covered.end = end,
+            None => covered = Some((Span::new(idx, end), token)),
+        }
+    }
+
+    let Some((covered, first)) = covered else {
+        return Err(ExtractError::NoTokens);
+    };
+
+    // A binary operator would parse again as a unary one, e.g. the `+ 2` of
+    // `1 + 2`.
+    if let Token::Operator(_) = first {
+        return Err(MathExpressionParserError::InvalidExpression {
+            idx: Position::in_source(source, covered.start),
+            found: Some(first.clone()),
+            after: None,
+        }
+        .into());
+    }
+
+    let offset = Position::in_source(source, covered.start);
+    let extracted = parse_statement(&MathExpressionParser::new(), offset, covered.slice(source))?;
+
+    Ok((extracted, covered))
+}
+
+pub(super) fn token_len(source: &str, idx: usize, token: &Token) -> usize {
+    match token {
+        Token::Digit(_) => source.get(idx..).map_or(0, scan::literal_len),
+        Token::Operator(_) | Token::UnaryOperator(_) | Token::OpenBrace | Token::CloseBrace => 1,
+    }
+}
+
+// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ops::Range;
+
+    fn extract(source: &str, span: Range<usize>) -> Result<(String, Range<usize>), ExtractError> {
+        let expr: MathExpression = source.parse().unwrap();
+        extract_subexpression(&expr, source, span.into())
+            .map(|(extracted, span)| (extracted.to_string(), span.into()))
+    }
+
+    #[test]
+    fn test_extract_product() {
+        assert_eq!(extract("1+2*3+4", 2..5), Ok(("2 * 3".to_string(), 2..5)));
+        // Whitespace around the selection is dropped.
+        assert_eq!(
+            extract("1 + 2 * 3 + 4", 3..10),
+            Ok(("2 * 3".to_string(), 4..9))
+        );
+    }
+
+    #[test]
+    fn test_extract_keeps_source_positions() {
+        let source = "1 + 2 * 3";
+        let expr: MathExpression = source.parse().unwrap();
+        let (extracted, _) = extract_subexpression(&expr, source, Span::new(4, 9)).unwrap();
+
+        assert_eq!(
+            extracted.positioned_tokens().collect::<Vec<_>>(),
+            expr.positioned_tokens().skip(2).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_extract_group() {
+        assert_eq!(
+            extract("4 * (1 + 2)", 4..11),
+            Ok(("(1 + 2)".to_string(), 4..11))
+        );
+        assert_eq!(
+            extract("4 * -(1 + 2)", 4..12),
+            Ok(("-(1 + 2)".to_string(), 4..12))
+        );
+    }
+
+    #[test]
+    fn test_extract_across_paren_boundary() {
+        let at = |idx| Position::new(idx, idx);
+
+        assert_eq!(
+            extract("(1 + 2) * 3", 1..11),
+            Err(ExtractError::Incomplete(
+                MathExpressionParserError::UnmatchedCloseBrace {
+                    idx: at(6),
+                    last_group: None,
+                }
+            ))
+        );
+        assert_eq!(
+            extract("2 * (3 + 4)", 0..6),
+            Err(ExtractError::Incomplete(
+                MathExpressionParserError::UnclosedBrace { idx: at(4) }
+            ))
+        );
+    }
+
+    #[test]
+    fn test_extract_operator_only() {
+        assert_eq!(
+            extract("1 + 2", 1..4).unwrap_err().to_string(),
+            "Selection is not a complete expression: \
+             Expression cannot start with operator '+' at position 3"
+        );
+        assert_eq!(
+            extract("1 + 2", 2..5).unwrap_err().to_string(),
+            "Selection is not a complete expression: \
+             Expression cannot start with operator '+' at position 3"
+        );
+        assert_eq!(
+            extract("1 * 2", 0..3).unwrap_err().to_string(),
+            "Selection is not a complete expression: \
+             Expression cannot end with operator '*' at position 4"
+        );
+    }
+
+    #[test]
+    fn test_extract_cutting_a_literal() {
+        assert_eq!(
+            extract("12 + 345", 1..8),
+            Err(ExtractError::SplitsToken {
+                idx: Position::new(0, 0)
+            })
+        );
+        assert_eq!(
+            extract("\u{3000}1 + 12.5", 8..11),
+            Err(ExtractError::SplitsToken {
+                idx: Position::new(7, 5)
+            })
+        );
+    }
+
+    #[test]
+    fn test_extract_empty_or_out_of_bounds() {
+        assert_eq!(extract("1 +  2", 3..5), Err(ExtractError::NoTokens));
+        assert_eq!(extract("1 + 2", 2..2), Err(ExtractError::NoTokens));
+        assert_eq!(
+            extract("1 + 2", 3..9),
+            Err(ExtractError::OutOfBounds {
+                span: Span::new(3, 9),
+                len: 5,
+            })
+        );
+    }
+
+    #[test]
+    fn test_extract_at_every_offset_of_multibyte_source() {
+        let source = "1\u{3000}+\u{3000}(23)";
+        for start in 0..=source.len() {
+            for end in start..=source.len() {
+                let result = extract(source, start..end);
+                let on_boundaries = source.is_char_boundary(start) && source.is_char_boundary(end);
+                assert_eq!(
+                    !on_boundaries,
+                    matches!(result, Err(ExtractError::NotCharBoundary { .. })),
+                    "{start}..{end}: {result:?}"
+                );
+            }
+        }
+
+        assert_eq!(
+            extract(source, 0..2),
+            Err(ExtractError::NotCharBoundary { byte: 2 })
+        );
+        assert_eq!(extract(source, 0..4), Ok(("1".to_string(), 0..1)));
+    }
+}
@@ -0,0 +1,203 @@
+use super::MathExpression;
+use super::extract::token_len;
+use crate::math_expression_tokenizer::{Token, scan};
+use crate::position::Span;
+
+// A change to the source an expression was parsed from: the bytes in `span`
+// are replaced with `replacement`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextEdit<'a> {
+    pub span: Span,
+    pub replacement: &'a str,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+// With `smallvec` the expression keeps short token lists inline, which is
+// what makes it large; boxing it would undo that.
+#[cfg_attr(feature = "smallvec", allow(clippy::large_enum_variant))]
+pub enum EditOutcome {
+    // The expression of the edited source, with positions shifted.
+    Patched(MathExpression),
+    // The edit may change the tokens, so the edited source has to be parsed.
+    Reparse,
+}
+
+impl MathExpression {
+    // Updates the expression for an edit of `source`, the input it was parsed
+    // from, without parsing again when the edit only changes whitespace
+    // between tokens or the digits of a single literal. The result is what
+    // parsing the edited source would return; anything else is `Reparse`.
+    pub fn apply_edit(&self, source: &str, edit: TextEdit) -> EditOutcome {
+        let TextEdit { span, replacement } = edit;
+        if span.start > span.end
+            || !source.is_char_boundary(span.start)
+            || !source.is_char_boundary(span.end)
+        {
+            return EditOutcome::Reparse;
+        }
+
+        let edited = [&source[..span.start], replacement, &source[span.end..]].concat();
+        let spans: Vec<_> = self
+            .positioned_tokens()
+            .map(|(idx, token)| Span::new(idx, idx + token_len(source, idx, token)))
+            .collect();
+
+        // An empty replacement is a deletion of either.
+        let mut patched = None;
+        if replacement.chars().all(char::is_whitespace) {
+            patched = self.patch_whitespace(&spans, span, replacement.len());
+        }
+        if patched.is_none()
+            && replacement
+                .bytes()
+                .all(|byte| byte.is_ascii_digit() || byte == b'.')
+        {
+            patched = self.patch_literal(&spans, span, &edited, replacement.len());
+        }
+
+        patched.map_or(EditOutcome::Reparse, EditOutcome::Patched)
+    }
+
+    fn patch_whitespace(
+        &self,
+        spans: &[Span],
+        edit: Span,
+        inserted: usize,
+    ) -> Option<MathExpression> {
+        let touches = |token: &Span| {
+            if edit.is_empty() {
+                token.start < edit.start && edit.start < token.end
+            } else {
+                token.start < edit.end && edit.start < token.end
+            }
+        };
+        if spans.iter().any(touches) {
+            return None;
+        }
+
+        // Literals are always separated by an operator, so removing spaces
+        // never merges two tokens.
+        let after = spans.partition_point(|token| token.end <= edit.start);
+        self.shifted(spans, after, edit, inserted)
+    }
+
+    fn patch_literal(
+        &self,
+        spans: &[Span],
+        edit: Span,
+        edited: &str,
+        inserted: usize,
+    ) -> Option<MathExpression> {
+        let literal = spans.iter().zip(&self.tokens).position(|(span, token)| {
+            matches!(token, Token::Digit(_)) && span.start <= edit.start && edit.end <= span.end
+        })?;
+
+        let start = spans[literal].start;
+        let end = (spans[literal].end + inserted).checked_sub(edit.len())?;
+        let text = &edited[start..end];
+        if text.is_empty() || scan::literal_len(&edited[start..]) != text.len() {
+            return None;
+        }
+
+        let mut patched = self.shifted(spans, literal + 1, edit, inserted)?;
+        patched.tokens[literal] = Token::Digit(scan::parse_number(text)?);
+        Some(patched)
+    }
+
+    // Copy with the positions of the tokens from `first` on moved by the
+    // change in length, or `None` if a position no longer fits.
+    fn shifted(
+        &self,
+        spans: &[Span],
+        first: usize,
+        edit: Span,
+        inserted: usize,
+    ) -> Option<MathExpression> {
+        let mut shifted = self.clone();
+        for (position, span) in shifted.positions[first..].iter_mut().zip(&spans[first..]) {
+            let moved = (span.start + inserted).checked_sub(edit.len())?;
+            *position = u32::try_from(moved).ok()?;
+        }
+
+        Some(shifted)
+    }
+}
+
+// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math_expression_parser::tests::expression;
+    use proptest::prelude::*;
+
+    fn edit(source: &str, start: usize, end: usize, replacement: &str) -> EditOutcome {
+        let expr: MathExpression = source.parse().unwrap();
+        expr.apply_edit(
+            source,
+            TextEdit {
+                span: Span::new(start, end),
+                replacement,
+            },
+        )
+    }
+
+    fn patched(source: &str) -> EditOutcome {
+        EditOutcome::Patched(source.parse().unwrap())
+    }
+
+    #[test]
+    fn test_whitespace_edits() {
+        assert_eq!(edit("1 + 2", 1, 2, "   "), patched("1   + 2"));
+        assert_eq!(edit("1 + 2", 3, 4, ""), patched("1 +2"));
+        assert_eq!(edit("(1)*2", 0, 0, "\t"), patched("\t(1)*2"));
+        assert_eq!(edit("1 + 2", 5, 5, " "), patched("1 + 2 "));
+        // A space inside a literal splits it.
+        assert_eq!(edit("12 + 3", 1, 1, " "), EditOutcome::Reparse);
+    }
+
+    #[test]
+    fn test_literal_edits() {
+        assert_eq!(edit("12 + 3", 1, 1, "5"), patched("152 + 3"));
+        assert_eq!(edit("12 + 3", 0, 2, "7.25"), patched("7.25 + 3"));
+        assert_eq!(edit("1+2", 1, 1, "0"), patched("10+2"));
+        assert_eq!(edit("1.5 * 2", 1, 3, ""), patched("1 * 2"));
+        assert_eq!(edit("1e5 + 3", 2, 3, "12"), patched("1e12 + 3"));
+        assert_eq!(edit("2.5E-3 * 2", 5, 6, "40"), patched("2.5E-40 * 2"));
+        // A second point or an empty literal does not parse as before.
+        assert_eq!(edit("1.5 * 2", 3, 3, "."), EditOutcome::Reparse);
+        assert_eq!(edit("1 + 23", 4, 6, ""), EditOutcome::Reparse);
+        assert_eq!(edit("1e5 + 3", 2, 3, ""), EditOutcome::Reparse);
+        // Edits of operators, or across tokens, always reparse.
+        assert_eq!(edit("1 + 2", 2, 3, "-"), EditOutcome::Reparse);
+        assert_eq!(edit("1 + 2", 0, 5, "3"), EditOutcome::Reparse);
+    }
+
+    #[test]
+    fn test_edit_out_of_bounds() {
+        assert_eq!(edit("1 + 2", 4, 9, ""), EditOutcome::Reparse);
+        assert_eq!(edit("1 + 2", 3, 2, ""), EditOutcome::Reparse);
+    }
+
+    proptest! {
+        #[test]
+        fn test_patch_matches_reparse(
+            source in expression(),
+            start in any::<prop::sample::Index>(),
+            removed in 0usize..3,
+            replacement in prop_oneof!["[0-9.]{0,2}", " {0,2}", "[+*/()-]"],
+        ) {
+            let start = start.index(source.len() + 1);
+            let end = (start + removed).min(source.len());
+            let expr: MathExpression = source.parse().unwrap();
+            let outcome = expr.apply_edit(
+                &source,
+                TextEdit { span: Span::new(start, end), replacement: &replacement },
+            );
+
+            if let EditOutcome::Patched(patched) = outcome {
+                let edited = [&source[..start], &replacement, &source[end..]].concat();
+                prop_assert_eq!(Ok(patched), edited.parse::<MathExpression>());
+            }
+        }
+    }
+}
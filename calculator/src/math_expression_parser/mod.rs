@@ -1,93 +1,1932 @@
-use crate::math_expression_tokenizer::{MathExpressionTokenizerError, Token, TokenizerTraits};
+mod arena;
+mod ast;
+mod builder;
+mod canonical;
+#[cfg(test)]
+mod differential;
+mod edit;
+mod embedded;
+pub mod encoding;
+mod extract;
+mod incremental;
+mod options;
+pub(crate) mod precedence;
+mod script;
+mod warnings;
+
+pub use arena::{ArenaExpr, ExprArena, ExprId};
+pub(crate) use ast::fold_tree;
+pub use ast::{Expr, ExprNode, TreeNode};
+pub use builder::ExpressionBuilder;
+pub use edit::{EditFailure, TokenEdit};
+pub use embedded::find_expressions;
+pub use extract::{ExtractError, extract_subexpression};
+pub use incremental::{EditOutcome, TextEdit};
+pub use options::ParserOptions;
+#[cfg(feature = "rayon")]
+pub use script::parse_script_parallel;
+pub use script::{ScriptResult, parse_all};
+pub use warnings::{ParseWarning, WarningKind};
+
+use crate::cancellation::CancellationToken;
+use crate::diagnostic::{Diagnostic, Label, SourcedError};
+use crate::error_code::ErrorCode;
+use crate::math_expression_tokenizer::{
+    MathExpressionTokenizer, MathExpressionTokenizerError, OPERATORS, Token, TokenizerTraits,
+    suggest,
+};
+use crate::position::{Position, Span};
+
+use std::fmt;
+use std::num::TryFromIntError;
+use std::str::FromStr;
+use std::time::Instant;
+
+#[cfg(feature = "smallvec")]
+use smallvec::SmallVec;
 
 use thiserror::Error;
 
-#[derive(Debug, Error)]
+// Most expressions are short, so with the `smallvec` feature tokens and open
+// brace positions are stored inline up to a small size before spilling.
+#[cfg(feature = "smallvec")]
+type TokenStorage = SmallVec<[Token; 16]>;
+#[cfg(not(feature = "smallvec"))]
+type TokenStorage = Vec<Token>;
+
+#[cfg(feature = "smallvec")]
+type PositionStorage = SmallVec<[u32; 16]>;
+#[cfg(not(feature = "smallvec"))]
+type PositionStorage = Vec<u32>;
+
+#[cfg(feature = "smallvec")]
+pub(crate) type BraceStack = SmallVec<[usize; 8]>;
+#[cfg(not(feature = "smallvec"))]
+pub(crate) type BraceStack = Vec<usize>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[non_exhaustive]
 pub enum MathExpressionParserError {
     #[error("Tokenizer error: {0}")]
     Tokenizer(#[from] MathExpressionTokenizerError),
-    #[error("Invalid expression by index '{idx}'")]
-    InvalidExpression { idx: usize },
-    #[error("Invalid braces consequence '{idx}'")]
-    InvalidBraceConsequence { idx: usize },
+    // `found` is the rejected token (`None` at the end of input) and `after`
+    // the token before it (`None` at the start of input).
+    #[error("{} at position {idx}", describe_invalid_expression(.found, .after))]
+    InvalidExpression {
+        idx: Position,
+        found: Option<Token>,
+        after: Option<Token>,
+    },
+    // `idx` is the earliest '(' that is never closed.
+    #[error("Unclosed brace at position {idx}")]
+    UnclosedBrace { idx: Position },
+    // `last_group` is the '(' of the most recently closed group: a missing
+    // open brace placed there would match this ')'.
+    #[error("Unmatched closing brace at position {idx}")]
+    UnmatchedCloseBrace {
+        idx: Position,
+        last_group: Option<Position>,
+    },
+    #[error("Parsing cancelled at position {idx}")]
+    Cancelled { idx: Position },
+    #[error("Parsing deadline exceeded at position {idx} after {consumed_tokens} tokens")]
+    DeadlineExceeded {
+        idx: Position,
+        consumed_tokens: usize,
+    },
+    #[error("Token at position {idx} is too far into the input for a 32-bit offset")]
+    PositionOverflow { idx: Position },
+    // Only possible for tokens that were not tokenized, e.g. decoded ones.
+    #[error("Digit at position {idx} is NaN")]
+    NotANumber { idx: Position },
+    #[error("Expression has no tokens")]
+    EmptyExpression,
+}
+
+impl MathExpressionParserError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            MathExpressionParserError::Tokenizer(err) => err.code(),
+            MathExpressionParserError::InvalidExpression { .. } => {
+                ErrorCode::ParserInvalidExpression
+            }
+            MathExpressionParserError::UnclosedBrace { .. } => ErrorCode::ParserUnclosedBrace,
+            MathExpressionParserError::UnmatchedCloseBrace { .. } => {
+                ErrorCode::ParserUnmatchedCloseBrace
+            }
+            MathExpressionParserError::Cancelled { .. } => ErrorCode::ParserCancelled,
+            MathExpressionParserError::DeadlineExceeded { .. } => ErrorCode::ParserDeadlineExceeded,
+            MathExpressionParserError::PositionOverflow { .. } => ErrorCode::ParserPositionOverflow,
+            MathExpressionParserError::NotANumber { .. } => ErrorCode::ParserNotANumber,
+            MathExpressionParserError::EmptyExpression => ErrorCode::ParserEmptyExpression,
+        }
+    }
+
+    // Position in the input the error refers to, if any.
+    pub fn idx(&self) -> Option<Position> {
+        match self {
+            MathExpressionParserError::Tokenizer(err) => err.idx(),
+            MathExpressionParserError::InvalidExpression { idx, .. }
+            | MathExpressionParserError::UnclosedBrace { idx }
+            | MathExpressionParserError::UnmatchedCloseBrace { idx, .. }
+            | MathExpressionParserError::Cancelled { idx }
+            | MathExpressionParserError::DeadlineExceeded { idx, .. }
+            | MathExpressionParserError::PositionOverflow { idx }
+            | MathExpressionParserError::NotANumber { idx } => Some(*idx),
+            MathExpressionParserError::EmptyExpression => None,
+        }
+    }
+
+    pub fn with_source(self, source: &str) -> SourcedError {
+        SourcedError::new(self, source)
+    }
+
+    pub fn to_diagnostic(&self, source: &str) -> Diagnostic {
+        let (labels, help) = match self {
+            MathExpressionParserError::Tokenizer(err) => return err.to_diagnostic(source),
+            MathExpressionParserError::InvalidExpression {
+                idx, found: None, ..
+            } => (
+                vec![Label::at_char(source, idx.byte, "unexpected end of input")],
+                None,
+            ),
+            MathExpressionParserError::InvalidExpression { idx, .. } => (
+                vec![Label::at_char(source, idx.byte, "unexpected token")],
+                None,
+            ),
+            MathExpressionParserError::UnclosedBrace { idx } => (
+                vec![
+                    Label::at_char(source, idx.byte, "this '(' is never closed"),
+                    Label::at_char(source, source.len(), "expected ')' before the end of input"),
+                ],
+                Some("add ')' to close the group".to_string()),
+            ),
+            MathExpressionParserError::UnmatchedCloseBrace { idx, last_group } => {
+                let mut labels = vec![Label::at_char(source, idx.byte, "unmatched ')'")];
+                if let Some(group) = last_group {
+                    labels.push(Label::at_char(
+                        source,
+                        group.byte,
+                        "the most recently closed group starts here",
+                    ));
+                }
+                (
+                    labels,
+                    Some("remove this ')' or add a matching '('".to_string()),
+                )
+            }
+            MathExpressionParserError::Cancelled { idx } => (
+                vec![Label::at_char(source, idx.byte, "parsing stopped here")],
+                None,
+            ),
+            MathExpressionParserError::DeadlineExceeded { idx, .. } => (
+                vec![Label::at_char(source, idx.byte, "parsing stopped here")],
+                Some("allow more time or shorten the input".to_string()),
+            ),
+            MathExpressionParserError::NotANumber { idx } => (
+                vec![Label::at_char(source, idx.byte, "this digit is NaN")],
+                Some("replace it with a number".to_string()),
+            ),
+            MathExpressionParserError::PositionOverflow { .. } => (
+                vec![],
+                Some("split the input into expressions smaller than 4 GiB".to_string()),
+            ),
+            MathExpressionParserError::EmptyExpression => {
+                (vec![], Some("provide a non-empty expression".to_string()))
+            }
+        };
+
+        Diagnostic {
+            code: self.code(),
+            message: self.to_string(),
+            labels,
+            help,
+        }
+    }
 }
 
+fn describe_token(token: &Token) -> String {
+    match token {
+        Token::Digit(number) => format!("number '{number}'"),
+        Token::Operator(op) => format!("operator '{op}'"),
+        Token::UnaryOperator(op) => format!("unary operator '{op}'"),
+        Token::OpenBrace => "'('".to_string(),
+        Token::CloseBrace => "')'".to_string(),
+    }
+}
+
+fn describe_invalid_expression(found: &Option<Token>, after: &Option<Token>) -> String {
+    match (found, after) {
+        (Some(found), Some(after)) => {
+            let mut message = format!(
+                "{} cannot follow {}",
+                describe_token(found),
+                describe_token(after)
+            );
+            message[..1].make_ascii_uppercase();
+            message
+        }
+        (Some(found), None) => format!("Expression cannot start with {}", describe_token(found)),
+        (None, Some(after)) => format!("Expression cannot end with {}", describe_token(after)),
+        (None, None) => "Invalid expression".to_string(),
+    }
+}
+
+// A parser error together with how far parsing got: the number of tokens that
+// were accepted before the failure and the tokenizer position where it stopped.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{error} (after {tokens_parsed} tokens, stopped at {stopped_at})")]
+pub struct ParseFailure {
+    #[source]
+    pub error: MathExpressionParserError,
+    pub tokens_parsed: usize,
+    pub stopped_at: Position,
+}
+
+// Parsed expressions, the parser and its errors are all `Send + Sync`, so they
+// can be shared between threads (e.g. in a global cache).
+// Tokens and their byte positions are kept in parallel arrays, with positions
+// narrowed to `u32`, which keeps large expressions compact. A parsed
+// expression always has at least one token.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct MathExpression {
-    pub expression: Vec<Token>,
+    tokens: TokenStorage,
+    positions: PositionStorage,
+}
+
+impl MathExpression {
+    fn empty() -> Self {
+        Self {
+            tokens: TokenStorage::new(),
+            positions: PositionStorage::new(),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.tokens.clear();
+        self.positions.clear();
+    }
+
+    fn push(&mut self, idx: usize, token: Token) -> Result<(), TryFromIntError> {
+        let position = u32::try_from(idx)?;
+
+        #[cfg(feature = "profiling")]
+        let capacity = self.tokens.capacity();
+
+        self.tokens.push(token);
+        self.positions.push(position);
+
+        profile!(stats => stats.token_reallocations += u64::from(self.tokens.capacity() != capacity));
+        Ok(())
+    }
+
+    fn truncate(&mut self, len: usize) {
+        self.tokens.truncate(len);
+        self.positions.truncate(len);
+    }
+
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    pub fn positioned_tokens(&self) -> impl ExactSizeIterator<Item = (usize, &Token)> {
+        self.positions
+            .iter()
+            .map(|&position| position as usize)
+            .zip(self.tokens.iter())
+    }
+
+    // Hash of the token sequence alone, so spacing does not matter. Digits are
+    // hashed by their bits. The value is FNV-1a over a fixed byte encoding of
+    // the tokens, so unlike `Hash` with `RandomState` it is stable across runs
+    // and platforms and can be stored.
+    pub fn content_hash(&self) -> u64 {
+        let mut hash = FNV_OFFSET_BASIS;
+        for token in &self.tokens {
+            let (tag, payload) = match token {
+                Token::Digit(number) => (0, number.to_bits()),
+                Token::Operator(op) => (1, u64::from(*op)),
+                Token::UnaryOperator(op) => (2, u64::from(*op)),
+                Token::OpenBrace => (3, 0),
+                Token::CloseBrace => (4, 0),
+            };
+
+            hash = fnv1a(hash, &[tag]);
+            hash = fnv1a(hash, &payload.to_le_bytes());
+        }
+
+        hash
+    }
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for &byte in bytes {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+// Prints the canonical form of the expression: binary operators surrounded by
+// single spaces, unary operators and braces attached to their operand. Parsing
+// the printed form yields the same tokens again.
+impl fmt::Display for MathExpression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for token in &self.tokens {
+            match token {
+                Token::Operator(op) => write!(f, " {op} ")?,
+                token => write!(f, "{token}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Tokenizes and parses `s` with the default parser and tokenizer settings.
+///
+/// ```
+/// use calculator::{MathExpression, Token};
+///
+/// let expr: MathExpression = "1 + 2".parse().unwrap();
+/// assert_eq!(
+///     expr.tokens(),
+///     [Token::Digit(1.0), Token::Operator('+'), Token::Digit(2.0)]
+/// );
+/// assert_eq!(expr.to_string(), "1 + 2");
+///
+/// assert!("1 +".parse::<MathExpression>().is_err());
+/// ```
+impl FromStr for MathExpression {
+    type Err = MathExpressionParserError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokenizer = MathExpressionTokenizer::new(s.to_string())?;
+        MathExpressionParser::new().parse(tokenizer)
+    }
+}
+
+impl TryFrom<&str> for MathExpression {
+    type Error = MathExpressionParserError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+// Adjacency rules shared by the parser and `ExpressionBuilder`.
+
+fn ends_operand(tokens: &[Token]) -> bool {
+    tokens
+        .last()
+        .is_some_and(|last| matches!(last, Token::Digit(_) | Token::CloseBrace))
+}
+
+// Operators that are unary where an operand is expected.
+pub(crate) const UNARY_OPERATORS: [char; 2] = ['+', '-'];
+
+// A '+' or '-' where an operand is expected is unary. Unary operators may
+// repeat, so "- -5" is a double negation.
+fn classify(token: Token, ends_operand: bool) -> Token {
+    match token {
+        Token::Operator(op) | Token::UnaryOperator(op)
+            if !ends_operand && UNARY_OPERATORS.contains(&op) =>
+        {
+            Token::UnaryOperator(op)
+        }
+        Token::UnaryOperator(op) => Token::Operator(op),
+        token => token,
+    }
+}
+
+// Brace balance is checked separately.
+fn may_follow(token: &Token, ends_operand: bool) -> bool {
+    match token {
+        Token::OpenBrace | Token::Digit(_) | Token::UnaryOperator(_) => !ends_operand,
+        Token::CloseBrace | Token::Operator(_) => ends_operand,
+    }
+}
+
+fn can_end_with(token: &Token) -> bool {
+    matches!(token, Token::Digit(_) | Token::CloseBrace)
+}
+
+// A token read by `next_checked`.
+pub(crate) enum Next {
+    Take(usize, Token),
+    // The token at this byte offset cannot continue a complete expression.
+    StopAt(usize),
+}
+
+// Reads the next token and checks it against `last`, the token before it,
+// keeping track of the open braces and of where the last closed group
+// started. With `stop_early` a token that cannot continue a complete
+// expression ends it instead of failing.
+pub(crate) fn next_checked<Tokenizer: TokenizerTraits + ?Sized>(
+    tokenizer: &mut Tokenizer,
+    last: Option<&Token>,
+    braces: &mut BraceStack,
+    last_group: &mut Option<usize>,
+    stop_early: bool,
+) -> Result<Next, MathExpressionParserError> {
+    let ends_operand = last.is_some_and(can_end_with);
+    // Stopping is only possible where the expression could end.
+    let can_stop = stop_early && braces.is_empty() && ends_operand;
+
+    let (idx, token) = match tokenizer.next_token() {
+        Ok(next) => next,
+        Err(MathExpressionTokenizerError::InvalidToken { idx, .. }) if can_stop => {
+            return Ok(Next::StopAt(idx.byte));
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    let read = token.clone();
+    let token = classify(token, ends_operand);
+    // A token that is not taken goes back to the tokenizer, so a caller that
+    // lent it is left at that token.
+    let reject = |tokenizer: &mut Tokenizer, err: MathExpressionParserError| {
+        tokenizer.put_back(idx, read.clone());
+        if can_stop {
+            Ok(Next::StopAt(idx))
+        } else {
+            Err(err)
+        }
+    };
+
+    // Other tokenizers may send any char as an operator. Unknown unary
+    // ones are classified as binary above, so this catches both.
+    if let Token::Operator(op) = token
+        && !OPERATORS.contains(&op)
+    {
+        let err = MathExpressionTokenizerError::InvalidToken {
+            idx: tokenizer.position_of(idx),
+            ch: op,
+            suggestion: suggest(op, None, None),
+        };
+        return reject(tokenizer, err.into());
+    }
+
+    // The check of `Token::digit`, for tokens that bypassed it. This is an
+    // error even where the expression could stop.
+    if let Token::Digit(number) = token
+        && Token::digit(number).is_err()
+    {
+        tokenizer.put_back(idx, read);
+        return Err(MathExpressionParserError::NotANumber {
+            idx: tokenizer.position_of(idx),
+        });
+    }
+
+    if token == Token::CloseBrace && braces.is_empty() {
+        let err = MathExpressionParserError::UnmatchedCloseBrace {
+            idx: tokenizer.position_of(idx),
+            last_group: last_group.map(|group| tokenizer.position_of(group)),
+        };
+        return reject(tokenizer, err);
+    }
+
+    if !may_follow(&token, ends_operand) {
+        let err = MathExpressionParserError::InvalidExpression {
+            idx: tokenizer.position_of(idx),
+            found: Some(token),
+            after: last.cloned(),
+        };
+        return reject(tokenizer, err);
+    }
+
+    match token {
+        Token::OpenBrace => braces.push(idx),
+        Token::CloseBrace => *last_group = braces.pop(),
+        Token::Digit(_) | Token::Operator(_) | Token::UnaryOperator(_) => {}
+    }
+
+    Ok(Next::Take(idx, token))
 }
 
-pub struct MathExpressionParser;
+// Checks that the input ended where an expression can end, given the last
+// token and the braces left open. Open braces are only allowed with
+// `close_braces`, for the caller to close.
+pub(crate) fn check_end<Tokenizer: TokenizerTraits + ?Sized>(
+    tokenizer: &Tokenizer,
+    last: Option<&Token>,
+    braces: &BraceStack,
+    close_braces: bool,
+) -> Result<(), MathExpressionParserError> {
+    let Some(last) = last else {
+        return Err(MathExpressionParserError::EmptyExpression);
+    };
+
+    if !can_end_with(last) {
+        return Err(MathExpressionParserError::InvalidExpression {
+            idx: tokenizer.position_of(tokenizer.curr_index()),
+            found: None,
+            after: Some(last.clone()),
+        });
+    }
+
+    match braces.first() {
+        Some(&first) if !close_braces => Err(MathExpressionParserError::UnclosedBrace {
+            idx: tokenizer.position_of(first),
+        }),
+        _ => Ok(()),
+    }
+}
+
+// Number of tokens parsed between two checks of the cancellation token and
+// the deadline.
+pub const CANCELLATION_CHECK_INTERVAL: usize = 64;
+
+#[derive(Debug, Clone)]
+pub struct MathExpressionParser {
+    cancellation: Option<CancellationToken>,
+    deadline: Option<Instant>,
+    options: ParserOptions,
+    // Scratch buffers reused by `parse_into`; always cleared before use.
+    scratch: MathExpression,
+    braces: BraceStack,
+}
+
+impl Default for MathExpressionParser {
+    fn default() -> Self {
+        Self {
+            cancellation: None,
+            deadline: None,
+            options: ParserOptions::default(),
+            scratch: MathExpression::empty(),
+            braces: BraceStack::new(),
+        }
+    }
+}
 
 impl MathExpressionParser {
     pub fn new() -> Self {
-        Self {}
+        Self::default()
+    }
+
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    // Stops parsing with `DeadlineExceeded` once `deadline` has passed. Parsing
+    // is linear in the input, so this only matters for slow tokenizers (e.g.
+    // reading from a channel) or huge inputs; like cancellation it is checked
+    // every `CANCELLATION_CHECK_INTERVAL` tokens.
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
     }
 
+    pub fn with_options(mut self, options: ParserOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    // A tokenizer lent as `&mut` is left at the token parsing failed on, if
+    // it can `put_back` tokens, e.g. `MathExpressionTokenizer` or a
+    // `PeekableTokenizer` around any other.
     pub fn parse<Tokenizer: TokenizerTraits>(
         &self,
         mut tokenizer: Tokenizer,
     ) -> Result<MathExpression, MathExpressionParserError> {
-        let mut parsed_expression = MathExpression { expression: vec![] };
-        let mut braces = vec![];
+        self.parse_tokens(&mut tokenizer)
+    }
 
-        while let Ok((idx, token)) = tokenizer.next_token() {
-            match token {
-                Token::OpenBrace => {
-                    braces.push(idx);
-                    if let Some(last_token) = parsed_expression.expression.last() {
-                        if !matches!(last_token, Token::Operator(_) | Token::OpenBrace) {
-                            return Err(MathExpressionParserError::InvalidExpression { idx });
-                        };
-                    }
-                }
-                Token::CloseBrace => {
-                    if braces.pop().is_none() {
-                        return Err(MathExpressionParserError::InvalidExpression { idx });
-                    }
-
-                    let Some(last_token) = parsed_expression.expression.last() else {
-                        return Err(MathExpressionParserError::InvalidExpression { idx });
-                    };
-
-                    if !matches!(last_token, Token::Digit(_) | Token::CloseBrace) {
-                        return Err(MathExpressionParserError::InvalidExpression { idx });
-                    }
+    // Same as `parse`, reporting the progress made before a failure.
+    pub fn parse_detailed<Tokenizer: TokenizerTraits>(
+        &self,
+        mut tokenizer: Tokenizer,
+    ) -> Result<MathExpression, ParseFailure> {
+        let mut parsed_expression = MathExpression::empty();
+        match self.parse_tokens_into(
+            &mut tokenizer,
+            &mut parsed_expression,
+            &mut BraceStack::new(),
+            &mut None,
+            false,
+        ) {
+            Ok(_) => Ok(parsed_expression),
+            Err(error) => Err(ParseFailure {
+                error,
+                tokens_parsed: parsed_expression.tokens.len(),
+                stopped_at: tokenizer.position_of(tokenizer.curr_index()),
+            }),
+        }
+    }
+
+    pub fn parse_dyn(
+        &self,
+        tokenizer: &mut dyn TokenizerTraits,
+    ) -> Result<MathExpression, MathExpressionParserError> {
+        self.parse_tokens(tokenizer)
+    }
+
+    // Parses into `out`, reusing the allocations of both `out` and the parser's
+    // internal buffers. On error `out` is left unchanged.
+    pub fn parse_into<Tokenizer: TokenizerTraits>(
+        &mut self,
+        mut tokenizer: Tokenizer,
+        out: &mut MathExpression,
+    ) -> Result<(), MathExpressionParserError> {
+        let mut scratch = std::mem::replace(&mut self.scratch, MathExpression::empty());
+        let mut braces = std::mem::take(&mut self.braces);
+        scratch.clear();
+        braces.clear();
+
+        let result = self
+            .parse_tokens_into(&mut tokenizer, &mut scratch, &mut braces, &mut None, false)
+            .map(|_| ());
+        if result.is_ok() {
+            std::mem::swap(&mut scratch, out);
+        }
+
+        self.scratch = scratch;
+        self.braces = braces;
+        result
+    }
+
+    fn parse_tokens<Tokenizer: TokenizerTraits + ?Sized>(
+        &self,
+        tokenizer: &mut Tokenizer,
+    ) -> Result<MathExpression, MathExpressionParserError> {
+        let mut parsed_expression = MathExpression::empty();
+        self.parse_tokens_into(
+            tokenizer,
+            &mut parsed_expression,
+            &mut BraceStack::new(),
+            &mut None,
+            false,
+        )?;
+        Ok(parsed_expression)
+    }
+
+    // Same as `parse`, also returning the byte offsets of the braces that were
+    // closed automatically under `ParserOptions::auto_close_parens`, in the
+    // order they were closed.
+    pub fn parse_lenient<Tokenizer: TokenizerTraits>(
+        &self,
+        mut tokenizer: Tokenizer,
+    ) -> Result<(MathExpression, Vec<usize>), MathExpressionParserError> {
+        let mut parsed_expression = MathExpression::empty();
+        let mut braces = BraceStack::new();
+        self.parse_tokens_into(
+            &mut tokenizer,
+            &mut parsed_expression,
+            &mut braces,
+            &mut None,
+            false,
+        )?;
+
+        Ok((parsed_expression, braces.into_iter().rev().collect()))
+    }
+
+    // Parses the leading complete expression and returns it with the byte
+    // offset where parsing stopped: either the start of the first token that
+    // cannot continue it (a second operand, a character the tokenizer rejects
+    // or a stray ')' closing a construct of the host language), or the
+    // tokenizer index at the end of input. A token is put back into the
+    // tokenizer, so one that supports `put_back` goes on from it; a character
+    // the tokenizer rejected has been skipped, so in general the rest of the
+    // input is resumed from `source[offset..]`. A token that cannot continue
+    // an incomplete expression (e.g. `1 + *`) is still an error, and is put
+    // back the same way.
+    pub fn parse_partial<Tokenizer: TokenizerTraits + ?Sized>(
+        &self,
+        tokenizer: &mut Tokenizer,
+    ) -> Result<(MathExpression, usize), MathExpressionParserError> {
+        let mut parsed_expression = MathExpression::empty();
+        let stopped_at = self.parse_tokens_into(
+            tokenizer,
+            &mut parsed_expression,
+            &mut BraceStack::new(),
+            &mut None,
+            true,
+        )?;
+
+        Ok((
+            parsed_expression,
+            stopped_at.unwrap_or_else(|| tokenizer.curr_index()),
+        ))
+    }
+
+    // Returns the position of the token parsing stopped at, which only happens
+    // when `stop_early` is set and the tokens so far form a complete expression.
+    // On success `braces` holds the braces that were closed automatically and
+    // `dropped_tail` the input dropped under `ParserOptions::allow_incomplete`.
+    fn parse_tokens_into<Tokenizer: TokenizerTraits + ?Sized>(
+        &self,
+        tokenizer: &mut Tokenizer,
+        parsed_expression: &mut MathExpression,
+        braces: &mut BraceStack,
+        dropped_tail: &mut Option<Span>,
+        stop_early: bool,
+    ) -> Result<Option<usize>, MathExpressionParserError> {
+        let mut last_group = None;
+
+        while tokenizer.has_token() {
+            let consumed_tokens = parsed_expression.tokens.len();
+            if consumed_tokens.is_multiple_of(CANCELLATION_CHECK_INTERVAL) {
+                if let Some(cancellation) = &self.cancellation
+                    && cancellation.is_cancelled()
+                {
+                    return Err(MathExpressionParserError::Cancelled {
+                        idx: tokenizer.position_of(tokenizer.curr_index()),
+                    });
                 }
-                Token::Digit(_) => {
-                    if let Some(last_token) = parsed_expression.expression.last() {
-                        if !matches!(last_token, Token::Operator(_) | Token::OpenBrace) {
-                            return Err(MathExpressionParserError::InvalidExpression { idx });
-                        };
-                    }
+
+                if let Some(deadline) = self.deadline
+                    && Instant::now() >= deadline
+                {
+                    return Err(MathExpressionParserError::DeadlineExceeded {
+                        idx: tokenizer.position_of(tokenizer.curr_index()),
+                        consumed_tokens,
+                    });
                 }
-                Token::Operator(_) => {
-                    let Some(last_token) = parsed_expression.expression.last() else {
-                        return Err(MathExpressionParserError::InvalidExpression { idx });
-                    };
-
-                    if !matches!(last_token, Token::Digit(_) | Token::CloseBrace) {
-                        return Err(MathExpressionParserError::InvalidExpression { idx });
-                    }
+            }
+
+            let (idx, token) = match next_checked(
+                tokenizer,
+                parsed_expression.tokens.last(),
+                braces,
+                &mut last_group,
+                stop_early,
+            )? {
+                Next::Take(idx, token) => (idx, token),
+                Next::StopAt(idx) => return Ok(Some(idx)),
+            };
+
+            parsed_expression.push(idx, token).map_err(|_| {
+                MathExpressionParserError::PositionOverflow {
+                    idx: tokenizer.position_of(idx),
                 }
+            })?;
+        }
+
+        if self.options.allow_incomplete
+            && let Some(complete) = parsed_expression.tokens.iter().rposition(can_end_with)
+        {
+            // The dropped tail holds no ')', so every '(' in it is still open.
+            let cut = parsed_expression.positions[complete] as usize;
+            while braces.last().is_some_and(|&open| open > cut) {
+                braces.pop();
+            }
+            if let Some(&start) = parsed_expression.positions.get(complete + 1) {
+                *dropped_tail = Some(Span::new(start as usize, tokenizer.curr_index()));
             }
+            parsed_expression.truncate(complete + 1);
+        }
+
+        check_end(
+            tokenizer,
+            parsed_expression.tokens.last(),
+            braces,
+            self.options.auto_close_parens,
+        )?;
+
+        // Only left open under `ParserOptions::auto_close_parens`.
+        let end = tokenizer.curr_index();
+        for _ in 0..braces.len() {
+            parsed_expression
+                .push(end, Token::CloseBrace)
+                .map_err(|_| MathExpressionParserError::PositionOverflow {
+                    idx: tokenizer.position_of(end),
+                })?;
+        }
+
+        Ok(None)
+    }
+}
+
+// Unit tests
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::math_expression_tokenizer::channel_tokenizer::{ChannelTokenizer, tokenize_into};
+    use crate::math_expression_tokenizer::{ArgumentError, PeekableTokenizer};
+    use proptest::prelude::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    const EXPR: &str = "(12.5 + 3) * 2";
+
+    // Valid expressions, with literals ranging from short decimals to the
+    // shortest printed form of any finite `f64`, in both notations.
+    pub(crate) fn expression() -> impl Strategy<Value = String> {
+        let leaf = prop_oneof![
+            (0u32..100_000, 0u32..1000).prop_map(|(int, frac)| format!("{int}.{frac}")),
+            any::<f64>()
+                .prop_filter("finite", |n| n.is_finite())
+                .prop_map(|n| n.abs().to_string()),
+            any::<f64>()
+                .prop_filter("finite", |n| n.is_finite())
+                .prop_map(|n| format!("{:e}", n.abs())),
+        ]
+        .boxed();
+
+        leaf.prop_recursive(4, 32, 4, |inner| {
+            prop_oneof![
+                (inner.clone(), "[+*/%^-]", inner.clone(), " {0,3}")
+                    .prop_map(|(lhs, op, rhs, pad)| format!("{lhs}{pad}{op}{pad}{rhs}")),
+                inner.clone().prop_map(|e| format!("( {e} )")),
+                inner.prop_map(|e| format!("-{e}")),
+            ]
+        })
+    }
+
+    fn boxed_tokenizers() -> Vec<Box<dyn TokenizerTraits>> {
+        let (tx, rx) = mpsc::channel();
+        tokenize_into(EXPR.to_string(), tx);
+
+        vec![
+            Box::new(MathExpressionTokenizer::new(EXPR.to_string()).unwrap()),
+            Box::new(ChannelTokenizer::new(rx)),
+        ]
+    }
+
+    #[test]
+    fn test_parse_dyn_boxed_tokenizers() {
+        let expected = vec![
+            Token::OpenBrace,
+            Token::Digit(12.5),
+            Token::Operator('+'),
+            Token::Digit(3.0),
+            Token::CloseBrace,
+            Token::Operator('*'),
+            Token::Digit(2.0),
+        ];
+
+        for mut tokenizer in boxed_tokenizers() {
+            let parsed = MathExpressionParser::new()
+                .parse_dyn(tokenizer.as_mut())
+                .unwrap();
+            assert_eq!(parsed.tokens(), expected);
+        }
+
+        for tokenizer in boxed_tokenizers() {
+            let parsed = MathExpressionParser::new().parse(tokenizer).unwrap();
+            assert_eq!(parsed.tokens(), expected);
+        }
+    }
+
+    // Produces `1 + 1 + ...` forever, pausing once after `cancel_at` tokens until
+    // another thread confirms the parser's cancellation token was cancelled.
+    struct EndlessTokenizer {
+        produced: usize,
+        cancel_at: usize,
+        request_cancel: mpsc::Sender<()>,
+        cancelled: mpsc::Receiver<()>,
+    }
+
+    impl TokenizerTraits for EndlessTokenizer {
+        fn has_token(&self) -> bool {
+            true
+        }
+
+        fn next_token(&mut self) -> Result<(usize, Token), MathExpressionTokenizerError> {
+            if self.produced == self.cancel_at {
+                self.request_cancel.send(()).unwrap();
+                self.cancelled.recv().unwrap();
+            }
+
+            let idx = self.produced * 2;
+            self.produced += 1;
+
+            Ok(if idx.is_multiple_of(4) {
+                (idx, Token::Digit(1.0))
+            } else {
+                (idx, Token::Operator('+'))
+            })
+        }
 
-            parsed_expression.expression.push(token);
+        fn curr_index(&self) -> usize {
+            self.produced * 2
         }
+    }
+
+    #[test]
+    fn test_cancel_from_another_thread() {
+        let token = CancellationToken::new();
+        let (request_tx, request_rx) = mpsc::channel();
+        let (cancelled_tx, cancelled_rx) = mpsc::channel();
+
+        let canceller = {
+            let token = token.clone();
+            std::thread::spawn(move || {
+                request_rx.recv().unwrap();
+                token.cancel();
+                cancelled_tx.send(()).unwrap();
+            })
+        };
+
+        let mut tokenizer = EndlessTokenizer {
+            produced: 0,
+            cancel_at: 1000,
+            request_cancel: request_tx,
+            cancelled: cancelled_rx,
+        };
+
+        let err = MathExpressionParser::new()
+            .with_cancellation(token)
+            .parse(&mut tokenizer)
+            .unwrap_err();
+        canceller.join().unwrap();
+
+        assert!(matches!(err, MathExpressionParserError::Cancelled { .. }));
+        assert!(tokenizer.produced <= 1000 + CANCELLATION_CHECK_INTERVAL);
+    }
+
+    // Produces `1 + 1 + ...` up to `len` tokens, sleeping before each one.
+    struct SlowTokenizer {
+        produced: usize,
+        len: usize,
+        delay: Duration,
+    }
+
+    impl TokenizerTraits for SlowTokenizer {
+        fn has_token(&self) -> bool {
+            self.produced < self.len
+        }
+
+        fn next_token(&mut self) -> Result<(usize, Token), MathExpressionTokenizerError> {
+            std::thread::sleep(self.delay);
+            let idx = self.produced * 2;
+            self.produced += 1;
+
+            Ok(if idx.is_multiple_of(4) {
+                (idx, Token::Digit(1.0))
+            } else {
+                (idx, Token::Operator('+'))
+            })
+        }
+
+        fn curr_index(&self) -> usize {
+            self.produced * 2
+        }
+    }
+
+    #[test]
+    fn test_deadline_exceeded_by_slow_tokenizer() {
+        let tokenizer = SlowTokenizer {
+            produced: 0,
+            len: 10 * CANCELLATION_CHECK_INTERVAL + 1,
+            delay: Duration::from_millis(2),
+        };
+
+        // The first interval alone takes longer than the deadline.
+        let deadline = Instant::now() + Duration::from_millis(100);
+        let err = MathExpressionParser::new()
+            .with_deadline(deadline)
+            .parse(tokenizer)
+            .unwrap_err();
+
+        assert_eq!(
+            err,
+            MathExpressionParserError::DeadlineExceeded {
+                idx: at(2 * CANCELLATION_CHECK_INTERVAL),
+                consumed_tokens: CANCELLATION_CHECK_INTERVAL,
+            }
+        );
+    }
+
+    #[test]
+    fn test_deadline_in_lenient_mode() {
+        let tokenizer = MathExpressionTokenizer::new("(1 + 2").unwrap();
+        let err = MathExpressionParser::new()
+            .with_options(ParserOptions::default().auto_close_parens(true))
+            .with_deadline(Instant::now())
+            .parse_lenient(tokenizer)
+            .unwrap_err();
 
-        if let Some(last_token) = parsed_expression.expression.last() {
-            if matches!(last_token, Token::Operator(_) | Token::OpenBrace) {
-                return Err(MathExpressionParserError::InvalidExpression {
-                    idx: tokenizer.curr_index(),
+        assert_eq!(
+            err,
+            MathExpressionParserError::DeadlineExceeded {
+                idx: at(0),
+                consumed_tokens: 0,
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "Parsing deadline exceeded at position 1 after 0 tokens"
+        );
+    }
+
+    #[test]
+    fn test_deadline_not_reached() {
+        let tokenizer = SlowTokenizer {
+            produced: 0,
+            len: 3,
+            delay: Duration::ZERO,
+        };
+        let parsed = MathExpressionParser::new()
+            .with_deadline(Instant::now() + Duration::from_secs(60))
+            .parse(tokenizer);
+
+        assert!(parsed.is_ok());
+    }
+
+    #[test]
+    fn test_cancelled_before_parse() {
+        let token = CancellationToken::new();
+        token.cancel();
+
+        let tokenizer = MathExpressionTokenizer::new("1 + 2".to_string()).unwrap();
+        let err = MathExpressionParser::new()
+            .with_cancellation(token)
+            .parse(tokenizer)
+            .unwrap_err();
+
+        assert_eq!(err, MathExpressionParserError::Cancelled { idx: at(0) });
+    }
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_public_types_are_send_sync() {
+        assert_send_sync::<MathExpression>();
+        assert_send_sync::<MathExpressionParser>();
+        assert_send_sync::<MathExpressionParserError>();
+        assert_send_sync::<CancellationToken>();
+    }
+
+    #[test]
+    fn test_parser_shared_between_threads() {
+        let parser = MathExpressionParser::new();
+
+        std::thread::scope(|scope| {
+            for expr in ["1 + 2", "(3)", "4 * (5 - 6)"] {
+                let parser = &parser;
+                scope.spawn(move || {
+                    let tokenizer = MathExpressionTokenizer::new(expr.to_string()).unwrap();
+                    assert!(parser.parse(tokenizer).is_ok());
                 });
             }
+        });
+    }
+
+    // Positions of ASCII inputs, where byte and char offsets coincide.
+    fn at(idx: usize) -> Position {
+        Position::new(idx, idx)
+    }
+
+    fn invalid(
+        idx: usize,
+        found: Option<Token>,
+        after: Option<Token>,
+    ) -> MathExpressionParserError {
+        MathExpressionParserError::InvalidExpression {
+            idx: at(idx),
+            found,
+            after,
         }
+    }
 
-        if braces.is_empty() {
-            Ok(parsed_expression)
-        } else {
-            Err(MathExpressionParserError::InvalidBraceConsequence {
-                idx: *braces.last().unwrap(),
+    fn tokenizer(expr: &'static str) -> MathExpressionTokenizer {
+        MathExpressionTokenizer::new(expr).unwrap()
+    }
+
+    #[test]
+    fn test_parse_into_reuses_buffers() {
+        let mut parser = MathExpressionParser::new();
+        let mut out = parser.parse(tokenizer("(1 + 2) * (3 + 4) - 5")).unwrap();
+        let capacity = out.tokens.capacity();
+        assert_eq!(out.positions.len(), out.tokens.len());
+
+        parser.parse_into(tokenizer("6"), &mut out).unwrap();
+        assert_eq!(out.tokens(), [Token::Digit(6.0)]);
+
+        parser.parse_into(tokenizer("7 + 8"), &mut out).unwrap();
+        assert_eq!(out.tokens().len(), 3);
+        // The buffers were swapped twice, so the first allocation is back in `out`.
+        assert!(out.tokens.capacity() >= capacity);
+    }
+
+    #[test]
+    fn test_parse_into_does_not_leak_state() {
+        let mut parser = MathExpressionParser::new();
+        let mut out = parser.parse(tokenizer("1")).unwrap();
+
+        let err = parser
+            .parse_into(tokenizer("((2 + 3) *"), &mut out)
+            .unwrap_err();
+        assert_eq!(err, invalid(10, None, Some(Token::Operator('*'))));
+        assert_eq!(out.tokens(), [Token::Digit(1.0)]);
+
+        parser.parse_into(tokenizer("4 * 5"), &mut out).unwrap();
+        assert_eq!(out, parser.parse(tokenizer("4 * 5")).unwrap());
+
+        let err = parser.parse_into(tokenizer("(6"), &mut out).unwrap_err();
+        assert_eq!(err, MathExpressionParserError::UnclosedBrace { idx: at(0) });
+
+        parser.parse_into(tokenizer("7"), &mut out).unwrap();
+        assert_eq!(out.tokens(), [Token::Digit(7.0)]);
+    }
+
+    #[test]
+    fn test_positioned_tokens() {
+        let parsed = MathExpressionParser::new()
+            .parse(tokenizer(" (12 +3)"))
+            .unwrap();
+        let positioned: Vec<_> = parsed.positioned_tokens().collect();
+
+        assert_eq!(
+            positioned,
+            vec![
+                (1, &Token::OpenBrace),
+                (2, &Token::Digit(12.0)),
+                (5, &Token::Operator('+')),
+                (6, &Token::Digit(3.0)),
+                (7, &Token::CloseBrace),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compact_token_size() {
+        assert_eq!(std::mem::size_of::<Token>(), 16);
+        assert_eq!(std::mem::size_of::<(usize, Token)>(), 24);
+        // One token plus its narrowed position.
+        assert_eq!(
+            std::mem::size_of::<Token>() + std::mem::size_of::<u32>(),
+            20
+        );
+    }
+
+    struct FarAwayTokenizer {
+        produced: bool,
+    }
+
+    impl TokenizerTraits for FarAwayTokenizer {
+        fn has_token(&self) -> bool {
+            !self.produced
+        }
+
+        fn next_token(&mut self) -> Result<(usize, Token), MathExpressionTokenizerError> {
+            self.produced = true;
+            Ok((u32::MAX as usize + 1, Token::Digit(1.0)))
+        }
+
+        fn curr_index(&self) -> usize {
+            u32::MAX as usize + 2
+        }
+    }
+
+    #[test]
+    fn test_position_overflow() {
+        let err = MathExpressionParser::new()
+            .parse(FarAwayTokenizer { produced: false })
+            .unwrap_err();
+
+        assert_eq!(
+            err,
+            MathExpressionParserError::PositionOverflow {
+                idx: at(u32::MAX as usize + 1)
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_borrowed_tokenizer() {
+        let mut tokenizer = MathExpressionTokenizer::new("1 + 2".to_string()).unwrap();
+        let parsed = MathExpressionParser::new().parse(&mut tokenizer).unwrap();
+
+        assert_eq!(parsed.tokens().len(), 3);
+        assert!(!tokenizer.has_token());
+        assert_eq!(tokenizer.curr_index(), 5);
+    }
+
+    #[test]
+    fn test_parse_partial_then_remainder() {
+        let source = "1+2 3*4";
+        let mut tokenizer = MathExpressionTokenizer::new(source).unwrap();
+        let parser = MathExpressionParser::new();
+
+        let (first, offset) = parser.parse_partial(&mut tokenizer).unwrap();
+        assert_eq!(
+            first.tokens(),
+            &[Token::Digit(1.0), Token::Operator('+'), Token::Digit(2.0)]
+        );
+        assert_eq!(offset, 4);
+
+        let mut rest = MathExpressionTokenizer::new(&source[offset..]).unwrap();
+        let (second, end) = parser.parse_partial(&mut rest).unwrap();
+        assert_eq!(
+            second.tokens(),
+            &[Token::Digit(3.0), Token::Operator('*'), Token::Digit(4.0)]
+        );
+        assert_eq!(end, 3);
+        assert!(!rest.has_token());
+
+        // The token parsing stopped at was put back, so the same tokenizer
+        // goes on from there.
+        assert_eq!(tokenizer.curr_index(), 4);
+        let (again, end) = parser.parse_partial(&mut tokenizer).unwrap();
+        assert_eq!(again.tokens(), second.tokens());
+        assert_eq!(end, source.len());
+    }
+
+    #[test]
+    fn test_parse_partial_stops_after_group() {
+        let mut tokenizer = MathExpressionTokenizer::new("(1 + 2) (3)").unwrap();
+        let (parsed, offset) = MathExpressionParser::new()
+            .parse_partial(&mut tokenizer)
+            .unwrap();
+
+        assert_eq!(parsed.tokens().len(), 5);
+        assert_eq!(offset, 8);
+    }
+
+    #[test]
+    fn test_parse_partial_stops_at_host_language() {
+        let parser = MathExpressionParser::new();
+        let cases = [
+            ("1 + 2 rest of DSL", 3, 6),
+            ("(1+2))", 5, 5),
+            ("2 * (3 - 1) then", 7, 12),
+            ("1 + 2", 3, 5),
+        ];
+
+        for (source, tokens, offset) in cases {
+            let mut tokenizer = MathExpressionTokenizer::new(source).unwrap();
+            let (parsed, stopped_at) = parser.parse_partial(&mut tokenizer).unwrap();
+            assert_eq!(
+                (parsed.tokens().len(), stopped_at),
+                (tokens, offset),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_partial_incomplete_is_error() {
+        let parser = MathExpressionParser::new();
+        let cases = [
+            (
+                "1 + * 2",
+                invalid(4, Some(Token::Operator('*')), Some(Token::Operator('+'))),
+            ),
+            (
+                "(1 2",
+                invalid(3, Some(Token::Digit(2.0)), Some(Token::Digit(1.0))),
+            ),
+            ("1 +", invalid(3, None, Some(Token::Operator('+')))),
+            (
+                "(1 + 2 rest",
+                MathExpressionTokenizerError::InvalidToken {
+                    idx: at(7),
+                    ch: 'r',
+                    suggestion: None,
+                }
+                .into(),
+            ),
+            (
+                ")",
+                MathExpressionParserError::UnmatchedCloseBrace {
+                    idx: at(0),
+                    last_group: None,
+                },
+            ),
+        ];
+
+        for (source, expected) in cases {
+            let mut tokenizer = MathExpressionTokenizer::new(source).unwrap();
+            assert_eq!(
+                parser.parse_partial(&mut tokenizer).unwrap_err(),
+                expected,
+                "{source}"
+            );
+        }
+    }
+
+    fn parse_lenient(
+        options: ParserOptions,
+        source: &'static str,
+    ) -> Result<(String, Vec<usize>), MathExpressionParserError> {
+        MathExpressionParser::new()
+            .with_options(options)
+            .parse_lenient(MathExpressionTokenizer::new(source).unwrap())
+            .map(|(parsed, closed)| (parsed.to_string(), closed))
+    }
+
+    #[test]
+    fn test_auto_close_parens() {
+        let options = ParserOptions::default().auto_close_parens(true);
+
+        assert_eq!(
+            parse_lenient(options, "(1 + 2"),
+            Ok(("(1 + 2)".to_string(), vec![0]))
+        );
+        assert_eq!(
+            parse_lenient(options, "(1+2*(3+4"),
+            Ok(("(1 + 2 * (3 + 4))".to_string(), vec![5, 0]))
+        );
+        assert_eq!(
+            parse_lenient(options, "((1) + (2"),
+            Ok(("((1) + (2))".to_string(), vec![7, 0]))
+        );
+        assert_eq!(
+            parse_lenient(options, "(1) + 2"),
+            Ok(("(1) + 2".to_string(), vec![]))
+        );
+
+        // The closers are real tokens, placed at the end of input.
+        let tokenizer = MathExpressionTokenizer::new("(1").unwrap();
+        let parsed = MathExpressionParser::new()
+            .with_options(options)
+            .parse(tokenizer)
+            .unwrap();
+        assert_eq!(
+            parsed.positioned_tokens().collect::<Vec<_>>(),
+            [
+                (0, &Token::OpenBrace),
+                (1, &Token::Digit(1.0)),
+                (2, &Token::CloseBrace)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_auto_close_keeps_other_errors() {
+        let options = ParserOptions::default().auto_close_parens(true);
+
+        assert_eq!(
+            parse_lenient(options, "(1 +"),
+            Err(invalid(4, None, Some(Token::Operator('+'))))
+        );
+        assert_eq!(
+            parse_lenient(options, "(1 + 2))"),
+            Err(MathExpressionParserError::UnmatchedCloseBrace {
+                idx: at(7),
+                last_group: Some(at(0)),
+            })
+        );
+    }
+
+    #[test]
+    fn test_allow_incomplete() {
+        let options = ParserOptions::default()
+            .auto_close_parens(true)
+            .allow_incomplete(true);
+
+        assert_eq!(
+            parse_lenient(options, "(1 + 2 * ("),
+            Ok(("(1 + 2)".to_string(), vec![0]))
+        );
+        assert_eq!(
+            parse_lenient(options, "(1+2*(3+"),
+            Ok(("(1 + 2 * (3))".to_string(), vec![5, 0]))
+        );
+        assert_eq!(
+            parse_lenient(options, "1 * -"),
+            Ok(("1".to_string(), vec![]))
+        );
+
+        // Nothing complete is left to keep.
+        assert_eq!(
+            parse_lenient(options, "-("),
+            Err(invalid(2, None, Some(Token::OpenBrace)))
+        );
+
+        // Without auto-closing, the dropped tail does not excuse an open brace.
+        assert_eq!(
+            parse_lenient(ParserOptions::default().allow_incomplete(true), "(1 +"),
+            Err(MathExpressionParserError::UnclosedBrace { idx: at(0) })
+        );
+    }
+
+    #[test]
+    fn test_default_options_are_strict() {
+        for source in ["(1 + 2", "(1+2*(3+4", "1 +", "(1 + 2 * ("] {
+            let strict =
+                MathExpressionParser::new().parse(MathExpressionTokenizer::new(source).unwrap());
+            assert!(strict.is_err(), "{source}");
+            assert_eq!(
+                parse_lenient(ParserOptions::default(), source).unwrap_err(),
+                strict.unwrap_err(),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_borrowed_tokenizer_stops_at_error() {
+        let mut tokenizer = MathExpressionTokenizer::new("1 + * 2").unwrap();
+        let err = MathExpressionParser::new()
+            .parse(&mut tokenizer)
+            .unwrap_err();
+
+        assert_eq!(
+            err,
+            invalid(4, Some(Token::Operator('*')), Some(Token::Operator('+')))
+        );
+        assert_eq!(tokenizer.curr_index(), 4);
+        assert!(tokenizer.has_token());
+        assert_eq!(tokenizer.next_token(), Ok((4, Token::Operator('*'))));
+        assert_eq!(tokenizer.next_token(), Ok((6, Token::Digit(2.0))));
+
+        // The same through a wrapper that cannot put tokens back itself.
+        let inner = sent(&[
+            (0, Token::Digit(1.0)),
+            (2, Token::CloseBrace),
+            (4, Token::Digit(2.0)),
+        ]);
+        let mut tokenizer = PeekableTokenizer::new(inner);
+        assert!(matches!(
+            MathExpressionParser::new().parse(&mut tokenizer),
+            Err(MathExpressionParserError::UnmatchedCloseBrace { .. })
+        ));
+        assert_eq!(tokenizer.next_token(), Ok((2, Token::CloseBrace)));
+    }
+
+    fn parse_str(expr: &str) -> Result<Vec<Token>, MathExpressionParserError> {
+        MathExpressionParser::new()
+            .parse(MathExpressionTokenizer::new(expr.to_string()).unwrap())
+            .map(|parsed| parsed.tokens().to_vec())
+    }
+
+    #[test]
+    fn test_unary_operators() {
+        use Token::{CloseBrace, Digit, OpenBrace, Operator, UnaryOperator};
+
+        let cases = [
+            (
+                "-5 + 3",
+                vec![UnaryOperator('-'), Digit(5.0), Operator('+'), Digit(3.0)],
+            ),
+            ("+7", vec![UnaryOperator('+'), Digit(7.0)]),
+            ("-0", vec![UnaryOperator('-'), Digit(0.0)]),
+            (
+                "(-2)*4",
+                vec![
+                    OpenBrace,
+                    UnaryOperator('-'),
+                    Digit(2.0),
+                    CloseBrace,
+                    Operator('*'),
+                    Digit(4.0),
+                ],
+            ),
+            (
+                "-(1+2)",
+                vec![
+                    UnaryOperator('-'),
+                    OpenBrace,
+                    Digit(1.0),
+                    Operator('+'),
+                    Digit(2.0),
+                    CloseBrace,
+                ],
+            ),
+            (
+                "(-(-3))",
+                vec![
+                    OpenBrace,
+                    UnaryOperator('-'),
+                    OpenBrace,
+                    UnaryOperator('-'),
+                    Digit(3.0),
+                    CloseBrace,
+                    CloseBrace,
+                ],
+            ),
+            (
+                "- -5",
+                vec![UnaryOperator('-'), UnaryOperator('-'), Digit(5.0)],
+            ),
+            (
+                "--5",
+                vec![UnaryOperator('-'), UnaryOperator('-'), Digit(5.0)],
+            ),
+            (
+                "+-5",
+                vec![UnaryOperator('+'), UnaryOperator('-'), Digit(5.0)],
+            ),
+            (
+                "(-5)",
+                vec![OpenBrace, UnaryOperator('-'), Digit(5.0), CloseBrace],
+            ),
+            (
+                "2*(-3)",
+                vec![
+                    Digit(2.0),
+                    Operator('*'),
+                    OpenBrace,
+                    UnaryOperator('-'),
+                    Digit(3.0),
+                    CloseBrace,
+                ],
+            ),
+            (
+                "2 * -3",
+                vec![Digit(2.0), Operator('*'), UnaryOperator('-'), Digit(3.0)],
+            ),
+            (
+                "1 - -1",
+                vec![Digit(1.0), Operator('-'), UnaryOperator('-'), Digit(1.0)],
+            ),
+        ];
+
+        for (expr, expected) in cases {
+            assert_eq!(parse_str(expr), Ok(expected), "{expr}");
+        }
+    }
+
+    #[test]
+    fn test_unary_operators_rejected() {
+        use Token::{CloseBrace, OpenBrace, Operator, UnaryOperator};
+
+        let cases = [
+            ("*5", invalid(0, Some(Operator('*')), None)),
+            ("/5", invalid(0, Some(Operator('/')), None)),
+            ("(*2)", invalid(1, Some(Operator('*')), Some(OpenBrace))),
+            ("5 * -", invalid(5, None, Some(UnaryOperator('-')))),
+            ("-", invalid(1, None, Some(UnaryOperator('-')))),
+            (
+                "(-)",
+                invalid(2, Some(CloseBrace), Some(UnaryOperator('-'))),
+            ),
+            (
+                "- * 5",
+                invalid(2, Some(Operator('*')), Some(UnaryOperator('-'))),
+            ),
+        ];
+
+        for (expr, expected) in cases {
+            assert_eq!(parse_str(expr), Err(expected), "{expr}");
+        }
+    }
+
+    #[test]
+    fn test_adjacency_errors_carry_tokens() {
+        use Token::{CloseBrace, Digit, OpenBrace, Operator};
+
+        let cases = [
+            ("1 (2)", invalid(2, Some(OpenBrace), Some(Digit(1.0)))),
+            ("(1) (2)", invalid(4, Some(OpenBrace), Some(CloseBrace))),
+            ("1 2", invalid(2, Some(Digit(2.0)), Some(Digit(1.0)))),
+            ("(1) 2", invalid(4, Some(Digit(2.0)), Some(CloseBrace))),
+            ("(1 +)", invalid(4, Some(CloseBrace), Some(Operator('+')))),
+            (
+                "1 * / 2",
+                invalid(4, Some(Operator('/')), Some(Operator('*'))),
+            ),
+            ("(", invalid(1, None, Some(OpenBrace))),
+        ];
+
+        for (expr, expected) in cases {
+            assert_eq!(parse_str(expr), Err(expected), "{expr}");
+        }
+
+        assert_eq!(
+            parse_str("1 * / 2").unwrap_err().to_string(),
+            "Operator '/' cannot follow operator '*' at position 5"
+        );
+        assert_eq!(
+            parse_str("* 2").unwrap_err().to_string(),
+            "Expression cannot start with operator '*' at position 1"
+        );
+    }
+
+    #[test]
+    fn test_brace_errors() {
+        use MathExpressionParserError::{UnclosedBrace, UnmatchedCloseBrace};
+
+        let deep_open = format!("{}1{}", "(".repeat(100), ")".repeat(99));
+        let deep_close = format!("{}1{}", "(".repeat(100), ")".repeat(101));
+        let cases = [
+            ("((1+2)", UnclosedBrace { idx: at(0) }),
+            (
+                "(()",
+                invalid(2, Some(Token::CloseBrace), Some(Token::OpenBrace)),
+            ),
+            ("((1)", UnclosedBrace { idx: at(0) }),
+            (
+                "()(",
+                invalid(1, Some(Token::CloseBrace), Some(Token::OpenBrace)),
+            ),
+            (
+                "(1)(",
+                invalid(3, Some(Token::OpenBrace), Some(Token::CloseBrace)),
+            ),
+            ("(1) + (2", UnclosedBrace { idx: at(6) }),
+            (
+                "())(",
+                invalid(1, Some(Token::CloseBrace), Some(Token::OpenBrace)),
+            ),
+            (
+                "(1))(",
+                UnmatchedCloseBrace {
+                    idx: at(3),
+                    last_group: Some(at(0)),
+                },
+            ),
+            (
+                "1 + )",
+                UnmatchedCloseBrace {
+                    idx: at(4),
+                    last_group: None,
+                },
+            ),
+            (&deep_open, UnclosedBrace { idx: at(0) }),
+            (
+                &deep_close,
+                UnmatchedCloseBrace {
+                    idx: at(201),
+                    last_group: Some(at(0)),
+                },
+            ),
+        ];
+
+        for (expr, expected) in cases {
+            assert_eq!(parse_str(expr), Err(expected), "{expr}");
+        }
+    }
+
+    // Tokens sent by hand, as another producer than `tokenize_into` could.
+    fn sent(tokens: &[(usize, Token)]) -> ChannelTokenizer {
+        let (tx, rx) = mpsc::channel();
+        for (idx, token) in tokens {
+            let (start, end) = (at(*idx), at(*idx + 1));
+            tx.send(Ok((start, end, token.clone()))).unwrap();
+        }
+        ChannelTokenizer::new(rx)
+    }
+
+    #[test]
+    fn test_unknown_operators_from_other_tokenizers() {
+        let invalid_token = |idx, ch| {
+            MathExpressionParserError::from(MathExpressionTokenizerError::InvalidToken {
+                idx: at(idx),
+                ch,
+                suggestion: None,
+            })
+        };
+        let cases = [
+            (
+                vec![
+                    (0, Token::Digit(1.0)),
+                    (2, Token::Operator('&')),
+                    (4, Token::Digit(2.0)),
+                ],
+                invalid_token(2, '&'),
+            ),
+            (
+                vec![(0, Token::UnaryOperator('!')), (1, Token::Digit(2.0))],
+                invalid_token(0, '!'),
+            ),
+            (
+                vec![
+                    (0, Token::Digit(1.0)),
+                    (1, Token::UnaryOperator('&')),
+                    (2, Token::Digit(2.0)),
+                ],
+                invalid_token(1, '&'),
+            ),
+        ];
+
+        let parser = MathExpressionParser::new();
+        for (tokens, expected) in cases {
+            assert_eq!(parser.parse(sent(&tokens)), Err(expected), "{tokens:?}");
+        }
+
+        // A partial parse stops at one like at an invalid character.
+        let tokens = [(0, Token::Digit(1.0)), (2, Token::Operator('&'))];
+        let (parsed, stopped_at) = parser.parse_partial(&mut sent(&tokens)).unwrap();
+        assert_eq!(parsed.tokens(), [Token::Digit(1.0)]);
+        assert_eq!(stopped_at, 2);
+    }
+
+    struct EmptyTokenizer;
+
+    impl TokenizerTraits for EmptyTokenizer {
+        fn has_token(&self) -> bool {
+            false
+        }
+
+        fn next_token(&mut self) -> Result<(usize, Token), MathExpressionTokenizerError> {
+            Err(MathExpressionTokenizerError::NoToken)
+        }
+
+        fn curr_index(&self) -> usize {
+            0
+        }
+    }
+
+    #[test]
+    fn test_empty_expression() {
+        let mut parser = MathExpressionParser::new();
+        assert_eq!(
+            parser.parse(EmptyTokenizer),
+            Err(MathExpressionParserError::EmptyExpression)
+        );
+        assert_eq!(
+            parser.parse_partial(&mut EmptyTokenizer).unwrap_err(),
+            MathExpressionParserError::EmptyExpression
+        );
+
+        let mut out = parser.parse(tokenizer("1")).unwrap();
+        assert_eq!(
+            parser.parse_into(EmptyTokenizer, &mut out),
+            Err(MathExpressionParserError::EmptyExpression)
+        );
+        assert_eq!(out.tokens(), [Token::Digit(1.0)]);
+    }
+
+    #[test]
+    fn test_parse_detailed_reports_progress() {
+        let parser = MathExpressionParser::new();
+        let cases = [
+            // Stopped at the rejected token, which is given back.
+            ("* 1", invalid(0, Some(Token::Operator('*')), None), 0, 0),
+            (
+                "1 + * 2",
+                invalid(4, Some(Token::Operator('*')), Some(Token::Operator('+'))),
+                2,
+                4,
+            ),
+            (
+                "(1 + 2) * ",
+                invalid(9, None, Some(Token::Operator('*'))),
+                6,
+                9,
+            ),
+        ];
+
+        for (expr, error, tokens_parsed, stopped_at) in cases {
+            assert_eq!(
+                parser.parse_detailed(tokenizer(expr)),
+                Err(ParseFailure {
+                    error,
+                    tokens_parsed,
+                    stopped_at: at(stopped_at)
+                }),
+                "{expr}"
+            );
+        }
+
+        assert_eq!(
+            parser.parse_detailed(tokenizer("1 + 2")),
+            parser.parse(tokenizer("1 + 2")).map_err(|_| unreachable!())
+        );
+    }
+
+    #[test]
+    fn test_error_positions_after_multibyte_whitespace() {
+        assert_eq!(
+            parse_str("\u{3000}(1 +\u{3000}* 2"),
+            Err(MathExpressionParserError::InvalidExpression {
+                idx: Position::new(10, 6),
+                found: Some(Token::Operator('*')),
+                after: Some(Token::Operator('+')),
+            })
+        );
+        assert_eq!(
+            parse_str("\u{3000}(1"),
+            Err(MathExpressionParserError::UnclosedBrace {
+                idx: Position::new(3, 1)
             })
+        );
+
+        let err = parse_str("\u{3000}(1)\u{3000})").unwrap_err();
+        assert_eq!(
+            err,
+            MathExpressionParserError::UnmatchedCloseBrace {
+                idx: Position::new(9, 5),
+                last_group: Some(Position::new(3, 1)),
+            }
+        );
+        assert_eq!(err.to_string(), "Unmatched closing brace at position 6");
+    }
+
+    #[test]
+    fn test_stray_close_brace_reports_first() {
+        let cases = [
+            (")", 0, None),
+            ("1)", 1, None),
+            ("(1))(", 3, Some(0)),
+            ("((1))))", 5, Some(0)),
+            ("(1) + (2)) + 3)", 9, Some(6)),
+        ];
+
+        for (expr, idx, last_group) in cases {
+            assert_eq!(
+                parse_str(expr),
+                Err(MathExpressionParserError::UnmatchedCloseBrace {
+                    idx: at(idx),
+                    last_group: last_group.map(at),
+                }),
+                "{expr}"
+            );
+        }
+    }
+
+    fn reparse(parsed: &MathExpression) -> MathExpression {
+        let printed = parsed.to_string();
+        MathExpressionParser::new()
+            .parse(MathExpressionTokenizer::new(printed.clone()).unwrap())
+            .unwrap_or_else(|err| panic!("'{printed}' does not parse: {err}"))
+    }
+
+    #[test]
+    fn test_expression_from_str() {
+        let parsed: MathExpression = "(1 + 2) * 3".parse().unwrap();
+        assert_eq!(
+            parsed,
+            MathExpressionParser::new()
+                .parse(tokenizer("(1 + 2) * 3"))
+                .unwrap()
+        );
+        assert_eq!(MathExpression::try_from("(1 + 2) * 3"), Ok(parsed));
+
+        assert_eq!(
+            "1 +".parse::<MathExpression>(),
+            Err(invalid(3, None, Some(Token::Operator('+'))))
+        );
+        assert_eq!(
+            "".parse::<MathExpression>(),
+            Err(MathExpressionParserError::Tokenizer(
+                MathExpressionTokenizerError::InvalidArgument {
+                    reason: ArgumentError::Empty
+                }
+            ))
+        );
+    }
+
+    fn content_hash(source: &str) -> u64 {
+        source.parse::<MathExpression>().unwrap().content_hash()
+    }
+
+    #[test]
+    fn test_content_hash_ignores_spacing() {
+        assert_eq!(content_hash("(1+2)*3"), content_hash(" ( 1 + 2 )\t*  3 "));
+        assert_eq!(content_hash("-1.50"), content_hash("- 1.5"));
+    }
+
+    #[test]
+    fn test_content_hash_tells_expressions_apart() {
+        let sources = [
+            "1 + 2", "2 + 1", "1 - 2", "1 + 3", "(1 + 2)", "-1 + 2", "1 + -2", "1 * 2", "0", "0.1",
+        ];
+        let hashes: std::collections::HashSet<_> =
+            sources.iter().map(|source| content_hash(source)).collect();
+
+        assert_eq!(hashes.len(), sources.len());
+    }
+
+    #[test]
+    fn test_content_hash_is_stable() {
+        // Pinned so that a change to the encoding, which would invalidate
+        // stored hashes, is noticed.
+        assert_eq!(content_hash("1 + 2"), 15_958_303_044_299_443_570);
+    }
+
+    #[test]
+    fn test_display_canonical_form() {
+        let parsed = MathExpressionParser::new()
+            .parse(tokenizer("(1+2.50)*  - 3 /-(4)"))
+            .unwrap();
+        assert_eq!(parsed.to_string(), "(1 + 2.5) * -3 / -(4)");
+    }
+
+    #[test]
+    fn test_display_roundtrips_edge_literals() {
+        // 1e-308, the largest and the smallest subnormal `f64` at full precision,
+        // and literals that overflow, underflow or lose precision.
+        let sources = [
+            format!("0.{}1", "0".repeat(307)),
+            f64::MAX.to_string(),
+            f64::from_bits(1).to_string(),
+            format!("1{}", "0".repeat(400)),
+            "1e400".to_string(),
+            format!("0.{}1", "0".repeat(400)),
+            "-0".to_string(),
+            "- -0.0".to_string(),
+            "0.30000000000000004".to_string(),
+            "9007199254740993".to_string(),
+            "123456789.12345678901234567890".to_string(),
+        ];
+
+        for source in sources {
+            let parsed = MathExpressionParser::new()
+                .parse(MathExpressionTokenizer::new(source.clone()).unwrap())
+                .unwrap();
+            assert_eq!(reparse(&parsed).tokens(), parsed.tokens(), "{source}");
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_display_roundtrips(source in expression()) {
+            let parsed = MathExpressionParser::new()
+                .parse(MathExpressionTokenizer::new(source).unwrap())
+                .unwrap();
+            let reparsed = reparse(&parsed);
+
+            prop_assert_eq!(reparsed.tokens(), parsed.tokens());
+            prop_assert_eq!(reparsed.to_string(), parsed.to_string());
         }
     }
 }
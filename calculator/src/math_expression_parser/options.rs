@@ -0,0 +1,24 @@
+// Optional leniency of the parser, e.g. for best-effort evaluation of what a
+// user is still typing. The default options reject every incomplete
+// expression.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ParserOptions {
+    // Close the braces still open at the end of input instead of reporting
+    // `UnclosedBrace`, innermost first.
+    pub auto_close_parens: bool,
+    // Drop an unfinished tail at the end of input, e.g. the `* (` of
+    // `1 + 2 * (`, instead of reporting the expression as incomplete.
+    pub allow_incomplete: bool,
+}
+
+impl ParserOptions {
+    pub fn auto_close_parens(mut self, enabled: bool) -> Self {
+        self.auto_close_parens = enabled;
+        self
+    }
+
+    pub fn allow_incomplete(mut self, enabled: bool) -> Self {
+        self.allow_incomplete = enabled;
+        self
+    }
+}
@@ -0,0 +1,177 @@
+use super::MathExpression;
+use crate::math_expression_tokenizer::Token;
+
+// What to build from the operands and operators of an expression, in the
+// order precedence dictates.
+pub(crate) trait Fold {
+    type Value;
+    type Error;
+
+    fn number(&mut self, number: f64) -> Self::Value;
+    fn unary(&mut self, op: char, operand: Self::Value) -> Self::Value;
+    // `idx` is the byte offset of the operator.
+    fn binary(
+        &mut self,
+        op: char,
+        idx: usize,
+        lhs: Self::Value,
+        rhs: Self::Value,
+    ) -> Result<Self::Value, Self::Error>;
+    fn group(&mut self, inner: Self::Value) -> Self::Value;
+}
+
+// Operators waiting for their right operand, with the position of binary ones.
+#[derive(Debug, Clone, Copy)]
+enum Pending {
+    Binary(char, usize),
+    Unary(char),
+    OpenBrace,
+}
+
+// How tightly operators bind. Unary operators bind tighter than any binary
+// one but '^', so `-2 * 3` is `(-2) * 3` while `-2 ^ 2` is `-(2 ^ 2)`.
+const UNARY_PRECEDENCE: u8 = 3;
+
+fn precedence(op: char) -> u8 {
+    match op {
+        '^' => 4,
+        '*' | '/' | '%' => 2,
+        _ => 1,
+    }
+}
+
+// '^' is right-associative, so `2 ^ 3 ^ 2` is `2 ^ (3 ^ 2)`; the other binary
+// operators are left-associative.
+fn is_right_associative(op: char) -> bool {
+    op == '^'
+}
+
+// Whether `top`, waiting on the stack, takes its right operand before the
+// binary operator `op` that follows it.
+fn binds_before(top: Pending, op: char) -> bool {
+    let top = match top {
+        Pending::Unary(_) => UNARY_PRECEDENCE,
+        Pending::Binary(other, _) => precedence(other),
+        Pending::OpenBrace => return false,
+    };
+    top > precedence(op) || (top == precedence(op) && !is_right_associative(op))
+}
+
+// Folds `expr` with the usual precedence: '^' before '*', '/' and '%' before
+// '+' and '-', and parenthesised groups first.
+pub(crate) fn fold<F: Fold>(expr: &MathExpression, folder: &mut F) -> Result<F::Value, F::Error> {
+    fold_with(expr, folder, &mut FoldStacks::new())
+}
+
+// Same as `fold`, reusing the allocations of `stacks`, whatever a previous
+// fold left in them.
+pub(crate) fn fold_with<F: Fold>(
+    expr: &MathExpression,
+    folder: &mut F,
+    stacks: &mut FoldStacks<F::Value>,
+) -> Result<F::Value, F::Error> {
+    stacks.clear();
+    for (idx, token) in expr.positioned_tokens() {
+        stacks.push(folder, idx, token)?;
+    }
+    stacks.finish(folder)
+}
+
+// The state of `fold` between tokens, for callers that fold tokens as they
+// are read. This is the shunting-yard algorithm with every operator folded as
+// soon as it would be written to the output, so no RPN is built and nesting
+// depth only costs stack entries on the heap. The tokens must form a valid
+// expression; a fold that failed must not be continued.
+pub(crate) struct FoldStacks<V> {
+    values: Vec<V>,
+    pending: Vec<Pending>,
+}
+
+impl<V> FoldStacks<V> {
+    pub(crate) fn new() -> Self {
+        Self {
+            values: Vec::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    // Empties the stacks, keeping their allocations.
+    pub(crate) fn clear(&mut self) {
+        self.values.clear();
+        self.pending.clear();
+    }
+
+    pub(crate) fn push<F: Fold<Value = V>>(
+        &mut self,
+        folder: &mut F,
+        idx: usize,
+        token: &Token,
+    ) -> Result<(), F::Error> {
+        let (values, pending) = (&mut self.values, &mut self.pending);
+        match *token {
+            Token::Digit(number) => values.push(folder.number(number)),
+            Token::UnaryOperator(op) => pending.push(Pending::Unary(op)),
+            Token::OpenBrace => pending.push(Pending::OpenBrace),
+            Token::CloseBrace => {
+                while let Some(top) = pending.pop() {
+                    if let Pending::OpenBrace = top {
+                        break;
+                    }
+                    apply(folder, top, values)?;
+                }
+                let inner = pop(values);
+                values.push(folder.group(inner));
+            }
+            Token::Operator(op) => {
+                while let Some(&top) = pending.last() {
+                    if !binds_before(top, op) {
+                        break;
+                    }
+                    pending.pop();
+                    apply(folder, top, values)?;
+                }
+                pending.push(Pending::Binary(op, idx));
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn finish<F: Fold<Value = V>>(&mut self, folder: &mut F) -> Result<V, F::Error> {
+        while let Some(top) = self.pending.pop() {
+            apply(folder, top, &mut self.values)?;
+        }
+
+        let value = pop(&mut self.values);
+        assert!(
+            self.values.is_empty(),
+            "parsed expression left extra operands"
+        );
+        Ok(value)
+    }
+}
+
+fn apply<F: Fold>(
+    folder: &mut F,
+    operator: Pending,
+    values: &mut Vec<F::Value>,
+) -> Result<(), F::Error> {
+    let rhs = pop(values);
+    let value = match operator {
+        Pending::Unary(op) => folder.unary(op, rhs),
+        Pending::Binary(op, idx) => {
+            let lhs = pop(values);
+            folder.binary(op, idx, lhs, rhs)?
+        }
+        Pending::OpenBrace => unreachable!("parsed expression has an unclosed brace"),
+    };
+
+    values.push(value);
+    Ok(())
+}
+
+fn pop<T>(values: &mut Vec<T>) -> T {
+    let Some(value) = values.pop() else {
+        unreachable!("parsed expression has an operator without operand")
+    };
+    value
+}
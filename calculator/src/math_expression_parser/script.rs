@@ -0,0 +1,306 @@
+use super::{MathExpression, MathExpressionParser, MathExpressionParserError};
+use crate::math_expression_tokenizer::{MathExpressionTokenizer, MathExpressionTokenizerError};
+use crate::position::Position;
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+pub type ScriptResult = Result<MathExpression, MathExpressionParserError>;
+
+// Parses every statement of a script separated by ';' or newlines. Blank
+// statements are skipped; token positions and error indices refer to `source`.
+pub fn parse_all(source: &str) -> Vec<ScriptResult> {
+    let parser = MathExpressionParser::new();
+
+    split_statements(source)
+        .into_iter()
+        .map(|(offset, statement)| parse_statement(&parser, offset, statement))
+        .collect()
+}
+
+// Same as `parse_all`, with statements parsed on the rayon thread pool.
+// Results are returned in source order.
+#[cfg(feature = "rayon")]
+pub fn parse_script_parallel(source: &str) -> Vec<ScriptResult> {
+    let parser = MathExpressionParser::new();
+
+    split_statements(source)
+        .into_par_iter()
+        .map(|(offset, statement)| parse_statement(&parser, offset, statement))
+        .collect()
+}
+
+pub(super) fn parse_statement(
+    parser: &MathExpressionParser,
+    offset: Position,
+    statement: &str,
+) -> ScriptResult {
+    let tokenizer = MathExpressionTokenizer::new(statement.to_string())
+        .map_err(|err| MathExpressionParserError::from(err.offset_by(offset)))?;
+
+    parser
+        .parse(tokenizer)
+        .map_err(|err| err.offset_by(offset))?
+        .offset_by(offset, statement)
+}
+
+// Separators inside parentheses do not split, so an unclosed '(' swallows the
+// rest of the script into one statement that then fails to parse.
+fn split_statements(source: &str) -> Vec<(Position, &str)> {
+    let mut statements = Vec::new();
+    let mut depth = 0usize;
+    let mut start = Position::default();
+
+    for (char_idx, (idx, ch)) in source.char_indices().enumerate() {
+        match ch {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            ';' | '\n' if depth == 0 => {
+                statements.push((start, &source[start.byte..idx]));
+                start = Position::new(idx + 1, char_idx + 1);
+            }
+            _ => {}
+        }
+    }
+    statements.push((start, &source[start.byte..]));
+
+    statements.retain(|(_, statement)| !statement.trim().is_empty());
+    statements
+}
+
+impl MathExpressionTokenizerError {
+    pub(crate) fn offset_by(self, offset: Position) -> Self {
+        match self {
+            MathExpressionTokenizerError::InvalidToken {
+                idx,
+                ch,
+                suggestion,
+            } => MathExpressionTokenizerError::InvalidToken {
+                idx: idx.offset_by(offset),
+                ch,
+                suggestion,
+            },
+            MathExpressionTokenizerError::MalformedNumber { idx } => {
+                MathExpressionTokenizerError::MalformedNumber {
+                    idx: idx.offset_by(offset),
+                }
+            }
+            MathExpressionTokenizerError::ConsecutiveOperators {
+                first_idx,
+                second_idx,
+            } => MathExpressionTokenizerError::ConsecutiveOperators {
+                first_idx: first_idx.offset_by(offset),
+                second_idx: second_idx.offset_by(offset),
+            },
+            MathExpressionTokenizerError::PrecisionLoss { idx, literal } => {
+                MathExpressionTokenizerError::PrecisionLoss {
+                    idx: idx.offset_by(offset),
+                    literal,
+                }
+            }
+            MathExpressionTokenizerError::TrailingInput { idx } => {
+                MathExpressionTokenizerError::TrailingInput {
+                    idx: idx.offset_by(offset),
+                }
+            }
+            err => err,
+        }
+    }
+}
+
+impl MathExpressionParserError {
+    pub(crate) fn offset_by(self, offset: Position) -> Self {
+        match self {
+            MathExpressionParserError::Tokenizer(err) => {
+                MathExpressionParserError::Tokenizer(err.offset_by(offset))
+            }
+            MathExpressionParserError::InvalidExpression { idx, found, after } => {
+                MathExpressionParserError::InvalidExpression {
+                    idx: idx.offset_by(offset),
+                    found,
+                    after,
+                }
+            }
+            MathExpressionParserError::UnclosedBrace { idx } => {
+                MathExpressionParserError::UnclosedBrace {
+                    idx: idx.offset_by(offset),
+                }
+            }
+            MathExpressionParserError::UnmatchedCloseBrace { idx, last_group } => {
+                MathExpressionParserError::UnmatchedCloseBrace {
+                    idx: idx.offset_by(offset),
+                    last_group: last_group.map(|group| group.offset_by(offset)),
+                }
+            }
+            MathExpressionParserError::EmptyExpression => {
+                MathExpressionParserError::EmptyExpression
+            }
+            MathExpressionParserError::Cancelled { idx } => MathExpressionParserError::Cancelled {
+                idx: idx.offset_by(offset),
+            },
+            MathExpressionParserError::DeadlineExceeded {
+                idx,
+                consumed_tokens,
+            } => MathExpressionParserError::DeadlineExceeded {
+                idx: idx.offset_by(offset),
+                consumed_tokens,
+            },
+            MathExpressionParserError::PositionOverflow { idx } => {
+                MathExpressionParserError::PositionOverflow {
+                    idx: idx.offset_by(offset),
+                }
+            }
+            MathExpressionParserError::NotANumber { idx } => {
+                MathExpressionParserError::NotANumber {
+                    idx: idx.offset_by(offset),
+                }
+            }
+        }
+    }
+}
+
+impl MathExpression {
+    // `statement` is the source the positions currently refer to.
+    fn offset_by(
+        mut self,
+        offset: Position,
+        statement: &str,
+    ) -> Result<Self, MathExpressionParserError> {
+        for position in self.positions.iter_mut() {
+            let local = *position as usize;
+            *position = u32::try_from(local + offset.byte).map_err(|_| {
+                MathExpressionParserError::PositionOverflow {
+                    idx: Position::in_source(statement, local).offset_by(offset),
+                }
+            })?;
+        }
+
+        Ok(self)
+    }
+}
+
+// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math_expression_tokenizer::Token;
+
+    fn generated_script(statements: usize) -> String {
+        const TEMPLATES: [&str; 6] = [
+            "1 + 2",
+            "(3 *\n 4) - 5",
+            "6 / (7 + (8; 9))",
+            "  ",
+            "10 +",
+            "(11 $ 12)",
+        ];
+
+        (0..statements)
+            .map(|i| TEMPLATES[i % TEMPLATES.len()])
+            .collect::<Vec<_>>()
+            .join(";\n")
+    }
+
+    #[test]
+    fn test_split_respects_parentheses() {
+        let source = "1; (2;\n3)\n\n4 ;";
+        assert_eq!(
+            split_statements(source),
+            vec![
+                (Position::new(0, 0), "1"),
+                (Position::new(2, 2), " (2;\n3)"),
+                (Position::new(11, 11), "4 ")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_positions_are_absolute() {
+        let results = parse_all("1 + 2;\n(3 * 4");
+        assert_eq!(results.len(), 2);
+
+        let first: Vec<_> = results[0].as_ref().unwrap().positioned_tokens().collect();
+        assert_eq!(
+            first,
+            vec![
+                (0, &Token::Digit(1.0)),
+                (2, &Token::Operator('+')),
+                (4, &Token::Digit(2.0)),
+            ]
+        );
+        assert_eq!(
+            results[1],
+            Err(MathExpressionParserError::UnclosedBrace {
+                idx: Position::new(7, 7)
+            })
+        );
+    }
+
+    #[test]
+    fn test_tokenizer_error_is_absolute() {
+        assert_eq!(
+            parse_all("1\n2 $ 3"),
+            vec![
+                MathExpressionParser::new().parse(MathExpressionTokenizer::new("1").unwrap()),
+                Err(MathExpressionTokenizerError::InvalidToken {
+                    idx: Position::new(4, 4),
+                    ch: '$',
+                    suggestion: None,
+                }
+                .into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_error_positions_count_chars_of_earlier_statements() {
+        assert_eq!(
+            parse_all("\u{3000}1;\u{3000}2 $")[1],
+            Err(MathExpressionTokenizerError::InvalidToken {
+                idx: Position::new(10, 6),
+                ch: '$',
+                suggestion: None,
+            }
+            .into())
+        );
+    }
+
+    #[test]
+    fn test_blank_script() {
+        assert!(parse_all("").is_empty());
+        assert!(parse_all(" ;\n ; ").is_empty());
+    }
+
+    #[test]
+    fn test_generated_script_errors_point_into_source() {
+        let source = generated_script(60);
+        for result in parse_all(&source) {
+            match result {
+                Ok(parsed) => {
+                    for (idx, token) in parsed.positioned_tokens() {
+                        if let Token::Operator(op) = token {
+                            assert!(source[idx..].starts_with(*op));
+                        }
+                    }
+                }
+                Err(MathExpressionParserError::InvalidExpression { idx, .. }) => {
+                    assert!(idx.byte <= source.len());
+                }
+                Err(MathExpressionParserError::Tokenizer(
+                    MathExpressionTokenizerError::InvalidToken { idx, ch, .. },
+                )) => assert!(source[idx.byte..].starts_with(ch)),
+                Err(err) => panic!("unexpected error {err:?}"),
+            }
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_parallel_matches_sequential() {
+        let source = generated_script(10_000);
+        let sequential = parse_all(&source);
+
+        assert!(sequential.len() > 8_000);
+        assert_eq!(parse_script_parallel(&source), sequential);
+    }
+}
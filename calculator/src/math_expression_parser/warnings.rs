@@ -0,0 +1,178 @@
+use super::{BraceStack, MathExpression, MathExpressionParser, MathExpressionParserError};
+use crate::math_expression_tokenizer::{MathExpressionTokenizer, TokenizerWarning};
+use crate::position::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
+pub enum WarningKind {
+    // A literal was rounded to the nearest `f64`.
+    PrecisionLoss,
+    // A literal was too large for `f64` and became infinity.
+    Overflow,
+    // A '(' was closed at the end of input, see
+    // `ParserOptions::auto_close_parens`.
+    AutoClosedParen,
+    // An unfinished tail was dropped, see `ParserOptions::allow_incomplete`.
+    DroppedTail,
+}
+
+// A decision that made the parsed expression differ from what was written,
+// with the span of the input it concerns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ParseWarning {
+    pub kind: WarningKind,
+    pub span: Span,
+    pub description: String,
+}
+
+impl MathExpressionParser {
+    // Same as `parse`, also returning a warning for every lossy or permissive
+    // decision, in input order: literals the tokenizer rounded, braces closed
+    // under `auto_close_parens` and the tail dropped under `allow_incomplete`.
+    // With `TokenizerConfig::strict` and the default options the list is
+    // always empty, as each of these is then an error.
+    pub fn parse_verbose(
+        &self,
+        mut tokenizer: MathExpressionTokenizer,
+    ) -> Result<(MathExpression, Vec<ParseWarning>), MathExpressionParserError> {
+        let mut parsed_expression = MathExpression::empty();
+        let mut braces = BraceStack::new();
+        let mut dropped_tail = None;
+        self.parse_tokens_into(
+            &mut tokenizer,
+            &mut parsed_expression,
+            &mut braces,
+            &mut dropped_tail,
+            false,
+        )?;
+
+        let mut warnings: Vec<_> = tokenizer
+            .take_warnings()
+            .into_iter()
+            .map(ParseWarning::from)
+            .collect();
+        warnings.extend(braces.iter().map(|&open| ParseWarning {
+            kind: WarningKind::AutoClosedParen,
+            span: Span::new(open, open + 1),
+            description: "'(' was closed at the end of input".to_string(),
+        }));
+        warnings.extend(dropped_tail.map(|span| ParseWarning {
+            kind: WarningKind::DroppedTail,
+            span,
+            description: "Unfinished input was dropped".to_string(),
+        }));
+        warnings.sort_by_key(|warning| warning.span);
+
+        Ok((parsed_expression, warnings))
+    }
+}
+
+impl From<TokenizerWarning> for ParseWarning {
+    fn from(warning: TokenizerWarning) -> Self {
+        let (kind, idx, literal) = match warning {
+            TokenizerWarning::PrecisionLoss { idx, literal } => {
+                (WarningKind::PrecisionLoss, idx, literal)
+            }
+            TokenizerWarning::Overflow { idx, literal } => (WarningKind::Overflow, idx, literal),
+        };
+
+        let description = match kind {
+            WarningKind::Overflow => {
+                format!("Literal '{literal}' is too large and became infinity")
+            }
+            _ => format!("Literal '{literal}' was rounded"),
+        };
+        ParseWarning {
+            kind,
+            span: Span::new(idx.byte, idx.byte + literal.len()),
+            description,
+        }
+    }
+}
+
+// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math_expression_parser::ParserOptions;
+    use crate::math_expression_tokenizer::TokenizerConfig;
+
+    fn warnings(options: ParserOptions, source: &'static str) -> Vec<(WarningKind, &'static str)> {
+        let (_, warnings) = MathExpressionParser::new()
+            .with_options(options)
+            .parse_verbose(MathExpressionTokenizer::new(source).unwrap())
+            .unwrap();
+        warnings
+            .into_iter()
+            .map(|warning| (warning.kind, warning.span.slice(source)))
+            .collect()
+    }
+
+    fn lenient() -> ParserOptions {
+        ParserOptions::default()
+            .auto_close_parens(true)
+            .allow_incomplete(true)
+    }
+
+    #[test]
+    fn test_one_warning_per_decision() {
+        assert_eq!(
+            warnings(lenient(), "0.1000000000000000000001 + 1"),
+            [(WarningKind::PrecisionLoss, "0.1000000000000000000001")]
+        );
+
+        let huge = format!("1{} * 2", "0".repeat(400));
+        let (_, overflow) = MathExpressionParser::new()
+            .parse_verbose(MathExpressionTokenizer::new(huge).unwrap())
+            .unwrap();
+        assert_eq!(overflow.len(), 1);
+        assert_eq!(overflow[0].kind, WarningKind::Overflow);
+        assert_eq!(overflow[0].span, Span::new(0, 401));
+
+        assert_eq!(
+            warnings(lenient(), "2 * (1 + (3"),
+            [
+                (WarningKind::AutoClosedParen, "("),
+                (WarningKind::AutoClosedParen, "(")
+            ]
+        );
+        assert_eq!(
+            warnings(lenient(), "1 + 2 * -"),
+            [(WarningKind::DroppedTail, "* -")]
+        );
+        assert_eq!(
+            warnings(lenient(), "(1 + 2 * ("),
+            [
+                (WarningKind::AutoClosedParen, "("),
+                (WarningKind::DroppedTail, "* (")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_disabled_options_have_no_warnings() {
+        assert!(warnings(lenient(), "(1 + 2) * 3").is_empty());
+
+        // Without the option the input is an error instead.
+        let parse = |options, source| {
+            MathExpressionParser::new()
+                .with_options(options)
+                .parse_verbose(MathExpressionTokenizer::new(source).unwrap())
+        };
+        assert!(parse(lenient().auto_close_parens(false), "2 * (1 + 3").is_err());
+        assert!(parse(lenient().allow_incomplete(false), "1 + 2 *").is_err());
+    }
+
+    #[test]
+    fn test_strict_mode_has_no_warnings() {
+        for source in ["0.1000000000000000000001 + 1", "(1 + 2", "1 + 2 *"] {
+            let tokenizer = MathExpressionTokenizer::new(source)
+                .unwrap()
+                .with_config(TokenizerConfig::strict());
+            let result = MathExpressionParser::new().parse_verbose(tokenizer);
+            assert!(result.is_err(), "{source}");
+        }
+    }
+}
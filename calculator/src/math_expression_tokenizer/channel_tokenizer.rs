@@ -0,0 +1,207 @@
+use std::cell::RefCell;
+use std::sync::mpsc::{Receiver, Sender};
+
+use super::{MathExpressionTokenizer, MathExpressionTokenizerError, Token, TokenizerTraits};
+use crate::position::Position;
+
+// A token with the positions of its first byte and of the byte after it.
+pub type TokenMessage = Result<(Position, Position, Token), MathExpressionTokenizerError>;
+
+// Tokenizes `expr` and sends every token (or the first error) through `tx`.
+// Sending stops silently if the receiving side has been dropped. Char offsets
+// are counted incrementally from the previous token.
+pub fn tokenize_into(expr: String, tx: Sender<TokenMessage>) {
+    let mut tokenizer = match MathExpressionTokenizer::new(expr) {
+        Ok(tokenizer) => tokenizer,
+        Err(err) => {
+            let _ = tx.send(Err(err));
+            return;
+        }
+    };
+
+    let mut last = Position::default();
+    while tokenizer.has_token() {
+        let message = tokenizer.next_spanned().map(|(span, token)| {
+            let advance = |from: Position, to: usize| {
+                Position::new(
+                    to,
+                    from.char + tokenizer.expr[from.byte..to].chars().count(),
+                )
+            };
+            let start = advance(last, span.start);
+            last = advance(start, span.end);
+            (start, last, token)
+        });
+        let failed = message.is_err();
+
+        if tx.send(message).is_err() || failed {
+            return;
+        }
+    }
+}
+
+// Consuming half of `tokenize_into`. A closed channel is reported as `NoToken`.
+// Like the receiver it wraps, it is `Send` but not `Sync`.
+pub struct ChannelTokenizer {
+    rx: Receiver<TokenMessage>,
+    pending: RefCell<Option<TokenMessage>>,
+    curr_idx: usize,
+    seen: Vec<Position>,
+}
+
+impl ChannelTokenizer {
+    pub fn new(rx: Receiver<TokenMessage>) -> Self {
+        Self {
+            rx,
+            pending: RefCell::new(None),
+            curr_idx: 0,
+            seen: Vec::new(),
+        }
+    }
+
+    fn receive(&self) -> Option<TokenMessage> {
+        self.pending
+            .borrow_mut()
+            .take()
+            .or_else(|| self.rx.recv().ok())
+    }
+}
+
+impl TokenizerTraits for ChannelTokenizer {
+    fn has_token(&self) -> bool {
+        let mut pending = self.pending.borrow_mut();
+        if pending.is_none() {
+            *pending = self.rx.recv().ok();
+        }
+
+        pending.is_some()
+    }
+
+    fn next_token(&mut self) -> Result<(usize, Token), MathExpressionTokenizerError> {
+        let (start, end, token) = self
+            .receive()
+            .ok_or(MathExpressionTokenizerError::NoToken)??;
+        self.seen.extend([start, end]);
+        self.curr_idx = end.byte;
+
+        Ok((start.byte, token))
+    }
+
+    fn curr_index(&self) -> usize {
+        self.curr_idx
+    }
+
+    // Exact at the start and end of every token received so far, which are
+    // the only offsets this tokenizer hands out. Other offsets are counted
+    // from the closest of them before `byte`, as if the text between were
+    // ASCII.
+    fn position_of(&self, byte: usize) -> Position {
+        let seen = self.seen.partition_point(|position| position.byte <= byte);
+        let base = seen
+            .checked_sub(1)
+            .map_or_else(Position::default, |i| self.seen[i]);
+        Position::new(byte, base.char + (byte - base.byte))
+    }
+}
+
+// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math_expression_parser::MathExpressionParser;
+    use std::sync::mpsc;
+    use std::thread;
+
+    fn assert_send_static<T: Send + 'static>() {}
+
+    fn parse_in_process(expr: &str) -> Result<Vec<Token>, String> {
+        let tokenizer =
+            MathExpressionTokenizer::new(expr.to_string()).map_err(|e| e.to_string())?;
+        MathExpressionParser::new()
+            .parse(tokenizer)
+            .map(|parsed| parsed.tokens().to_vec())
+            .map_err(|e| e.to_string())
+    }
+
+    fn parse_through_channel(expr: &str) -> Result<Vec<Token>, String> {
+        let (tx, rx) = mpsc::channel();
+        let expr = expr.to_string();
+        let producer = thread::spawn(move || tokenize_into(expr, tx));
+
+        let result = MathExpressionParser::new()
+            .parse(ChannelTokenizer::new(rx))
+            .map(|parsed| parsed.tokens().to_vec())
+            .map_err(|e| e.to_string());
+
+        producer.join().unwrap();
+        result
+    }
+
+    #[test]
+    fn test_types_are_send() {
+        assert_send_static::<Token>();
+        assert_send_static::<MathExpressionTokenizerError>();
+        assert_send_static::<crate::math_expression_parser::MathExpressionParserError>();
+        assert_send_static::<TokenMessage>();
+    }
+
+    #[test]
+    fn test_closed_channel_is_no_token() {
+        let (tx, rx) = mpsc::channel::<TokenMessage>();
+        drop(tx);
+
+        let mut tokenizer = ChannelTokenizer::new(rx);
+        assert!(!tokenizer.has_token());
+        assert!(matches!(
+            tokenizer.next_token(),
+            Err(MathExpressionTokenizerError::NoToken)
+        ));
+    }
+
+    #[test]
+    fn test_indices_match_in_process() {
+        for expr in ["12.5 * (3 - 1)", "\u{3000}12\u{3000}+\u{3000}3.25e2 )"] {
+            let (tx, rx) = mpsc::channel();
+            tokenize_into(expr.to_string(), tx);
+            let mut channel = ChannelTokenizer::new(rx);
+            let mut local = MathExpressionTokenizer::new(expr.to_string()).unwrap();
+
+            while local.has_token() {
+                let (idx, token) = local.next_token().unwrap();
+                assert_eq!(channel.next_token(), Ok((idx, token)), "{expr}");
+                assert_eq!(channel.curr_index(), local.curr_index(), "{expr}");
+                for byte in [idx, local.curr_index()] {
+                    assert_eq!(channel.position_of(byte), local.position_of(byte));
+                }
+            }
+            assert!(!channel.has_token());
+        }
+    }
+
+    #[test]
+    fn test_channel_matches_in_process() {
+        let expressions = [
+            "1",
+            "(12.5 + 3) * 2",
+            "((1+2)*3)/4",
+            "1 +",
+            "1 + (",
+            "(1 + 2",
+            "1 + 2)",
+            "1 $ 2",
+            "* 1",
+            "1 2",
+            "\u{3000}1 + * 2",
+            "\u{3000}(1\u{3000}+ 2",
+            "\u{3000}1\u{3000})",
+        ];
+
+        for expr in expressions {
+            assert_eq!(
+                parse_through_channel(expr),
+                parse_in_process(expr),
+                "{expr}"
+            );
+        }
+    }
+}
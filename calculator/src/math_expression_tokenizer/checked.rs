@@ -0,0 +1,125 @@
+// Test wrapper that checks every index and position a tokenizer hands out
+// against the source: each must be a char boundary within it, token starts
+// must increase, and positions must count the chars before their byte.
+
+use super::{MathExpressionTokenizerError, Token, TokenizerTraits};
+use crate::position::Position;
+
+pub(crate) struct CheckedTokenizer<T> {
+    inner: T,
+    source: String,
+    last_idx: Option<usize>,
+}
+
+impl<T: TokenizerTraits> CheckedTokenizer<T> {
+    pub(crate) fn new(inner: T, source: &str) -> Self {
+        Self {
+            inner,
+            source: source.to_string(),
+            last_idx: None,
+        }
+    }
+
+    fn check_index(&self, idx: usize, what: &str) {
+        assert!(
+            self.source.is_char_boundary(idx),
+            "{what} {idx} is not a char boundary of {:?}",
+            self.source
+        );
+    }
+
+    fn check_position(&self, position: Position) {
+        self.check_index(position.byte, "position");
+        assert_eq!(
+            position.char,
+            self.source[..position.byte].chars().count(),
+            "char offset of byte {} in {:?}",
+            position.byte,
+            self.source
+        );
+    }
+
+    fn check_error(&self, err: &MathExpressionTokenizerError) {
+        match err {
+            MathExpressionTokenizerError::ConsecutiveOperators {
+                first_idx,
+                second_idx,
+            } => {
+                self.check_position(*first_idx);
+                self.check_position(*second_idx);
+            }
+            err => {
+                if let Some(idx) = err.idx() {
+                    self.check_position(idx);
+                }
+            }
+        }
+    }
+}
+
+impl<T: TokenizerTraits> TokenizerTraits for CheckedTokenizer<T> {
+    fn has_token(&self) -> bool {
+        self.inner.has_token()
+    }
+
+    fn next_token(&mut self) -> Result<(usize, Token), MathExpressionTokenizerError> {
+        let result = self.inner.next_token();
+        match &result {
+            Ok((idx, _)) => {
+                self.check_index(*idx, "token index");
+                assert!(
+                    self.last_idx.is_none_or(|last| last < *idx),
+                    "token index {idx} does not follow {:?} in {:?}",
+                    self.last_idx,
+                    self.source
+                );
+                self.last_idx = Some(*idx);
+            }
+            Err(err) => self.check_error(err),
+        }
+        self.check_index(self.inner.curr_index(), "current index");
+
+        result
+    }
+
+    fn curr_index(&self) -> usize {
+        self.inner.curr_index()
+    }
+
+    fn position_of(&self, byte: usize) -> Position {
+        let position = self.inner.position_of(byte);
+        self.check_position(position);
+        position
+    }
+}
+
+// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math_expression_parser::MathExpressionParser;
+    use crate::math_expression_tokenizer::{MathExpressionTokenizer, TokenizerConfig};
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn test_multibyte_input_keeps_boundaries(
+            source in "[0-9+\\-*/(). é€🙂\u{3000}×÷（）]{1,48}",
+            strict in any::<bool>(),
+        ) {
+            let config = if strict { TokenizerConfig::strict() } else { TokenizerConfig::default() };
+            let Ok(tokenizer) = MathExpressionTokenizer::new(source.clone()) else {
+                return Ok(());
+            };
+
+            // Tokens past an error are still checked, as the tokenizer skips it.
+            let mut checked = CheckedTokenizer::new(tokenizer.with_config(config), &source);
+            while checked.has_token() {
+                let _ = checked.next_token();
+            }
+
+            let tokenizer = MathExpressionTokenizer::new(source.clone()).unwrap();
+            let _ = MathExpressionParser::new().parse(CheckedTokenizer::new(tokenizer, &source));
+        }
+    }
+}
@@ -0,0 +1,22 @@
+// Optional checks of the tokenizer. The default configuration accepts every
+// input the tokenizer has always accepted; `strict` turns every check into an
+// error.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TokenizerConfig {
+    // Report literals that do not survive the conversion to `f64` as
+    // `PrecisionLoss` errors instead of warnings.
+    pub reject_precision_loss: bool,
+    // Report an operator directly followed by '*' or '/' as
+    // `ConsecutiveOperators`. A following '+' or '-' is a unary operator for
+    // the parser, so `2 * -3` and `1 ++ 2` are never rejected.
+    pub reject_consecutive_operators: bool,
+}
+
+impl TokenizerConfig {
+    pub fn strict() -> Self {
+        Self {
+            reject_precision_loss: true,
+            reject_consecutive_operators: true,
+        }
+    }
+}
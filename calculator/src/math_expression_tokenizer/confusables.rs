@@ -0,0 +1,173 @@
+use crate::position::Position;
+
+// Characters that are commonly typed or pasted in place of a valid one: `x`
+// for multiplication, typographic dashes for minus, brackets for parentheses.
+// A ',' is only a decimal separator between two digits; elsewhere it is not
+// suggested as anything.
+const CONFUSABLES: &[(char, char)] = &[
+    ('x', '*'),
+    ('X', '*'),
+    ('×', '*'),
+    ('·', '*'),
+    ('÷', '/'),
+    ('∕', '/'),
+    ('–', '-'),
+    ('—', '-'),
+    ('−', '-'),
+    ('[', '('),
+    (']', ')'),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Fix {
+    pub idx: Position,
+    pub from: char,
+    pub to: char,
+}
+
+// The character `ch` was probably meant to be, given its neighbours.
+pub(crate) fn suggest(ch: char, prev: Option<char>, next: Option<char>) -> Option<char> {
+    if ch == ',' {
+        let is_digit = |ch: Option<char>| ch.is_some_and(|ch| ch.is_ascii_digit());
+        return (is_digit(prev) && is_digit(next)).then_some('.');
+    }
+
+    CONFUSABLES
+        .iter()
+        .find(|&&(confusable, _)| confusable == ch)
+        .map(|&(_, suggestion)| suggestion)
+}
+
+// Replaces every confusable character of `expr` with its suggestion, e.g.
+// before tokenizing pasted input, and reports each replacement with its
+// position in `expr`.
+pub fn fix_confusables(expr: &str) -> (String, Vec<Fix>) {
+    let mut fixed = String::with_capacity(expr.len());
+    let mut fixes = Vec::new();
+    let mut prev = None;
+    let mut chars = expr.char_indices().enumerate().peekable();
+
+    while let Some((char_idx, (byte_idx, ch))) = chars.next() {
+        let next = chars.peek().map(|&(_, (_, next))| next);
+        match suggest(ch, prev, next) {
+            Some(to) => {
+                fixed.push(to);
+                fixes.push(Fix {
+                    idx: Position::new(byte_idx, char_idx),
+                    from: ch,
+                    to,
+                });
+            }
+            None => fixed.push(ch),
+        }
+        prev = Some(ch);
+    }
+
+    (fixed, fixes)
+}
+
+// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math_expression_tokenizer::{
+        MathExpressionTokenizer, MathExpressionTokenizerError, TokenizerTraits,
+    };
+
+    fn suggestion_for(expr: &str) -> Option<char> {
+        let mut tokenizer = MathExpressionTokenizer::new(expr.to_string()).unwrap();
+        loop {
+            match tokenizer.next_token() {
+                Ok(_) => {}
+                Err(MathExpressionTokenizerError::InvalidToken { suggestion, .. }) => {
+                    return suggestion;
+                }
+                Err(err) => panic!("unexpected error for {expr:?}: {err}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_every_table_entry_is_suggested() {
+        for &(confusable, expected) in CONFUSABLES {
+            let expr = format!("2 {confusable} 3");
+            assert_eq!(suggestion_for(&expr), Some(expected), "{expr}");
+        }
+    }
+
+    #[test]
+    fn test_comma_only_between_digits() {
+        assert_eq!(suggestion_for("1,5"), Some('.'));
+        assert_eq!(suggestion_for("1 ,5"), None);
+        assert_eq!(suggestion_for("1, 5"), None);
+        assert_eq!(suggestion_for("(1),5"), None);
+    }
+
+    #[test]
+    fn test_unknown_characters_have_no_suggestion() {
+        for expr in ["1 $ 2", "1 + é", "a", "1 ? 2", "1 “2”", "1 & 2"] {
+            assert_eq!(suggestion_for(expr), None, "{expr}");
+        }
+    }
+
+    #[test]
+    fn test_suggestion_in_message() {
+        let err = MathExpressionTokenizer::new("2 x 3")
+            .unwrap()
+            .nth(1)
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Found invalid token 'x' at position 3, did you mean '*'?"
+        );
+    }
+
+    #[test]
+    fn test_fix_confusables() {
+        let (fixed, fixes) = fix_confusables("[1,5 × 2] – 3,x");
+        assert_eq!(fixed, "(1.5 * 2) - 3,*");
+        assert_eq!(
+            fixes,
+            vec![
+                Fix {
+                    idx: Position::new(0, 0),
+                    from: '[',
+                    to: '(',
+                },
+                Fix {
+                    idx: Position::new(2, 2),
+                    from: ',',
+                    to: '.',
+                },
+                Fix {
+                    idx: Position::new(5, 5),
+                    from: '×',
+                    to: '*',
+                },
+                Fix {
+                    idx: Position::new(9, 8),
+                    from: ']',
+                    to: ')',
+                },
+                Fix {
+                    idx: Position::new(11, 10),
+                    from: '–',
+                    to: '-',
+                },
+                Fix {
+                    idx: Position::new(17, 14),
+                    from: 'x',
+                    to: '*',
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fixed_input_parses() {
+        let (fixed, _) = fix_confusables("2 x [3 − 1,5]");
+        assert!(fixed.parse::<crate::MathExpression>().is_ok(), "{fixed}");
+    }
+}
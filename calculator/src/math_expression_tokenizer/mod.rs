@@ -1,53 +1,596 @@
+pub mod channel_tokenizer;
+#[cfg(test)]
+pub(crate) mod checked;
+mod config;
+mod confusables;
+mod peekable;
+mod precision;
+pub(crate) mod scan;
+
+pub use config::TokenizerConfig;
+pub(crate) use confusables::suggest;
+pub use confusables::{Fix, fix_confusables};
+pub use peekable::PeekableTokenizer;
+
+use crate::diagnostic::{Diagnostic, Label};
+use crate::error_code::ErrorCode;
+use crate::position::{Position, Span};
+
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::fmt::{self, Write};
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
 use thiserror::Error;
 
-#[derive(Debug, PartialEq)]
+// Tokens and tokenizer errors are `Send + Sync`.
+// Digit payloads are compared by their bit pattern, so equality is reflexive
+// (a NaN equals the same NaN) and `0.0 != -0.0`. Use `approx_eq` to compare
+// values numerically.
+// Deserialized digits go through `Token::digit`, so NaN is rejected there too.
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "UncheckedToken")
+)]
 pub enum Token {
     Digit(f64),
     Operator(char),
+    // Never produced by the tokenizer: the parser turns a '+' or '-' that
+    // starts an operand into a unary operator.
+    UnaryOperator(char),
+    OpenBrace,
+    CloseBrace,
+}
+
+// The serialized form of `Token`, before digits are checked.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+enum UncheckedToken {
+    Digit(f64),
+    Operator(char),
+    UnaryOperator(char),
     OpenBrace,
     CloseBrace,
 }
 
-#[derive(Debug, Error)]
+#[cfg(feature = "serde")]
+impl TryFrom<UncheckedToken> for Token {
+    type Error = TokenError;
+
+    fn try_from(token: UncheckedToken) -> Result<Self, Self::Error> {
+        Ok(match token {
+            UncheckedToken::Digit(value) => return Token::digit(value),
+            UncheckedToken::Operator(op) => Token::Operator(op),
+            UncheckedToken::UnaryOperator(op) => Token::UnaryOperator(op),
+            UncheckedToken::OpenBrace => Token::OpenBrace,
+            UncheckedToken::CloseBrace => Token::CloseBrace,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[non_exhaustive]
+pub enum TokenError {
+    #[error("A digit cannot be NaN")]
+    NotANumber,
+}
+
+// Characters tokenized as `Token::Operator`.
+pub(crate) const OPERATORS: [char; 6] = ['+', '-', '*', '/', '%', '^'];
+
+// Token positions are stored as `u32`, so longer input is rejected up front.
+pub const MAX_INPUT_LEN: usize = u32::MAX as usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[non_exhaustive]
+pub enum ArgumentError {
+    #[error("input is empty")]
+    Empty,
+    #[error("input contains only whitespace")]
+    Blank,
+    #[error("input is {actual} bytes long, the limit is {limit}")]
+    TooLong { limit: usize, actual: usize },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[non_exhaustive]
 pub enum MathExpressionTokenizerError {
-    #[error("Invalid argument")]
-    InvalidArgument,
-    #[error("Found invalid token '{ch}' at position {idx}")]
-    InvalidToken { idx: usize, ch: char },
+    #[error("Invalid argument: {reason}")]
+    InvalidArgument { reason: ArgumentError },
+    // `suggestion` is the character `ch` was probably meant to be, e.g. '*'
+    // for 'x'.
+    #[error("Found invalid token '{ch}' at position {idx}{}", describe_suggestion(.suggestion))]
+    InvalidToken {
+        idx: Position,
+        ch: char,
+        suggestion: Option<char>,
+    },
     #[error("Token not found")]
     NoToken,
+    #[error(
+        "Malformed number at position {idx}: a number has at most one decimal point \
+         and a digit on at least one side of it"
+    )]
+    MalformedNumber { idx: Position },
+    #[error(
+        "Operator at position {second_idx} directly follows the operator at position {first_idx}"
+    )]
+    ConsecutiveOperators {
+        first_idx: Position,
+        second_idx: Position,
+    },
+    #[error("Literal '{literal}' at position {idx} cannot be represented exactly")]
+    PrecisionLoss { idx: Position, literal: String },
+    // Only reported when a single token is expected, e.g. by `Token::from_str`.
+    #[error("Unexpected trailing input at position {idx}")]
+    TrailingInput { idx: Position },
+}
+
+fn describe_suggestion(suggestion: &Option<char>) -> String {
+    suggestion.map_or_else(String::new, |suggestion| {
+        format!(", did you mean '{suggestion}'?")
+    })
 }
 
+// Problems that do not stop tokenization, collected by the tokenizer and
+// retrieved with `take_warnings`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TokenizerWarning {
+    // The literal was rounded to the nearest `f64`.
+    PrecisionLoss { idx: Position, literal: String },
+    // The literal is too large for `f64` and became infinity.
+    Overflow { idx: Position, literal: String },
+}
+
+impl PartialEq for Token {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Token::Digit(lhs), Token::Digit(rhs)) => lhs.to_bits() == rhs.to_bits(),
+            (Token::Operator(lhs), Token::Operator(rhs))
+            | (Token::UnaryOperator(lhs), Token::UnaryOperator(rhs)) => lhs == rhs,
+            (Token::OpenBrace, Token::OpenBrace) | (Token::CloseBrace, Token::CloseBrace) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Token {}
+
+impl Hash for Token {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Token::Digit(number) => number.to_bits().hash(state),
+            Token::Operator(op) | Token::UnaryOperator(op) => op.hash(state),
+            Token::OpenBrace | Token::CloseBrace => {}
+        }
+    }
+}
+
+// Prints the token as it is written in an expression.
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Digit(number) => write_number(f, *number),
+            Token::Operator(op) | Token::UnaryOperator(op) => f.write_char(*op),
+            Token::OpenBrace => f.write_char('('),
+            Token::CloseBrace => f.write_char(')'),
+        }
+    }
+}
+
+// `{}` prints the shortest decimal that parses back to the same `f64`.
+// Infinity is printed as `1e309`, the shortest literal that overflows again.
+// NaN and negative digits have no literal; they only come out of decoded
+// expressions and do not round-trip.
+pub(crate) fn write_number(out: &mut impl Write, number: f64) -> fmt::Result {
+    if number.is_infinite() {
+        if number.is_sign_negative() {
+            out.write_char('-')?;
+        }
+        return write!(out, "1e{}", f64::MAX_10_EXP + 1);
+    }
+
+    write!(out, "{number}")
+}
+
+impl Token {
+    // Checked `Token::Digit`. NaN is rejected: it has no literal, breaks
+    // comparisons and poisons results. Infinity is accepted, as the tokenizer
+    // produces it for literals that overflow (with a warning), and so are
+    // negative values, which decoded or edited expressions may hold. The
+    // tokenizer only reads digits and points, so it never produces NaN.
+    pub fn digit(value: f64) -> Result<Token, TokenError> {
+        if value.is_nan() {
+            return Err(TokenError::NotANumber);
+        }
+
+        Ok(Token::Digit(value))
+    }
+
+    // Digits are equal when they differ by at most `epsilon`; NaN is never
+    // approximately equal to anything. Other tokens compare as with `==`.
+    pub fn approx_eq(&self, other: &Token, epsilon: f64) -> bool {
+        match (self, other) {
+            (Token::Digit(lhs), Token::Digit(rhs)) => (lhs - rhs).abs() <= epsilon,
+            _ => self == other,
+        }
+    }
+}
+
+/// Parses exactly one token, surrounded by optional whitespace.
+///
+/// ```
+/// use calculator::{MathExpressionTokenizerError, Position, Token};
+///
+/// assert_eq!("3.5".parse::<Token>(), Ok(Token::Digit(3.5)));
+/// assert_eq!(" ( ".parse::<Token>(), Ok(Token::OpenBrace));
+///
+/// // Anything after the first token is an error, at where it starts.
+/// assert_eq!(
+///     "12 3".parse::<Token>(),
+///     Err(MathExpressionTokenizerError::TrailingInput {
+///         idx: Position::new(3, 3)
+///     })
+/// );
+/// ```
+impl FromStr for Token {
+    type Err = MathExpressionTokenizerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokenizer = MathExpressionTokenizer::new(s.to_string())?;
+        let (_, token) = tokenizer.next_token()?;
+
+        if tokenizer.has_token() {
+            let idx = tokenizer.skip_spaces();
+            return Err(MathExpressionTokenizerError::TrailingInput {
+                idx: tokenizer.position_of(idx),
+            });
+        }
+
+        Ok(token)
+    }
+}
+
+impl TryFrom<&str> for Token {
+    type Error = MathExpressionTokenizerError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl MathExpressionTokenizerError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            MathExpressionTokenizerError::InvalidArgument { .. } => {
+                ErrorCode::TokenizerInvalidArgument
+            }
+            MathExpressionTokenizerError::InvalidToken { .. } => ErrorCode::TokenizerInvalidToken,
+            MathExpressionTokenizerError::NoToken => ErrorCode::TokenizerNoToken,
+            MathExpressionTokenizerError::PrecisionLoss { .. } => ErrorCode::TokenizerPrecisionLoss,
+            MathExpressionTokenizerError::MalformedNumber { .. } => {
+                ErrorCode::TokenizerMalformedNumber
+            }
+            MathExpressionTokenizerError::ConsecutiveOperators { .. } => {
+                ErrorCode::TokenizerConsecutiveOperators
+            }
+            MathExpressionTokenizerError::TrailingInput { .. } => ErrorCode::TokenizerTrailingInput,
+        }
+    }
+
+    // Position in the input the error refers to, if any.
+    pub fn idx(&self) -> Option<Position> {
+        match self {
+            MathExpressionTokenizerError::InvalidToken { idx, .. }
+            | MathExpressionTokenizerError::PrecisionLoss { idx, .. }
+            | MathExpressionTokenizerError::MalformedNumber { idx }
+            | MathExpressionTokenizerError::TrailingInput { idx }
+            | MathExpressionTokenizerError::ConsecutiveOperators {
+                second_idx: idx, ..
+            } => Some(*idx),
+            MathExpressionTokenizerError::InvalidArgument { .. }
+            | MathExpressionTokenizerError::NoToken => None,
+        }
+    }
+
+    pub fn to_diagnostic(&self, source: &str) -> Diagnostic {
+        let (labels, help) = match self {
+            MathExpressionTokenizerError::InvalidArgument {
+                reason: ArgumentError::TooLong { .. },
+            } => (
+                vec![],
+                Some("split the input into smaller expressions".to_string()),
+            ),
+            MathExpressionTokenizerError::InvalidArgument { .. } => {
+                (vec![], Some("provide a non-empty expression".to_string()))
+            }
+            MathExpressionTokenizerError::InvalidToken {
+                idx, suggestion, ..
+            } => (
+                vec![Label::at_char(source, idx.byte, "invalid character")],
+                suggestion.map(|suggestion| format!("replace it with '{suggestion}'")),
+            ),
+            MathExpressionTokenizerError::NoToken => (
+                vec![Label::at_char(source, source.len(), "no token left here")],
+                None,
+            ),
+            MathExpressionTokenizerError::PrecisionLoss { idx, literal } => (
+                vec![Label::new(
+                    idx.byte..idx.byte + literal.len(),
+                    "this literal does not fit into an f64",
+                )],
+                Some("use at most 15 significant digits".to_string()),
+            ),
+            MathExpressionTokenizerError::MalformedNumber { idx } => (
+                vec![Label::at_char(source, idx.byte, "unexpected decimal point")],
+                Some("write numbers like `5`, `0.5`, `.5`, `5.` or `2.5e-3`".to_string()),
+            ),
+            MathExpressionTokenizerError::ConsecutiveOperators {
+                first_idx,
+                second_idx,
+            } => (
+                vec![
+                    Label::at_char(source, first_idx.byte, "first operator"),
+                    Label::at_char(source, second_idx.byte, "second operator"),
+                ],
+                Some("add an operand between the operators".to_string()),
+            ),
+            MathExpressionTokenizerError::TrailingInput { idx } => (
+                vec![Label::new(
+                    idx.byte..source.len(),
+                    "expected a single token",
+                )],
+                Some("remove everything after the first token".to_string()),
+            ),
+        };
+
+        Diagnostic {
+            code: self.code(),
+            message: self.to_string(),
+            labels,
+            help,
+        }
+    }
+}
+
+pub type TokenObserver = Box<dyn FnMut(usize, &Token) + Send>;
+
+// A tokenizer is `Send`, so it can be moved to another thread mid-iteration,
+// but it is intentionally not `Sync`: the observer is an `FnMut`.
+//
+// No method panics on any input: every `&str` either tokenizes or gives an
+// error, and so does parsing the tokens. Only a panicking observer, or a
+// position from another source passed to `position_of`, can panic.
 pub struct MathExpressionTokenizer {
-    expr: String,
+    expr: Cow<'static, str>,
     curr_byte_idx: usize,
+    // Result of the last whitespace scan as `(from, to)`, reused by
+    // `next_token` after a `has_token` call at the same position.
+    skipped: Cell<(usize, usize)>,
+    observer: Option<TokenObserver>,
+    config: TokenizerConfig,
+    warnings: Vec<TokenizerWarning>,
+    // Position of the previous token if it was an operator.
+    last_operator: Option<usize>,
+    // A token given back with `put_back`, returned again before reading on.
+    put_back: Option<(usize, Token)>,
 }
 
+// On error `next_token` moves past the offending character (or the whole
+// malformed literal), so a caller can keep pulling tokens to recover; the
+// error still carries the original index.
 pub trait TokenizerTraits {
     fn has_token(&self) -> bool;
     fn next_token(&mut self) -> Result<(usize, Token), MathExpressionTokenizerError>;
     fn curr_index(&self) -> usize;
+
+    // Converts a byte offset produced by this tokenizer into a `Position`.
+    // The default is only correct for ASCII input, where both are equal;
+    // tokenizers that see the source override it.
+    fn position_of(&self, byte: usize) -> Position {
+        Position::new(byte, byte)
+    }
+
+    // Gives back the token `next_token` just returned, so the following call
+    // returns it again; the parser does this with a token it rejects. The
+    // default drops the token, for tokenizers that cannot hold on to it.
+    fn put_back(&mut self, idx: usize, token: Token) {
+        let _ = (idx, token);
+    }
+}
+
+impl<T: TokenizerTraits + ?Sized> TokenizerTraits for &mut T {
+    fn has_token(&self) -> bool {
+        (**self).has_token()
+    }
+
+    fn next_token(&mut self) -> Result<(usize, Token), MathExpressionTokenizerError> {
+        (**self).next_token()
+    }
+
+    fn curr_index(&self) -> usize {
+        (**self).curr_index()
+    }
+
+    fn position_of(&self, byte: usize) -> Position {
+        (**self).position_of(byte)
+    }
+
+    fn put_back(&mut self, idx: usize, token: Token) {
+        (**self).put_back(idx, token);
+    }
+}
+
+impl<T: TokenizerTraits + ?Sized> TokenizerTraits for Box<T> {
+    fn has_token(&self) -> bool {
+        (**self).has_token()
+    }
+
+    fn next_token(&mut self) -> Result<(usize, Token), MathExpressionTokenizerError> {
+        (**self).next_token()
+    }
+
+    fn curr_index(&self) -> usize {
+        (**self).curr_index()
+    }
+
+    fn position_of(&self, byte: usize) -> Position {
+        (**self).position_of(byte)
+    }
+
+    fn put_back(&mut self, idx: usize, token: Token) {
+        (**self).put_back(idx, token);
+    }
 }
 
 impl TokenizerTraits for MathExpressionTokenizer {
     fn has_token(&self) -> bool {
+        if self.put_back.is_some() {
+            return true;
+        }
         let idx = self.skip_spaces();
-        return idx < self.expr.len();
+        idx < self.expr.len()
     }
 
+    // The start of a token that was put back, which is where reading resumes.
     fn curr_index(&self) -> usize {
-        self.curr_byte_idx
+        match &self.put_back {
+            Some((idx, _)) => *idx,
+            None => self.curr_byte_idx,
+        }
     }
 
+    fn position_of(&self, byte: usize) -> Position {
+        debug_assert!(self.expr.is_char_boundary(byte), "byte {byte}");
+        Position::in_source(&self.expr, byte)
+    }
+
+    // A token that was put back has been seen by the observer already.
     fn next_token(&mut self) -> Result<(usize, Token), MathExpressionTokenizerError> {
+        if let Some(put_back) = self.put_back.take() {
+            return Ok(put_back);
+        }
+
+        let result = self.read_token();
+        // Errors skip past the offending input, which must end on a boundary
+        // too, or the next token would be read from inside a char.
+        debug_assert!(self.expr.is_char_boundary(self.curr_byte_idx));
+        let (idx, token) = result?;
+        debug_assert!(self.expr.is_char_boundary(idx), "token at byte {idx}");
+        profile!(stats => stats.tokens += 1);
+
+        if let Some(observer) = self.observer.as_mut() {
+            observer(idx, &token);
+        }
+
+        Ok((idx, token))
+    }
+
+    fn put_back(&mut self, idx: usize, token: Token) {
+        self.put_back = Some((idx, token));
+    }
+}
+
+// Iteration stops after the first error, which is yielded as the last item.
+impl Iterator for MathExpressionTokenizer {
+    type Item = Result<(usize, Token), MathExpressionTokenizerError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
         if !self.has_token() {
-            return Err(MathExpressionTokenizerError::NoToken);
+            return None;
+        }
+
+        let item = self.next_token();
+        if item.is_err() {
+            self.curr_byte_idx = self.expr.len();
         }
 
-        self.curr_byte_idx = self.skip_spaces();
+        Some(item)
+    }
+}
+
+impl MathExpressionTokenizer {
+    // Borrowed `&'static str` input is tokenized without copying it. Empty and
+    // whitespace-only input is rejected.
+    pub fn new(expr: impl Into<Cow<'static, str>>) -> Result<Self, MathExpressionTokenizerError> {
+        let expr = expr.into();
+        validate_input(&expr, MAX_INPUT_LEN)
+            .map_err(|reason| MathExpressionTokenizerError::InvalidArgument { reason })?;
+
+        Ok(Self {
+            expr,
+            curr_byte_idx: 0,
+            skipped: Cell::new((usize::MAX, 0)),
+            observer: None,
+            config: TokenizerConfig::default(),
+            warnings: Vec::new(),
+            last_operator: None,
+            put_back: None,
+        })
+    }
+
+    // The observer sees every successfully produced token, in order, before it
+    // is returned to the caller. Errors are not reported to it.
+    pub fn with_observer(
+        expr: impl Into<Cow<'static, str>>,
+        observer: impl FnMut(usize, &Token) + Send + 'static,
+    ) -> Result<Self, MathExpressionTokenizerError> {
+        let mut tokenizer = Self::new(expr)?;
+        tokenizer.observer = Some(Box::new(observer));
+        Ok(tokenizer)
+    }
+
+    pub fn with_config(mut self, config: TokenizerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    // Same as `next_token`, with the span of the token's text.
+    pub fn next_spanned(&mut self) -> Result<(Span, Token), MathExpressionTokenizerError> {
+        let (idx, token) = self.next_token()?;
+        Ok((Span::new(idx, self.curr_byte_idx), token))
+    }
+
+    // Returns the warnings produced so far and clears them.
+    pub fn take_warnings(&mut self) -> Vec<TokenizerWarning> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    fn read_token(&mut self) -> Result<(usize, Token), MathExpressionTokenizerError> {
+        let last_operator = self.last_operator.take();
+        let (idx, token) = self.read_any_token()?;
+
+        if let Token::Operator(op) = token {
+            if let Some(first_idx) = last_operator
+                && self.config.reject_consecutive_operators
+                && matches!(op, '*' | '/' | '%' | '^')
+            {
+                return Err(MathExpressionTokenizerError::ConsecutiveOperators {
+                    first_idx: self.position_of(first_idx),
+                    second_idx: self.position_of(idx),
+                });
+            }
+            self.last_operator = Some(idx);
+        }
+
+        Ok((idx, token))
+    }
+
+    fn read_any_token(&mut self) -> Result<(usize, Token), MathExpressionTokenizerError> {
+        let start = self.skip_spaces();
+        let Some(ch) = self.expr[start..].chars().next() else {
+            return Err(MathExpressionTokenizerError::NoToken);
+        };
+
+        self.curr_byte_idx = start;
         let old_value = self.curr_byte_idx;
 
-        match self.expr[self.curr_byte_idx..].chars().next().unwrap() {
+        match ch {
             '(' => Ok((
                 std::mem::replace(&mut self.curr_byte_idx, old_value + 1),
                 Token::OpenBrace,
@@ -56,12 +599,46 @@ impl TokenizerTraits for MathExpressionTokenizer {
                 std::mem::replace(&mut self.curr_byte_idx, old_value + 1),
                 Token::CloseBrace,
             )),
-            op @ ('+' | '-' | '*' | '/') => Ok((
+            op if OPERATORS.contains(&op) => Ok((
                 std::mem::replace(&mut self.curr_byte_idx, old_value + 1),
                 Token::Operator(op),
             )),
             _ => {
-                let (digit, idx) = self.parse_digits()?;
+                let (digit, idx) = self.parse_digits().inspect_err(|err| {
+                    let literal_end = old_value + scan::literal_len(&self.expr[old_value..]);
+                    self.curr_byte_idx = match err {
+                        MathExpressionTokenizerError::InvalidToken { idx, ch, .. } => {
+                            literal_end.max(idx.byte + ch.len_utf8())
+                        }
+                        _ => literal_end,
+                    };
+                })?;
+
+                let literal = &self.expr[old_value..idx];
+                if precision::is_lossy(&scan::without_separators(literal), digit) {
+                    let literal = literal.to_string();
+                    let position = self.position_of(old_value);
+                    if self.config.reject_precision_loss {
+                        self.curr_byte_idx = idx;
+                        return Err(MathExpressionTokenizerError::PrecisionLoss {
+                            idx: position,
+                            literal,
+                        });
+                    }
+
+                    self.warnings.push(if digit.is_infinite() {
+                        TokenizerWarning::Overflow {
+                            idx: position,
+                            literal,
+                        }
+                    } else {
+                        TokenizerWarning::PrecisionLoss {
+                            idx: position,
+                            literal,
+                        }
+                    });
+                }
+
                 Ok((
                     std::mem::replace(&mut self.curr_byte_idx, idx),
                     Token::Digit(digit),
@@ -69,44 +646,156 @@ impl TokenizerTraits for MathExpressionTokenizer {
             }
         }
     }
-}
 
-impl MathExpressionTokenizer {
-    pub fn new(expr: String) -> Result<Self, MathExpressionTokenizerError> {
-        if expr.is_empty() {
-            return Err(MathExpressionTokenizerError::InvalidArgument);
+    fn parse_digits(&self) -> Result<(f64, usize), MathExpressionTokenizerError> {
+        let s = &self.expr[self.curr_byte_idx..];
+
+        if let Some(radix) = scan::radix_prefix(s) {
+            return self.parse_radix(radix);
         }
 
-        Ok(Self {
-            expr,
-            curr_byte_idx: 0,
-        })
+        let offset = scan::digits_len(s);
+
+        if offset == 0 {
+            let mut chars = s.chars();
+            let Some(ch) = chars.next() else {
+                return Err(MathExpressionTokenizerError::NoToken);
+            };
+            let prev = self.expr[..self.curr_byte_idx].chars().next_back();
+            return Err(MathExpressionTokenizerError::InvalidToken {
+                idx: self.position_of(self.curr_byte_idx),
+                ch,
+                suggestion: confusables::suggest(ch, prev, chars.next()),
+            });
+        }
+
+        self.check_separators(&s[..scan::literal_len(s)], 10)?;
+        let parse = |literal: &str| scan::parse_number(&scan::without_separators(literal));
+
+        // An unfinished exponent is only reported after the digits before it
+        // are known to be fine, so the first problem is reported first.
+        let number = match scan::exponent_len(&s[offset..]) {
+            Ok(exponent) => {
+                parse(&s[..offset + exponent]).map(|number| (number, offset + exponent))
+            }
+            Err(marker) if parse(&s[..offset]).is_some() => {
+                return Err(self.missing_digit(offset + marker));
+            }
+            Err(_) => None,
+        };
+
+        // A run of digits and points only fails to parse when it has no digit
+        // or more than one point; the error points at the offending '.'.
+        match number {
+            Some((number, len)) => Ok((number, self.curr_byte_idx + len)),
+            None => {
+                let literal = &s[..offset];
+                let point = if literal.bytes().any(|byte| byte.is_ascii_digit()) {
+                    literal.match_indices('.').nth(1).map_or(0, |(idx, _)| idx)
+                } else {
+                    0
+                };
+
+                Err(MathExpressionTokenizerError::MalformedNumber {
+                    idx: self.position_of(self.curr_byte_idx + point),
+                })
+            }
+        }
     }
 
-    fn parse_digits(&self) -> Result<(f64, usize), MathExpressionTokenizerError> {
+    // An integer with a '0x' or '0b' prefix. A letter, digit or point right
+    // after its digits would belong to the literal but is not a digit of its
+    // radix, so it is reported rather than read as the next token.
+    fn parse_radix(&self, radix: u32) -> Result<(f64, usize), MathExpressionTokenizerError> {
         let s = &self.expr[self.curr_byte_idx..];
+        let digits = &s[scan::RADIX_PREFIX_LEN..];
+        let len = scan::radix_digits_len(digits, radix);
+        let end = scan::RADIX_PREFIX_LEN + len;
+        self.check_separators(&s[..end], radix)?;
 
-        let offset = s
-            .char_indices()
-            .find(|&(_, ch)| !ch.is_digit(10) && ch != '.')
-            .map(|(i, _)| i)
-            .unwrap_or(s.len());
-
-        match s[..offset].parse::<f64>() {
-            Ok(number) => Ok((number, self.curr_byte_idx + offset)),
-            Err(_) => Err(MathExpressionTokenizerError::InvalidToken {
-                idx: self.curr_byte_idx,
-                ch: s.chars().nth(0).unwrap(),
+        match s[end..].chars().next() {
+            Some(ch) if ch.is_ascii_alphanumeric() || ch == '.' => {
+                Err(MathExpressionTokenizerError::InvalidToken {
+                    idx: self.position_of(self.curr_byte_idx + end),
+                    ch,
+                    suggestion: None,
+                })
+            }
+            _ if len == 0 => Err(self.missing_digit(end)),
+            _ => Ok((
+                scan::parse_radix(&scan::without_separators(&digits[..len]), radix),
+                self.curr_byte_idx + end,
+            )),
+        }
+    }
+
+    // Misplaced separators are reported before anything else about the
+    // literal, which starts at the current index.
+    fn check_separators(
+        &self,
+        literal: &str,
+        radix: u32,
+    ) -> Result<(), MathExpressionTokenizerError> {
+        match scan::misplaced_separator(literal, radix) {
+            Some(offset) => Err(MathExpressionTokenizerError::InvalidToken {
+                idx: self.position_of(self.curr_byte_idx + offset),
+                ch: '_',
+                suggestion: None,
             }),
+            None => Ok(()),
+        }
+    }
+
+    // A literal that needs a digit `end` bytes into it but has none: an
+    // exponent marker or sign, or a radix prefix. The error points at what
+    // should have been a digit, or at the last character of the literal at
+    // the end of the term.
+    fn missing_digit(&self, end: usize) -> MathExpressionTokenizerError {
+        let s = &self.expr[self.curr_byte_idx..];
+        let found = s[end..]
+            .chars()
+            .next()
+            .filter(|ch| !ch.is_whitespace())
+            .map(|ch| (end, ch))
+            .or_else(|| s[..end].char_indices().next_back());
+        let Some((offset, ch)) = found else {
+            return MathExpressionTokenizerError::NoToken;
+        };
+
+        MathExpressionTokenizerError::InvalidToken {
+            idx: self.position_of(self.curr_byte_idx + offset),
+            ch,
+            suggestion: None,
         }
     }
 
     fn skip_spaces(&self) -> usize {
-        self.expr[self.curr_byte_idx..]
-            .char_indices()
-            .find(|(_, char)| !char.is_whitespace())
-            .map(|(idx, _)| self.curr_byte_idx + idx)
-            .unwrap_or(self.expr.len())
+        let (from, to) = self.skipped.get();
+        if from == self.curr_byte_idx {
+            return to;
+        }
+
+        let to = self.curr_byte_idx + scan::whitespace_len(&self.expr[self.curr_byte_idx..]);
+
+        profile!(stats => stats.whitespace_bytes += (to - self.curr_byte_idx) as u64);
+
+        self.skipped.set((self.curr_byte_idx, to));
+        to
+    }
+}
+
+fn validate_input(expr: &str, limit: usize) -> Result<(), ArgumentError> {
+    if expr.is_empty() {
+        Err(ArgumentError::Empty)
+    } else if expr.len() > limit {
+        Err(ArgumentError::TooLong {
+            limit,
+            actual: expr.len(),
+        })
+    } else if scan::whitespace_len(expr) == expr.len() {
+        Err(ArgumentError::Blank)
+    } else {
+        Ok(())
     }
 }
 
@@ -115,10 +804,185 @@ impl MathExpressionTokenizer {
 mod tests {
     use super::*;
     use proptest::prelude::*;
+    use std::sync::{Arc, Mutex};
+
+    fn hash_of(token: &Token) -> u64 {
+        use std::collections::hash_map::DefaultHasher;
+
+        let mut hasher = DefaultHasher::new();
+        token.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_digit_equality_by_bits() {
+        let nan = Token::Digit(f64::NAN);
+        assert_eq!(nan, nan.clone());
+        assert_eq!(hash_of(&nan), hash_of(&nan.clone()));
+        assert_ne!(nan, Token::Digit(-f64::NAN));
+
+        assert_ne!(Token::Digit(0.0), Token::Digit(-0.0));
+        assert_ne!(hash_of(&Token::Digit(0.0)), hash_of(&Token::Digit(-0.0)));
+
+        assert_ne!(Token::Operator('-'), Token::UnaryOperator('-'));
+        assert_ne!(Token::OpenBrace, Token::CloseBrace);
+    }
+
+    #[test]
+    fn test_checked_digit() {
+        assert_eq!(Token::digit(f64::NAN), Err(TokenError::NotANumber));
+        assert_eq!(Token::digit(-f64::NAN), Err(TokenError::NotANumber));
+        assert_eq!(Token::digit(1.5), Ok(Token::Digit(1.5)));
+        assert_eq!(Token::digit(f64::INFINITY), Ok(Token::Digit(f64::INFINITY)));
+        assert_eq!(Token::digit(-0.0), Ok(Token::Digit(-0.0)));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_deserialized_digits_are_checked() {
+        let tokens = [
+            Token::Digit(1.5),
+            Token::Operator('*'),
+            Token::UnaryOperator('-'),
+            Token::OpenBrace,
+            Token::CloseBrace,
+        ];
+        let json = serde_json::to_string(&tokens).unwrap();
+        assert_eq!(serde_json::from_str::<Vec<Token>>(&json).unwrap(), tokens);
+
+        // Not JSON, and not a token either way.
+        assert!(serde_json::from_str::<Token>(r#"{"Digit": NaN}"#).is_err());
+        // Formats that can carry NaN are stopped by `Token::digit`.
+        let from_map = |variant: &'static str, value: f64| {
+            use serde::Deserialize;
+            use serde::de::value::{Error, MapAccessDeserializer, MapDeserializer};
+
+            let map = MapDeserializer::<_, Error>::new([(variant, value)].into_iter());
+            Token::deserialize(MapAccessDeserializer::new(map)).map_err(|err| err.to_string())
+        };
+        assert_eq!(
+            from_map("Digit", f64::NAN),
+            Err("A digit cannot be NaN".to_string())
+        );
+        assert_eq!(from_map("Digit", -0.0), Ok(Token::Digit(-0.0)));
+        assert_eq!(
+            from_map("Digit", f64::INFINITY),
+            Ok(Token::Digit(f64::INFINITY))
+        );
+    }
+
+    #[test]
+    fn test_approx_eq() {
+        assert!(Token::Digit(0.0).approx_eq(&Token::Digit(-0.0), 0.0));
+        assert!(Token::Digit(0.1 + 0.2).approx_eq(&Token::Digit(0.3), 1e-12));
+        assert!(!Token::Digit(1.0).approx_eq(&Token::Digit(1.1), 1e-12));
+        assert!(!Token::Digit(f64::NAN).approx_eq(&Token::Digit(f64::NAN), f64::INFINITY));
+        assert!(Token::Operator('+').approx_eq(&Token::Operator('+'), 0.0));
+        assert!(!Token::Digit(1.0).approx_eq(&Token::OpenBrace, 1.0));
+    }
 
     #[test]
     fn test_empty_string_tokens() {
-        assert!(MathExpressionTokenizer::new("".to_string()).is_err());
+        let cases = [
+            ("", ArgumentError::Empty),
+            ("   ", ArgumentError::Blank),
+            ("\n\t", ArgumentError::Blank),
+            ("\u{a0}\u{2003}", ArgumentError::Blank),
+        ];
+
+        for (input, reason) in cases {
+            assert_eq!(
+                MathExpressionTokenizer::new(input).err(),
+                Some(MathExpressionTokenizerError::InvalidArgument { reason }),
+                "{input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_input_length_limit() {
+        assert_eq!(validate_input("1 + 2", 5), Ok(()));
+        assert_eq!(
+            validate_input("1 + 23", 5),
+            Err(ArgumentError::TooLong {
+                limit: 5,
+                actual: 6
+            })
+        );
+        assert_eq!(
+            MathExpressionTokenizerError::InvalidArgument {
+                reason: ArgumentError::TooLong {
+                    limit: 5,
+                    actual: 6
+                }
+            }
+            .to_string(),
+            "Invalid argument: input is 6 bytes long, the limit is 5"
+        );
+    }
+
+    #[test]
+    fn test_construct_from_string_types() {
+        let owned = "1 + 2".to_string();
+        let sources: [Cow<'static, str>; 3] =
+            ["1 + 2".into(), owned.into(), Cow::Borrowed("1 + 2")];
+
+        for source in sources {
+            let tokens: Vec<_> = MathExpressionTokenizer::new(source)
+                .unwrap()
+                .map(|item| item.unwrap().1)
+                .collect();
+            assert_eq!(
+                tokens,
+                vec![Token::Digit(1.0), Token::Operator('+'), Token::Digit(2.0)]
+            );
+        }
+
+        assert!(MathExpressionTokenizer::new("").is_err());
+        assert!(MathExpressionTokenizer::new(Cow::Borrowed("")).is_err());
+    }
+
+    #[test]
+    fn test_number_at_end_of_input() {
+        let tokens: Vec<_> = MathExpressionTokenizer::new("1 + 23.5")
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+
+        assert_eq!(tokens.last(), Some(&(4, Token::Digit(23.5))));
+    }
+
+    #[test]
+    fn test_number_followed_by_close_brace() {
+        let mut tokenizer = MathExpressionTokenizer::new("(12.25)").unwrap();
+
+        assert_eq!(tokenizer.next_token().unwrap(), (0, Token::OpenBrace));
+        assert_eq!(tokenizer.next_token().unwrap(), (1, Token::Digit(12.25)));
+        assert_eq!(tokenizer.curr_index(), 6);
+        assert_eq!(tokenizer.next_token().unwrap(), (6, Token::CloseBrace));
+        assert!(!tokenizer.has_token());
+    }
+
+    #[test]
+    fn test_long_whitespace_runs() {
+        let gap = " ".repeat(10_000);
+        let expr = format!("{gap}1{gap}+{gap}(2{gap}){gap}");
+        let mut tokenizer = MathExpressionTokenizer::new(expr.clone()).unwrap();
+
+        let mut tokens = vec![];
+        while tokenizer.has_token() {
+            let (idx, token) = tokenizer.next_token().unwrap();
+            assert!(!expr[idx..].starts_with(' '));
+            tokens.push(token);
+        }
+
+        assert_eq!(tokens.len(), 5);
+        assert_eq!(tokenizer.curr_index(), expr.len() - gap.len());
+        assert!(matches!(
+            tokenizer.next_token(),
+            Err(MathExpressionTokenizerError::NoToken)
+        ));
+        assert_eq!(tokenizer.curr_index(), expr.len() - gap.len());
     }
 
     #[test]
@@ -151,6 +1015,629 @@ mod tests {
         }
     }
 
+    fn assert_send<T: Send>() {}
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_thread_safety() {
+        assert_send_sync::<Token>();
+        assert_send_sync::<MathExpressionTokenizerError>();
+        assert_send::<MathExpressionTokenizer>();
+        assert_send::<channel_tokenizer::ChannelTokenizer>();
+    }
+
+    #[test]
+    fn test_tokenizer_moved_mid_iteration() {
+        let mut tokenizer =
+            MathExpressionTokenizer::with_observer("1 + 2".to_string(), |_, _| {}).unwrap();
+        assert_eq!(tokenizer.next_token().unwrap(), (0, Token::Digit(1.0)));
+
+        let rest = std::thread::spawn(move || tokenizer.collect::<Vec<_>>().len())
+            .join()
+            .unwrap();
+        assert_eq!(rest, 2);
+    }
+
+    #[test]
+    fn test_observer_records_tokens() {
+        let seen = Arc::new(Mutex::new(vec![]));
+        let recorder = Arc::clone(&seen);
+        let mut tokenizer = MathExpressionTokenizer::with_observer(
+            "(1.5 + 2) * 3 $".to_string(),
+            move |idx, token| {
+                recorder.lock().unwrap().push((idx, format!("{:?}", token)));
+            },
+        )
+        .unwrap();
+
+        let mut returned = vec![];
+        while let Ok((idx, token)) = tokenizer.next_token() {
+            returned.push((idx, format!("{:?}", token)));
+        }
+
+        assert_eq!(returned.len(), 7);
+        assert_eq!(*seen.lock().unwrap(), returned);
+    }
+
+    #[test]
+    fn test_observer_through_iterator() {
+        let seen = Arc::new(Mutex::new(vec![]));
+        let recorder = Arc::clone(&seen);
+        let tokenizer =
+            MathExpressionTokenizer::with_observer("1 + 2 # 3".to_string(), move |idx, _| {
+                recorder.lock().unwrap().push(idx);
+            })
+            .unwrap();
+
+        let items: Vec<_> = tokenizer.collect();
+
+        assert_eq!(items.len(), 4);
+        assert_eq!(
+            items[3],
+            Err(MathExpressionTokenizerError::InvalidToken {
+                idx: Position::new(6, 6),
+                ch: '#',
+                suggestion: None,
+            })
+        );
+        assert_eq!(*seen.lock().unwrap(), vec![0, 2, 4]);
+    }
+
+    #[test]
+    fn test_precision_warnings() {
+        let overflow = format!("1{}", "0".repeat(400));
+        let expr = format!("9223372036854775807 + 123456789012345678901234567890 * {overflow}");
+        let mut tokenizer = MathExpressionTokenizer::new(expr).unwrap();
+        assert_eq!(tokenizer.by_ref().filter(Result::is_ok).count(), 5);
+
+        assert_eq!(
+            tokenizer.take_warnings(),
+            vec![
+                TokenizerWarning::PrecisionLoss {
+                    idx: Position::new(0, 0),
+                    literal: "9223372036854775807".to_string()
+                },
+                TokenizerWarning::PrecisionLoss {
+                    idx: Position::new(22, 22),
+                    literal: "123456789012345678901234567890".to_string()
+                },
+                TokenizerWarning::Overflow {
+                    idx: Position::new(55, 55),
+                    literal: overflow
+                },
+            ]
+        );
+        assert!(tokenizer.take_warnings().is_empty());
+    }
+
+    #[test]
+    fn test_exact_literals_have_no_warnings() {
+        let mut tokenizer =
+            MathExpressionTokenizer::new("9007199254740992 + 0.1 * (2.5 - 100000000000000000000)")
+                .unwrap();
+        assert!(tokenizer.by_ref().all(|item| item.is_ok()));
+        assert!(tokenizer.take_warnings().is_empty());
+    }
+
+    #[test]
+    fn test_strict_precision_rejects_lossy_literal() {
+        let mut tokenizer = MathExpressionTokenizer::new("1 + 9223372036854775807 + 2")
+            .unwrap()
+            .with_config(TokenizerConfig::strict());
+
+        let items: Vec<_> =
+            std::iter::from_fn(|| tokenizer.has_token().then(|| tokenizer.next_token())).collect();
+
+        assert_eq!(
+            items[2],
+            Err(MathExpressionTokenizerError::PrecisionLoss {
+                idx: Position::new(4, 4),
+                literal: "9223372036854775807".to_string()
+            })
+        );
+        assert_eq!(
+            items[3..],
+            [Ok((24, Token::Operator('+'))), Ok((26, Token::Digit(2.0)))]
+        );
+        assert!(tokenizer.take_warnings().is_empty());
+    }
+
+    #[test]
+    fn test_token_from_str() {
+        assert_eq!("3.5".parse::<Token>(), Ok(Token::Digit(3.5)));
+        assert_eq!(" ( ".parse::<Token>(), Ok(Token::OpenBrace));
+        assert_eq!("-".parse::<Token>(), Ok(Token::Operator('-')));
+        assert_eq!(Token::try_from("\t)\n"), Ok(Token::CloseBrace));
+        assert_eq!(
+            "$".parse::<Token>(),
+            Err(MathExpressionTokenizerError::InvalidToken {
+                idx: Position::new(0, 0),
+                ch: '$',
+                suggestion: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_token_from_str_rejects_trailing_input() {
+        let err = "12 3".parse::<Token>().unwrap_err();
+        assert_eq!(
+            err,
+            MathExpressionTokenizerError::TrailingInput {
+                idx: Position::new(3, 3)
+            }
+        );
+        assert_eq!(err.to_string(), "Unexpected trailing input at position 4");
+
+        assert_eq!(
+            Token::try_from("1+"),
+            Err(MathExpressionTokenizerError::TrailingInput {
+                idx: Position::new(1, 1)
+            })
+        );
+    }
+
+    #[test]
+    fn test_token_from_empty_str() {
+        assert_eq!(
+            "".parse::<Token>(),
+            Err(MathExpressionTokenizerError::InvalidArgument {
+                reason: ArgumentError::Empty
+            })
+        );
+        assert_eq!(
+            "   ".parse::<Token>(),
+            Err(MathExpressionTokenizerError::InvalidArgument {
+                reason: ArgumentError::Blank
+            })
+        );
+    }
+
+    #[test]
+    fn test_error_positions_after_multibyte_whitespace() {
+        // U+3000 is three bytes but a single char.
+        let invalid = MathExpressionTokenizer::new("\u{3000}1 + $")
+            .unwrap()
+            .find_map(Result::err)
+            .unwrap();
+        assert_eq!(
+            invalid,
+            MathExpressionTokenizerError::InvalidToken {
+                idx: Position::new(7, 5),
+                ch: '$',
+                suggestion: None,
+            }
+        );
+        assert_eq!(invalid.to_string(), "Found invalid token '$' at position 6");
+
+        let malformed = MathExpressionTokenizer::new("\u{3000}1.2.3")
+            .unwrap()
+            .find_map(Result::err)
+            .unwrap();
+        assert_eq!(malformed.idx(), Some(Position::new(6, 4)));
+    }
+
+    #[test]
+    fn test_malformed_numbers() {
+        let cases = [(".", 0), ("1 + .", 4), ("..", 0), ("1.2.3", 3), ("12..", 3)];
+
+        for (expr, idx) in cases {
+            let errors: Vec<_> = MathExpressionTokenizer::new(expr)
+                .unwrap()
+                .filter_map(Result::err)
+                .collect();
+            assert_eq!(
+                errors,
+                vec![MathExpressionTokenizerError::MalformedNumber {
+                    idx: Position::new(idx, idx)
+                }],
+                "{expr}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_trailing_point_is_valid() {
+        let tokens: Vec<_> = MathExpressionTokenizer::new("5. + .5")
+            .unwrap()
+            .map(|item| item.unwrap().1)
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![Token::Digit(5.0), Token::Operator('+'), Token::Digit(0.5)]
+        );
+    }
+
+    #[test]
+    fn test_malformed_number_skips_literal() {
+        let mut tokenizer = MathExpressionTokenizer::new("1.2.3 + 4").unwrap();
+        assert_eq!(
+            tokenizer.next_token(),
+            Err(MathExpressionTokenizerError::MalformedNumber {
+                idx: Position::new(3, 3)
+            })
+        );
+        assert_eq!(tokenizer.next_token(), Ok((6, Token::Operator('+'))));
+    }
+
+    #[test]
+    fn test_scientific_notation() {
+        let cases = [
+            ("1e10", 1e10),
+            ("2.5E-3", 2.5e-3),
+            ("3e+7", 3e7),
+            (".5e1", 5.0),
+            ("5.e2", 500.0),
+            ("1e0005", 1e5),
+        ];
+        for (expr, expected) in cases {
+            let tokens: Vec<_> = MathExpressionTokenizer::new(expr)
+                .unwrap()
+                .map(|item| item.unwrap())
+                .collect();
+            assert_eq!(tokens, vec![(0, Token::Digit(expected))], "{expr}");
+        }
+
+        let tokens: Vec<_> = MathExpressionTokenizer::new("2e-3*4 - 1E2")
+            .unwrap()
+            .map(|item| item.unwrap())
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                (0, Token::Digit(2e-3)),
+                (4, Token::Operator('*')),
+                (5, Token::Digit(4.0)),
+                (7, Token::Operator('-')),
+                (9, Token::Digit(100.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unfinished_exponents() {
+        let cases = [
+            ("1e", 1, 'e'),
+            ("1e+", 2, '+'),
+            ("1e+.5", 3, '.'),
+            ("1E- 2", 2, '-'),
+            ("1e + 2", 1, 'e'),
+            ("1ex", 2, 'x'),
+            ("2 * 1.5eé", 8, 'é'),
+        ];
+
+        for (expr, idx, ch) in cases {
+            let error = MathExpressionTokenizer::new(expr)
+                .unwrap()
+                .find_map(Result::err)
+                .unwrap();
+            let position = Position::in_source(expr, idx);
+            assert_eq!(
+                error,
+                MathExpressionTokenizerError::InvalidToken {
+                    idx: position,
+                    ch,
+                    suggestion: None,
+                },
+                "{expr}"
+            );
+        }
+
+        // Digits that do not parse are reported before the exponent.
+        assert_eq!(
+            MathExpressionTokenizer::new("1.2.3e")
+                .unwrap()
+                .find_map(Result::err),
+            Some(MathExpressionTokenizerError::MalformedNumber {
+                idx: Position::new(3, 3)
+            })
+        );
+    }
+
+    #[test]
+    fn test_infinity_prints_as_exponent() {
+        assert_eq!(Token::Digit(f64::INFINITY).to_string(), "1e309");
+
+        let mut tokenizer = MathExpressionTokenizer::new("1e400").unwrap();
+        let (_, token) = tokenizer.next_token().unwrap();
+        assert_eq!(token, Token::Digit(f64::INFINITY));
+
+        let mut tokenizer = MathExpressionTokenizer::new(token.to_string()).unwrap();
+        assert_eq!(tokenizer.next_token(), Ok((0, token)));
+    }
+
+    #[test]
+    fn test_unfinished_exponent_skips_literal() {
+        let mut tokenizer = MathExpressionTokenizer::new("1e+.5 + 2").unwrap();
+        assert!(matches!(
+            tokenizer.next_token(),
+            Err(MathExpressionTokenizerError::InvalidToken { ch: '.', .. })
+        ));
+        assert_eq!(tokenizer.next_token(), Ok((4, Token::Digit(5.0))));
+        assert_eq!(tokenizer.next_token(), Ok((6, Token::Operator('+'))));
+
+        let mut tokenizer = MathExpressionTokenizer::new("1e + 2").unwrap();
+        assert!(tokenizer.next_token().is_err());
+        assert_eq!(tokenizer.next_token(), Ok((3, Token::Operator('+'))));
+    }
+
+    #[test]
+    fn test_digit_separators() {
+        let cases = [
+            ("1_000", 1000.0),
+            ("1_0.5_5", 10.55),
+            ("1_000_000_000", 1e9),
+            ("2.5e1_0", 2.5e10),
+            ("0xFF_FF", 65535.0),
+            ("0b1010_1010", 170.0),
+        ];
+        for (expr, expected) in cases {
+            let tokens: Vec<_> = MathExpressionTokenizer::new(expr)
+                .unwrap()
+                .map(|item| item.unwrap())
+                .collect();
+            assert_eq!(tokens, vec![(0, Token::Digit(expected))], "{expr}");
+        }
+
+        let tokens: Vec<_> = MathExpressionTokenizer::new("  1_000 + 2")
+            .unwrap()
+            .map(|item| item.unwrap())
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                (2, Token::Digit(1000.0)),
+                (8, Token::Operator('+')),
+                (10, Token::Digit(2.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_misplaced_digit_separators() {
+        let cases = [
+            ("_1", 0),
+            ("1__0", 2),
+            ("1_", 1),
+            ("1_.5", 1),
+            ("1._5", 2),
+            ("1_e5", 1),
+            ("1e_5", 2),
+            ("0x_FF", 2),
+            ("0b1_", 3),
+            ("2 + 1__0", 6),
+        ];
+
+        for (expr, idx) in cases {
+            let error = MathExpressionTokenizer::new(expr)
+                .unwrap()
+                .find_map(Result::err)
+                .unwrap();
+            assert_eq!(
+                error,
+                MathExpressionTokenizerError::InvalidToken {
+                    idx: Position::new(idx, idx),
+                    ch: '_',
+                    suggestion: None,
+                },
+                "{expr}"
+            );
+        }
+
+        let mut tokenizer = MathExpressionTokenizer::new("1__0 + 2").unwrap();
+        assert!(tokenizer.next_token().is_err());
+        assert_eq!(tokenizer.next_token(), Ok((5, Token::Operator('+'))));
+    }
+
+    #[test]
+    fn test_separated_literal_precision() {
+        let mut tokenizer =
+            MathExpressionTokenizer::new("1_000_000 + 9_007_199_254_740_993").unwrap();
+        assert!(tokenizer.by_ref().all(|item| item.is_ok()));
+
+        assert_eq!(
+            tokenizer.take_warnings(),
+            vec![TokenizerWarning::PrecisionLoss {
+                idx: Position::new(12, 12),
+                literal: "9_007_199_254_740_993".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_radix_literals() {
+        let cases = [
+            ("0xFF", 255.0),
+            ("0X1f", 31.0),
+            ("0x1e5", 485.0),
+            ("0b1010", 10.0),
+            ("0B0", 0.0),
+            ("0x00000000000000000000000000000000001", 1.0),
+        ];
+        for (expr, expected) in cases {
+            let tokens: Vec<_> = MathExpressionTokenizer::new(expr)
+                .unwrap()
+                .map(|item| item.unwrap())
+                .collect();
+            assert_eq!(tokens, vec![(0, Token::Digit(expected))], "{expr}");
+        }
+
+        let tokens: Vec<_> = MathExpressionTokenizer::new("0xFF + 0b1010")
+            .unwrap()
+            .map(|item| item.unwrap())
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                (0, Token::Digit(255.0)),
+                (5, Token::Operator('+')),
+                (7, Token::Digit(10.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_radix_literals() {
+        let cases = [
+            ("0xZZ", 2, 'Z'),
+            ("0b12", 3, '2'),
+            ("0b1e1", 3, 'e'),
+            ("0xFFg", 4, 'g'),
+            ("0x1.5", 3, '.'),
+            ("0x", 1, 'x'),
+            ("0b + 1", 1, 'b'),
+            ("1 + 0xé", 6, 'é'),
+        ];
+
+        for (expr, idx, ch) in cases {
+            let error = MathExpressionTokenizer::new(expr)
+                .unwrap()
+                .find_map(Result::err)
+                .unwrap();
+            assert_eq!(
+                error,
+                MathExpressionTokenizerError::InvalidToken {
+                    idx: Position::in_source(expr, idx),
+                    ch,
+                    suggestion: None,
+                },
+                "{expr}"
+            );
+        }
+
+        let mut tokenizer = MathExpressionTokenizer::new("0b12 * 3").unwrap();
+        assert!(tokenizer.next_token().is_err());
+        assert_eq!(tokenizer.next_token(), Ok((5, Token::Operator('*'))));
+    }
+
+    #[test]
+    fn test_radix_precision_warnings() {
+        let overflow = format!("0x1{}", "0".repeat(300));
+        let expr = format!("0x20000000000001 + 0x20000000000002 + {overflow}");
+        let mut tokenizer = MathExpressionTokenizer::new(expr).unwrap();
+        assert!(tokenizer.by_ref().all(|item| item.is_ok()));
+
+        assert_eq!(
+            tokenizer.take_warnings(),
+            vec![
+                TokenizerWarning::PrecisionLoss {
+                    idx: Position::new(0, 0),
+                    literal: "0x20000000000001".to_string()
+                },
+                TokenizerWarning::Overflow {
+                    idx: Position::new(38, 38),
+                    literal: overflow
+                },
+            ]
+        );
+    }
+
+    fn first_error(
+        expr: &'static str,
+        config: TokenizerConfig,
+    ) -> Option<MathExpressionTokenizerError> {
+        MathExpressionTokenizer::new(expr)
+            .unwrap()
+            .with_config(config)
+            .find_map(Result::err)
+    }
+
+    #[test]
+    fn test_consecutive_operators() {
+        for first in OPERATORS {
+            for second in OPERATORS {
+                let expr = format!("1 {first} {second} 2").leak();
+                let expected = matches!(second, '*' | '/' | '%' | '^').then_some(
+                    MathExpressionTokenizerError::ConsecutiveOperators {
+                        first_idx: Position::new(2, 2),
+                        second_idx: Position::new(4, 4),
+                    },
+                );
+
+                assert_eq!(
+                    first_error(expr, TokenizerConfig::strict()),
+                    expected,
+                    "{expr}"
+                );
+                assert_eq!(
+                    first_error(expr, TokenizerConfig::default()),
+                    None,
+                    "{expr}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_consecutive_operators_allow_unary() {
+        for expr in ["2 * -3", "1 ++ 2", "1 - - -2", "-(1) */ 2"] {
+            let expected = (expr == "-(1) */ 2").then_some(
+                MathExpressionTokenizerError::ConsecutiveOperators {
+                    first_idx: Position::new(5, 5),
+                    second_idx: Position::new(6, 6),
+                },
+            );
+            assert_eq!(
+                first_error(expr, TokenizerConfig::strict()),
+                expected,
+                "{expr}"
+            );
+        }
+
+        assert_eq!(first_error("(1) * (2)", TokenizerConfig::strict()), None);
+    }
+
+    #[test]
+    fn test_recover_after_invalid_tokens() {
+        let mut tokenizer = MathExpressionTokenizer::new("1 $ 2 # 3").unwrap();
+        let mut tokens = vec![];
+        let mut errors = vec![];
+
+        while tokenizer.has_token() {
+            match tokenizer.next_token() {
+                Ok((_, token)) => tokens.push(token),
+                Err(err) => errors.push(err),
+            }
+        }
+
+        assert_eq!(
+            tokens,
+            vec![Token::Digit(1.0), Token::Digit(2.0), Token::Digit(3.0)]
+        );
+        assert_eq!(
+            errors,
+            vec![
+                MathExpressionTokenizerError::InvalidToken {
+                    idx: Position::new(2, 2),
+                    ch: '$',
+                    suggestion: None,
+                },
+                MathExpressionTokenizerError::InvalidToken {
+                    idx: Position::new(6, 6),
+                    ch: '#',
+                    suggestion: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_error_advances_past_multibyte_char() {
+        let mut tokenizer = MathExpressionTokenizer::new("é1").unwrap();
+
+        assert_eq!(
+            tokenizer.next_token(),
+            Err(MathExpressionTokenizerError::InvalidToken {
+                idx: Position::new(0, 0),
+                ch: 'é',
+                suggestion: None,
+            })
+        );
+        assert_eq!(tokenizer.curr_index(), 2);
+        assert_eq!(tokenizer.next_token(), Ok((2, Token::Digit(1.0))));
+        assert!(!tokenizer.has_token());
+    }
+
     proptest! {
         #[test]
         fn test_valid_positive_number_tokens(n in any::<f64>().prop_filter("Positive numbers", |&x| x > 0.0)) {
@@ -236,11 +1723,11 @@ mod tests {
                         Token::CloseBrace => {
                             assert_eq!(ch, ')');
                         },
-                        Token::Operator(op) => {
+                        Token::Operator(op) | Token::UnaryOperator(op) => {
                             assert_eq!(ch, op);
                         },
                         Token::Digit(_) => {
-                            assert!(ch.is_digit(10));
+                            assert!(ch.is_ascii_digit());
                         },
                     }
                 }
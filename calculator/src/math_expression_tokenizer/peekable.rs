@@ -0,0 +1,181 @@
+use super::{MathExpressionTokenizerError, Token, TokenizerTraits};
+use crate::position::Position;
+
+// Wraps a tokenizer so the next token can be looked at before deciding
+// whether to take it. Until it is taken, the peeked token does not count as
+// read: `curr_index` and `has_token` answer as if `peek_token` had not been
+// called, and the following `next_token` returns it, or its error.
+pub struct PeekableTokenizer<T> {
+    inner: T,
+    peeked: Option<Peeked>,
+}
+
+struct Peeked {
+    // `curr_index` of the inner tokenizer before the token was read.
+    index: usize,
+    result: Result<(usize, Token), MathExpressionTokenizerError>,
+}
+
+impl<T: TokenizerTraits> PeekableTokenizer<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            peeked: None,
+        }
+    }
+
+    // At the end of input this is `NoToken` and reads nothing.
+    pub fn peek_token(&mut self) -> Result<(usize, &Token), MathExpressionTokenizerError> {
+        if self.peeked.is_none() {
+            if !self.inner.has_token() {
+                return Err(MathExpressionTokenizerError::NoToken);
+            }
+            let index = self.inner.curr_index();
+            let result = self.inner.next_token();
+            self.peeked = Some(Peeked { index, result });
+        }
+
+        match self.peeked.as_ref().map(|peeked| &peeked.result) {
+            Some(Ok((idx, token))) => Ok((*idx, token)),
+            Some(Err(err)) => Err(err.clone()),
+            None => unreachable!("a token was just peeked"),
+        }
+    }
+
+    // The inner tokenizer, which has already read a peeked token.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: TokenizerTraits> TokenizerTraits for PeekableTokenizer<T> {
+    fn has_token(&self) -> bool {
+        self.peeked.is_some() || self.inner.has_token()
+    }
+
+    fn next_token(&mut self) -> Result<(usize, Token), MathExpressionTokenizerError> {
+        match self.peeked.take() {
+            Some(peeked) => peeked.result,
+            None => self.inner.next_token(),
+        }
+    }
+
+    fn curr_index(&self) -> usize {
+        match &self.peeked {
+            Some(peeked) => peeked.index,
+            None => self.inner.curr_index(),
+        }
+    }
+
+    fn position_of(&self, byte: usize) -> Position {
+        self.inner.position_of(byte)
+    }
+
+    // The token is peeked again, whether or not the inner tokenizer can put
+    // it back.
+    fn put_back(&mut self, idx: usize, token: Token) {
+        self.peeked = Some(Peeked {
+            index: idx,
+            result: Ok((idx, token)),
+        });
+    }
+}
+
+// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math_expression_parser::MathExpressionParser;
+    use crate::math_expression_tokenizer::MathExpressionTokenizer;
+    use proptest::prelude::*;
+
+    fn peekable(source: &str) -> PeekableTokenizer<MathExpressionTokenizer> {
+        PeekableTokenizer::new(MathExpressionTokenizer::new(source.to_owned()).unwrap())
+    }
+
+    #[test]
+    fn test_peek_then_next() {
+        let mut tokenizer = peekable("  12 *\t( 3 )  ");
+
+        assert_eq!(tokenizer.peek_token(), Ok((2, &Token::Digit(12.0))));
+        assert_eq!(tokenizer.peek_token(), Ok((2, &Token::Digit(12.0))));
+        assert_eq!(tokenizer.curr_index(), 0);
+        assert_eq!(tokenizer.next_token(), Ok((2, Token::Digit(12.0))));
+        assert_eq!(tokenizer.curr_index(), 4);
+
+        assert_eq!(tokenizer.next_token(), Ok((5, Token::Operator('*'))));
+        assert_eq!(tokenizer.peek_token(), Ok((7, &Token::OpenBrace)));
+        assert_eq!(tokenizer.curr_index(), 6);
+        assert_eq!(tokenizer.next_token(), Ok((7, Token::OpenBrace)));
+        assert_eq!(tokenizer.next_token(), Ok((9, Token::Digit(3.0))));
+        assert_eq!(tokenizer.peek_token(), Ok((11, &Token::CloseBrace)));
+        assert!(tokenizer.has_token());
+        assert_eq!(tokenizer.next_token(), Ok((11, Token::CloseBrace)));
+        assert!(!tokenizer.has_token());
+    }
+
+    #[test]
+    fn test_peek_at_end_of_input() {
+        let mut tokenizer = peekable("1   ");
+        tokenizer.next_token().unwrap();
+        let index = tokenizer.curr_index();
+
+        assert_eq!(
+            tokenizer.peek_token(),
+            Err(MathExpressionTokenizerError::NoToken)
+        );
+        assert_eq!(tokenizer.curr_index(), index);
+        assert_eq!(
+            tokenizer.next_token(),
+            Err(MathExpressionTokenizerError::NoToken)
+        );
+    }
+
+    #[test]
+    fn test_peeked_error_is_returned_by_next() {
+        let mut tokenizer = peekable("1 $ 2");
+        tokenizer.next_token().unwrap();
+
+        let peeked = tokenizer.peek_token().unwrap_err();
+        assert_eq!(tokenizer.curr_index(), 1);
+        assert_eq!(tokenizer.next_token(), Err(peeked));
+        assert_eq!(tokenizer.next_token(), Ok((4, Token::Digit(2.0))));
+    }
+
+    #[test]
+    fn test_parse_after_peek() {
+        let mut tokenizer = peekable("(1 + 2) * 3");
+        tokenizer.peek_token().unwrap();
+
+        let parsed = MathExpressionParser::new().parse(tokenizer).unwrap();
+        assert_eq!(parsed.to_string(), "(1 + 2) * 3");
+    }
+
+    proptest! {
+        #[test]
+        fn test_peeking_does_not_change_tokens(
+            source in r"[0-9+\-*/() \t.$]{1,24}",
+            peeks in proptest::collection::vec(any::<bool>(), 24),
+        ) {
+            prop_assume!(!source.trim().is_empty());
+            let mut plain = MathExpressionTokenizer::new(source.clone()).unwrap();
+            let mut tokenizer = peekable(&source);
+
+            for peek in peeks {
+                prop_assert_eq!(tokenizer.has_token(), plain.has_token());
+                prop_assert_eq!(tokenizer.curr_index(), plain.curr_index());
+                if !plain.has_token() {
+                    break;
+                }
+                if peek {
+                    let peeked = tokenizer.peek_token().map(|(idx, token)| (idx, token.clone()));
+                    prop_assert_eq!(tokenizer.curr_index(), plain.curr_index());
+                    prop_assert_eq!(&peeked, &tokenizer.next_token());
+                    prop_assert_eq!(peeked, plain.next_token());
+                } else {
+                    prop_assert_eq!(tokenizer.next_token(), plain.next_token());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use super::scan;
+
+// Any decimal with at most this many significant digits survives a round
+// trip through `f64` unchanged.
+const EXACT_DIGITS: usize = 15;
+
+// Whether `value`, parsed from `literal`, no longer has the literal's decimal
+// digits, or is not the integer a '0x' or '0b' literal stands for. Leading
+// and trailing zeros are not significant.
+pub(crate) fn is_lossy(literal: &str, value: f64) -> bool {
+    if let Some(radix) = scan::radix_prefix(literal) {
+        let digits = &literal[scan::RADIX_PREFIX_LEN..];
+        return u128::from_str_radix(digits, radix) != Ok(value as u128);
+    }
+
+    let (literal, literal_exponent) = literal.split_once(['e', 'E']).unwrap_or((literal, "0"));
+    let len = significant_len(literal);
+    if len == 0 {
+        return value != 0.0;
+    }
+    if len <= EXACT_DIGITS && value.is_normal() {
+        return false;
+    }
+    if !value.is_finite() || value == 0.0 {
+        return true;
+    }
+
+    let (digits, exponent) = significant_digits(literal);
+    // An exponent too large for `i64` makes any literal that fits in the
+    // input overflow or underflow, which was handled above.
+    let Some(exponent) = literal_exponent
+        .parse::<i64>()
+        .ok()
+        .and_then(|literal_exponent| exponent.checked_add(literal_exponent))
+    else {
+        return true;
+    };
+
+    // `{:e}` prints the shortest representation that parses back to `value`.
+    let printed = format!("{value:e}");
+    let Some((mantissa, printed_exponent)) = printed.split_once('e') else {
+        return true;
+    };
+    let (printed_digits, _) = significant_digits(mantissa);
+
+    printed_digits != digits || printed_exponent.parse::<i64>() != Ok(exponent)
+}
+
+// Number of significant digits, counted without allocating so that the common
+// short literal costs nothing.
+fn significant_len(literal: &str) -> usize {
+    let digits = literal.bytes().filter(|&byte| byte != b'.');
+    let total = digits.clone().count();
+    let leading = digits.clone().take_while(|&byte| byte == b'0').count();
+    if leading == total {
+        return 0;
+    }
+
+    let trailing = digits.rev().take_while(|&byte| byte == b'0').count();
+    total - leading - trailing
+}
+
+// Significant digits of a literal made of ASCII digits and at most one '.',
+// and the decimal exponent of the first of them.
+fn significant_digits(literal: &str) -> (String, i64) {
+    let (integer, fraction) = literal.split_once('.').unwrap_or((literal, ""));
+    let all = integer.bytes().chain(fraction.bytes());
+    let leading_zeros = all.clone().take_while(|&byte| byte == b'0').count();
+
+    let digits: String = all.skip(leading_zeros).map(char::from).collect();
+    let digits = digits.trim_end_matches('0').to_string();
+    let exponent = integer.len() as i64 - leading_zeros as i64 - 1;
+
+    (digits, exponent)
+}
+
+// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn lossy(literal: &str) -> bool {
+        is_lossy(literal, literal.parse().unwrap())
+    }
+
+    #[test]
+    fn test_exact_literals() {
+        for literal in [
+            "0",
+            "0.0",
+            "000.000",
+            "5.",
+            ".5",
+            "0.1",
+            "1.25",
+            "100",
+            "123456789012345",
+            "9007199254740992",
+            "1000000000000000000000",
+            "0.30000000000000004",
+            "1e5",
+            "2.5E-3",
+            "3e+7",
+            "1.7976931348623157e308",
+            "12345e-300",
+            "0.000123456789012345e20",
+        ] {
+            assert!(!lossy(literal), "{literal}");
+        }
+    }
+
+    #[test]
+    fn test_lossy_literals() {
+        for literal in [
+            "9007199254740993",
+            "9223372036854775807",
+            "123456789012345678901234567890",
+            "0.1000000000000000000001",
+            "9007199254740993e10",
+            "1.00000000000000000001E-5",
+        ] {
+            assert!(lossy(literal), "{literal}");
+        }
+    }
+
+    #[test]
+    fn test_out_of_range_literals() {
+        let overflow = format!("1{}", "0".repeat(400));
+        let underflow = format!("0.{}1", "0".repeat(400));
+
+        assert!(lossy(&overflow));
+        assert!(lossy(&underflow));
+        assert!(lossy("1e400"));
+        assert!(lossy("1e-400"));
+        assert!(lossy("1e99999999999999999999"));
+    }
+
+    proptest! {
+        #[test]
+        fn test_printed_floats_are_exact(n in any::<f64>().prop_filter("finite", |n| n.is_finite())) {
+            for literal in [n.abs().to_string(), format!("{:e}", n.abs())] {
+                prop_assert!(!lossy(&literal), "{}", literal);
+            }
+        }
+
+        #[test]
+        fn test_significant_len_matches_digits(literal in r"[0-9]{0,20}(\.[0-9]{0,20})?") {
+            prop_assert_eq!(significant_len(&literal), significant_digits(&literal).0.len());
+        }
+    }
+}
@@ -0,0 +1,330 @@
+// Byte-level scanners used by the tokenizer. They walk raw bytes while the
+// input is ASCII and fall back to char-based logic at the first byte >= 0x80,
+// so the returned offsets are always char boundaries.
+
+use std::borrow::Cow;
+
+const SPACES: u64 = u64::from_ne_bytes([b' '; 8]);
+
+// Length in bytes of the leading whitespace of `s`.
+pub(crate) fn whitespace_len(s: &str) -> usize {
+    let bytes = s.as_bytes();
+    let mut idx = 0;
+
+    // Long runs of plain spaces are skipped eight bytes at a time.
+    while let Some(&chunk) = bytes[idx..].first_chunk::<8>() {
+        if u64::from_ne_bytes(chunk) != SPACES {
+            break;
+        }
+        idx += 8;
+    }
+
+    while let Some(&byte) = bytes.get(idx) {
+        if !byte.is_ascii() {
+            return idx + whitespace_len_scalar(&s[idx..]);
+        }
+        // Same set as `char::is_whitespace` restricted to ASCII, which includes
+        // the vertical tab unlike `u8::is_ascii_whitespace`.
+        if !matches!(byte, b' ' | b'\t' | b'\n' | b'\x0B' | b'\x0C' | b'\r') {
+            return idx;
+        }
+        idx += 1;
+    }
+
+    idx
+}
+
+// Length in bytes of the leading run of ASCII digits, '.' and '_' in `s`. All
+// are ASCII, so any byte >= 0x80 ends the run on a char boundary.
+pub(crate) fn digits_len(s: &str) -> usize {
+    s.bytes()
+        .position(|byte| !byte.is_ascii_digit() && byte != b'.' && byte != b'_')
+        .unwrap_or(s.len())
+}
+
+// Offset of the first '_' in `literal` that is not a digit separator: one
+// that does not follow a digit of `radix`, such as a leading one or the
+// second of a doubled one, or one followed by neither a digit nor another
+// '_', such as a trailing one.
+pub(crate) fn misplaced_separator(literal: &str, radix: u32) -> Option<usize> {
+    let bytes = literal.as_bytes();
+    let is_digit = |byte: Option<&u8>| byte.is_some_and(|&byte| char::from(byte).is_digit(radix));
+
+    bytes
+        .iter()
+        .enumerate()
+        .filter(|&(_, &byte)| byte == b'_')
+        .map(|(idx, _)| idx)
+        .find(|&idx| {
+            let before = idx.checked_sub(1).and_then(|before| bytes.get(before));
+            let after = bytes.get(idx + 1);
+            !is_digit(before) || !(is_digit(after) || after == Some(&b'_'))
+        })
+}
+
+// `literal` without its digit separators, for parsing its value.
+pub(crate) fn without_separators(literal: &str) -> Cow<'_, str> {
+    if literal.contains('_') {
+        Cow::Owned(literal.replace('_', ""))
+    } else {
+        Cow::Borrowed(literal)
+    }
+}
+
+// Length in bytes of the exponent at the start of `s`: an 'e' or 'E', an
+// optional sign and at least one digit or separator. `Ok(0)` if `s` does not start with
+// 'e' or 'E', `Err` with the length of the marker and sign if no digit
+// follows them.
+pub(crate) fn exponent_len(s: &str) -> Result<usize, usize> {
+    let bytes = s.as_bytes();
+    if !matches!(bytes.first(), Some(b'e' | b'E')) {
+        return Ok(0);
+    }
+
+    let marker = if matches!(bytes.get(1), Some(b'+' | b'-')) {
+        2
+    } else {
+        1
+    };
+    let digits = bytes
+        .iter()
+        .skip(marker)
+        .take_while(|&&byte| byte.is_ascii_digit() || byte == b'_')
+        .count();
+    if digits == 0 {
+        Err(marker)
+    } else {
+        Ok(marker + digits)
+    }
+}
+
+// Length in bytes of the '0x' or '0b' prefix of integer literals.
+pub(crate) const RADIX_PREFIX_LEN: usize = 2;
+
+// Radix of the literal at the start of `s` if it has a '0x' or '0b' prefix,
+// in either case.
+pub(crate) fn radix_prefix(s: &str) -> Option<u32> {
+    match s.as_bytes() {
+        [b'0', b'x' | b'X', ..] => Some(16),
+        [b'0', b'b' | b'B', ..] => Some(2),
+        _ => None,
+    }
+}
+
+// Length in bytes of the leading run of digits of `radix` and '_' in `s`.
+pub(crate) fn radix_digits_len(s: &str, radix: u32) -> usize {
+    s.bytes()
+        .take_while(|&byte| char::from(byte).is_digit(radix) || byte == b'_')
+        .count()
+}
+
+// Value of digits found by `radix_digits_len`. Integers too large for `u128`
+// are summed up in `f64`, which is not correctly rounded, but they are
+// reported as lossy anyway.
+pub(crate) fn parse_radix(digits: &str, radix: u32) -> f64 {
+    match u128::from_str_radix(digits, radix) {
+        Ok(number) => number as f64,
+        Err(_) => digits.chars().fold(0.0, |number, ch| {
+            number * f64::from(radix) + f64::from(ch.to_digit(radix).unwrap_or(0))
+        }),
+    }
+}
+
+// Length in bytes of the literal at the start of `s`: a prefixed integer, or
+// a run found by `digits_len` and the exponent after it, complete or not.
+pub(crate) fn literal_len(s: &str) -> usize {
+    if let Some(radix) = radix_prefix(s) {
+        return RADIX_PREFIX_LEN + radix_digits_len(&s[RADIX_PREFIX_LEN..], radix);
+    }
+
+    let mantissa = digits_len(s);
+    if mantissa == 0 {
+        return 0;
+    }
+    mantissa + exponent_len(&s[mantissa..]).unwrap_or_else(|marker| marker)
+}
+
+// Parses a literal found by `literal_len`. With the `fast-float` feature an
+// Eisel-Lemire based parser is tried first; it produces bit-identical results
+// and anything it rejects is handed to the standard library parser.
+pub(crate) fn parse_number(s: &str) -> Option<f64> {
+    #[cfg(feature = "fast-float")]
+    if let Ok(number) = fast_float2::parse::<f64, _>(s) {
+        return Some(number);
+    }
+
+    s.parse::<f64>().ok()
+}
+
+fn whitespace_len_scalar(s: &str) -> usize {
+    s.char_indices()
+        .find(|(_, ch)| !ch.is_whitespace())
+        .map(|(idx, _)| idx)
+        .unwrap_or(s.len())
+}
+
+// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn digits_len_scalar(s: &str) -> usize {
+        s.char_indices()
+            .find(|&(_, ch)| !ch.is_ascii_digit() && ch != '.' && ch != '_')
+            .map(|(idx, _)| idx)
+            .unwrap_or(s.len())
+    }
+
+    #[test]
+    fn test_whitespace_kinds() {
+        assert_eq!(whitespace_len(" \t\n\x0B\x0C\r1"), 6);
+        assert_eq!(whitespace_len(&format!("{}x", " ".repeat(21))), 21);
+        assert_eq!(whitespace_len("  \u{00A0}\u{2003} 1"), 8);
+        assert_eq!(whitespace_len("  é"), 2);
+        assert_eq!(whitespace_len("   "), 3);
+        assert_eq!(whitespace_len(""), 0);
+    }
+
+    #[test]
+    fn test_digits_stop_at_multibyte() {
+        assert_eq!(digits_len("12.5é"), 4);
+        assert_eq!(digits_len("12.5"), 4);
+        assert_eq!(digits_len("x12"), 0);
+    }
+
+    #[test]
+    fn test_exponents() {
+        assert_eq!(exponent_len("e10"), Ok(3));
+        assert_eq!(exponent_len("E-3+1"), Ok(3));
+        assert_eq!(exponent_len("e+7"), Ok(3));
+        assert_eq!(exponent_len("+7"), Ok(0));
+        assert_eq!(exponent_len(""), Ok(0));
+        assert_eq!(exponent_len("e"), Err(1));
+        assert_eq!(exponent_len("e+"), Err(2));
+        assert_eq!(exponent_len("e+.5"), Err(2));
+        assert_eq!(exponent_len("eé"), Err(1));
+
+        assert_eq!(literal_len("2.5E-3*4"), 6);
+        assert_eq!(literal_len("1e+ 2"), 3);
+        assert_eq!(literal_len("1.5"), 3);
+        assert_eq!(literal_len("e5"), 0);
+    }
+
+    #[test]
+    fn test_separators() {
+        assert_eq!(digits_len("1_000.5_5+1"), 9);
+        assert_eq!(literal_len("1_0e1_0"), 7);
+        assert_eq!(literal_len("0xFF_FF"), 7);
+
+        assert_eq!(misplaced_separator("1_000", 10), None);
+        assert_eq!(misplaced_separator("1__0", 10), Some(2));
+        assert_eq!(misplaced_separator("_1", 10), Some(0));
+        assert_eq!(misplaced_separator("1_", 10), Some(1));
+        assert_eq!(misplaced_separator("1_.5", 10), Some(1));
+        assert_eq!(misplaced_separator("1._5", 10), Some(2));
+        assert_eq!(misplaced_separator("1_e5", 10), Some(1));
+        assert_eq!(misplaced_separator("0x1_e", 16), None);
+        assert_eq!(misplaced_separator("0x_1", 16), Some(2));
+
+        assert_eq!(without_separators("1_0.5_5"), "10.55");
+        assert!(matches!(without_separators("10.55"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_radix_literals() {
+        assert_eq!(radix_prefix("0xFF"), Some(16));
+        assert_eq!(radix_prefix("0B1"), Some(2));
+        assert_eq!(radix_prefix("0"), None);
+        assert_eq!(radix_prefix("10x1"), None);
+
+        assert_eq!(literal_len("0xFF+1"), 4);
+        assert_eq!(literal_len("0X1e5"), 5);
+        assert_eq!(literal_len("0b12"), 3);
+        assert_eq!(literal_len("0x"), 2);
+
+        assert_eq!(parse_radix("FF", 16), 255.0);
+        assert_eq!(parse_radix("1010", 2), 10.0);
+        assert_eq!(parse_radix(&"F".repeat(40), 16), 16f64.powi(40));
+    }
+
+    const TRICKY_LITERALS: &[&str] = &[
+        "0",
+        "0.0",
+        ".5",
+        "5.",
+        "0.000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000004940656458412465441765687928682213723651",
+        "0.000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000002225073858507201",
+        "9007199254740993",
+        "9007199254740992.5",
+        "1.00000000000000011102230246251565404236316680908203125",
+        "1.00000000000000011102230246251565404236316680908203124",
+        "1.00000000000000011102230246251565404236316680908203126",
+        "123456789012345678901234567890123456789012345678901234567890",
+        "179769313486231570000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000",
+        "1797693134862315700000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000",
+        "0.30000000000000004",
+        ".",
+        "1.2.3",
+        "",
+        "1e10",
+        "2.5E-3",
+        "3e+7",
+        "5.e3",
+        "1e0005",
+        "1.7976931348623157e308",
+        "1.7976931348623159e308",
+        "4.9406564584124654e-324",
+        "2e-324",
+        "1e-99999999999999999999",
+        "1e99999999999999999999",
+        "1e",
+    ];
+
+    #[test]
+    fn test_tricky_literals_match_std() {
+        for literal in TRICKY_LITERALS {
+            assert_eq!(
+                parse_number(literal).map(f64::to_bits),
+                literal.parse::<f64>().ok().map(f64::to_bits),
+                "{literal}"
+            );
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_parse_number_matches_std(n in any::<f64>().prop_filter("finite", |n| n.is_finite()), precision in 0usize..20) {
+            for literal in [
+                format!("{}", n.abs()),
+                format!("{:.*}", precision, n.abs()),
+                format!("{}", n.abs().trunc()),
+                format!("{:e}", n.abs()),
+                format!("{:.*E}", precision, n.abs()),
+            ] {
+                prop_assert_eq!(
+                    parse_number(&literal).map(f64::to_bits),
+                    literal.parse::<f64>().ok().map(f64::to_bits)
+                );
+            }
+        }
+
+        #[test]
+        fn test_scanners_match_scalar(s in any::<String>()) {
+            for (idx, _) in s.char_indices() {
+                let rest = &s[idx..];
+                prop_assert_eq!(whitespace_len(rest), whitespace_len_scalar(rest));
+                prop_assert_eq!(digits_len(rest), digits_len_scalar(rest));
+            }
+        }
+
+        #[test]
+        fn test_scanners_match_scalar_on_mixed_input(s in r"[0-9._ \t\n\u{0B}\u{A0}\u{2003}é+]{0,64}") {
+            for (idx, _) in s.char_indices() {
+                let rest = &s[idx..];
+                prop_assert_eq!(whitespace_len(rest), whitespace_len_scalar(rest));
+                prop_assert_eq!(digits_len(rest), digits_len_scalar(rest));
+            }
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use std::fmt;
+use std::ops::Range;
+
+// A location in the input as both a byte offset (for slicing in Rust) and a
+// char offset (for frontends that count characters). Both fields are
+// zero-based; `Display` shows the one-based char offset, which is what a
+// person counting along the input or an editor column expects.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Position {
+    pub byte: usize,
+    pub char: usize,
+}
+
+// A half-open byte range `start..end` of the input, e.g. the text of a token.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+// How a position is counted when it is shown, see `Position::display_with`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum IndexBase {
+    Zero,
+    #[default]
+    One,
+}
+
+// Formats a position's char offset in a given `IndexBase`.
+#[derive(Debug, Clone, Copy)]
+pub struct PositionDisplay {
+    position: Position,
+    base: IndexBase,
+}
+
+impl Position {
+    pub const fn new(byte: usize, char: usize) -> Self {
+        Self { byte, char }
+    }
+
+    // Position of byte offset `byte` in `source`, which must be a char boundary.
+    pub fn in_source(source: &str, byte: usize) -> Self {
+        Self::new(byte, source[..byte].chars().count())
+    }
+
+    // Same as `in_source`, or `None` if `byte` is past the end of `source` or
+    // inside a char.
+    pub fn try_in_source(source: &str, byte: usize) -> Option<Self> {
+        let before = source.get(..byte)?;
+        Some(Self::new(byte, before.chars().count()))
+    }
+
+    // Moves the position forward by another one, e.g. from the start of a
+    // statement to the start of the script containing it.
+    pub fn offset_by(self, offset: Position) -> Self {
+        Self::new(self.byte + offset.byte, self.char + offset.char)
+    }
+
+    pub fn display_one_based(self) -> PositionDisplay {
+        self.display_with(IndexBase::One)
+    }
+
+    pub fn display_with(self, base: IndexBase) -> PositionDisplay {
+        PositionDisplay {
+            position: self,
+            base,
+        }
+    }
+}
+
+impl Span {
+    pub const fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub fn contains(&self, byte: usize) -> bool {
+        (self.start..self.end).contains(&byte)
+    }
+
+    // Smallest span covering both, including any gap between them.
+    pub fn merge(self, other: Span) -> Self {
+        Self::new(self.start.min(other.start), self.end.max(other.end))
+    }
+
+    // Text of the span in `source`, which must start and end on char boundaries.
+    pub fn slice<'a>(&self, source: &'a str) -> &'a str {
+        &source[self.start..self.end]
+    }
+}
+
+impl From<Range<usize>> for Span {
+    fn from(range: Range<usize>) -> Self {
+        Self::new(range.start, range.end)
+    }
+}
+
+impl From<Span> for Range<usize> {
+    fn from(span: Span) -> Self {
+        span.start..span.end
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.display_one_based().fmt(f)
+    }
+}
+
+impl fmt::Display for PositionDisplay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.base {
+            IndexBase::Zero => write!(f, "{}", self.position.char),
+            IndexBase::One => write!(f, "{}", self.position.char + 1),
+        }
+    }
+}
+
+// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_source_counts_chars() {
+        let source = "é + ñ";
+        assert_eq!(Position::in_source(source, 0), Position::new(0, 0));
+        assert_eq!(Position::in_source(source, 2), Position::new(2, 1));
+        assert_eq!(
+            Position::in_source(source, source.len()),
+            Position::new(7, 5)
+        );
+
+        assert_eq!(
+            Position::try_in_source(source, 2),
+            Some(Position::new(2, 1))
+        );
+        assert_eq!(Position::try_in_source(source, 1), None);
+        assert_eq!(Position::try_in_source(source, 8), None);
+    }
+
+    #[test]
+    fn test_display_shows_one_based_char_offset() {
+        assert_eq!(Position::new(7, 5).to_string(), "6");
+        assert_eq!(Position::new(0, 0).to_string(), "1");
+        assert_eq!(
+            Position::new(7, 5)
+                .display_with(IndexBase::Zero)
+                .to_string(),
+            "5"
+        );
+        assert_eq!(Position::new(7, 5).display_one_based().to_string(), "6");
+    }
+
+    #[test]
+    fn test_offset_by() {
+        assert_eq!(
+            Position::new(2, 1).offset_by(Position::new(10, 4)),
+            Position::new(12, 5)
+        );
+    }
+
+    #[test]
+    fn test_span_helpers() {
+        let source = "1 + é2";
+        let plus = Span::new(2, 3);
+        let literal = Span::from(4..7);
+
+        assert_eq!(plus.len(), 1);
+        assert!(!plus.is_empty());
+        assert!(Span::new(3, 3).is_empty());
+        assert!(plus.contains(2));
+        assert!(!plus.contains(3));
+        assert_eq!(plus.slice(source), "+");
+        assert_eq!(literal.slice(source), "é2");
+        assert_eq!(plus.merge(literal), Span::new(2, 7));
+        assert_eq!(literal.merge(plus), Span::new(2, 7));
+        assert_eq!(Range::from(literal), 4..7);
+    }
+}
@@ -0,0 +1,70 @@
+use std::cell::Cell;
+
+// Counters collected on the current thread while the `profiling` feature is
+// enabled. Every hook is a plain increment, so the numbers are exact and can
+// be asserted on in tests.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub tokens: u64,
+    pub whitespace_bytes: u64,
+    pub token_reallocations: u64,
+}
+
+thread_local! {
+    static STATS: Cell<Stats> = Cell::new(Stats::default());
+}
+
+// Returns the counters gathered on this thread so far and resets them.
+pub fn take() -> Stats {
+    STATS.with(Cell::take)
+}
+
+pub(crate) fn record(update: impl FnOnce(&mut Stats)) {
+    STATS.with(|stats| {
+        let mut current = stats.get();
+        update(&mut current);
+        stats.set(current);
+    });
+}
+
+// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math_expression_parser::MathExpressionParser;
+    use crate::math_expression_tokenizer::MathExpressionTokenizer;
+    use std::thread;
+
+    #[test]
+    fn test_counts_for_known_expression() {
+        take();
+
+        // Whitespace: two leading bytes, one after `1` and two after `+`.
+        // Seven tokens are pushed; a Vec grows 0 -> 4 -> 8, while the inline
+        // smallvec storage never spills.
+        let tokenizer = MathExpressionTokenizer::new("  1 +  (2.5*3)").unwrap();
+        MathExpressionParser::new().parse(tokenizer).unwrap();
+
+        assert_eq!(
+            take(),
+            Stats {
+                tokens: 7,
+                whitespace_bytes: 5,
+                token_reallocations: if cfg!(feature = "smallvec") { 0 } else { 2 },
+            }
+        );
+        assert_eq!(take(), Stats::default());
+    }
+
+    #[test]
+    fn test_counters_are_per_thread() {
+        take();
+        MathExpressionTokenizer::new("1 + 2")
+            .unwrap()
+            .for_each(drop);
+
+        let other = thread::spawn(take).join().unwrap();
+        assert_eq!(other, Stats::default());
+        assert_eq!(take().tokens, 3);
+    }
+}
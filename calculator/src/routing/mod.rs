@@ -0,0 +1,140 @@
+use crate::math_expression_tokenizer::{MathExpressionTokenizer, Token, TokenizerTraits};
+
+// Only this many bytes and tokens at the start of the input are looked at, so
+// classifying costs the same for any input length.
+const SCAN_BYTES: usize = 256;
+const SCAN_TOKENS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum InputClass {
+    LikelyExpression,
+    // `name = ...`
+    LikelyAssignment,
+    // Starts with `:`, e.g. `:help`.
+    LikelyCommand,
+    NotExpression,
+}
+
+// Cheap guess at what a message is, e.g. to route chat input before running
+// the full pipeline. An expression has to start with a number, '(' or a sign,
+// have an operator among its first tokens, and at least three in four of
+// those tokens must be valid. The guess can be wrong either way; parsing is
+// what decides.
+pub fn classify_input(s: &str) -> InputClass {
+    let s = s.trim_start();
+    if s.starts_with(':') {
+        return InputClass::LikelyCommand;
+    }
+
+    let mut end = s.len().min(SCAN_BYTES);
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    let window = &s[..end];
+    if is_assignment(window) {
+        return InputClass::LikelyAssignment;
+    }
+
+    let Ok(mut tokenizer) = MathExpressionTokenizer::new(window.to_string()) else {
+        return InputClass::NotExpression;
+    };
+
+    let (mut valid, mut invalid, mut operators) = (0usize, 0usize, 0usize);
+    while tokenizer.has_token() && valid + invalid < SCAN_TOKENS {
+        let is_first = valid + invalid == 0;
+        match tokenizer.next_token() {
+            Ok((_, token)) => {
+                let starts_operand = matches!(
+                    token,
+                    Token::Digit(_) | Token::OpenBrace | Token::Operator('+' | '-')
+                );
+                if is_first && !starts_operand {
+                    return InputClass::NotExpression;
+                }
+                if !is_first && matches!(token, Token::Operator(_)) {
+                    operators += 1;
+                }
+                valid += 1;
+            }
+            Err(_) if is_first => return InputClass::NotExpression,
+            Err(_) => invalid += 1,
+        }
+    }
+
+    if operators > 0 && valid * 4 >= (valid + invalid) * 3 {
+        InputClass::LikelyExpression
+    } else {
+        InputClass::NotExpression
+    }
+}
+
+// An identifier followed by a single `=`.
+fn is_assignment(s: &str) -> bool {
+    let name_len = s
+        .find(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '_'))
+        .unwrap_or(s.len());
+    let starts_name = s
+        .chars()
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_');
+
+    starts_name
+        && s[name_len..]
+            .trim_start()
+            .strip_prefix('=')
+            .is_some_and(|rest| !rest.starts_with('='))
+}
+
+// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expressions() {
+        for input in ["1 + 2", "(1+2)*3", "  -4 * 2", "1 + 1 🙂"] {
+            assert_eq!(
+                classify_input(input),
+                InputClass::LikelyExpression,
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_prose_and_bare_numbers() {
+        for input in [
+            "meet at 3",
+            "3 apples",
+            "42",
+            "",
+            "   ",
+            "what is 2 + 2",
+            "2 - too many words here",
+            "🎉🎉 1 + 1",
+            "* 2",
+        ] {
+            assert_eq!(classify_input(input), InputClass::NotExpression, "{input}");
+        }
+    }
+
+    #[test]
+    fn test_assignments_and_commands() {
+        assert_eq!(classify_input("x = 3 + 4"), InputClass::LikelyAssignment);
+        assert_eq!(classify_input("rate_2=0.2"), InputClass::LikelyAssignment);
+        assert_eq!(classify_input("x == 3"), InputClass::NotExpression);
+        assert_eq!(classify_input("2x = 3"), InputClass::NotExpression);
+        assert_eq!(classify_input(":help"), InputClass::LikelyCommand);
+        assert_eq!(classify_input("  :quit now"), InputClass::LikelyCommand);
+    }
+
+    #[test]
+    fn test_only_the_start_is_scanned() {
+        let long = format!("1 + 2 {}", "é".repeat(10_000));
+        assert_eq!(classify_input(&long), InputClass::NotExpression);
+
+        let long = format!("1 + {}", "2 + ".repeat(100_000));
+        assert_eq!(classify_input(&long), InputClass::LikelyExpression);
+    }
+}
@@ -0,0 +1,239 @@
+use crate::math_expression_tokenizer::write_number;
+use crate::position::Span;
+
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[non_exhaustive]
+pub enum ImportError {
+    #[error("String literals are not supported")]
+    StringLiteral { span: Span },
+    #[error("'&' concatenation is not supported")]
+    Concatenation { span: Span },
+    #[error("Cell range '{range}' is not supported, only single numbers are")]
+    CellRange { range: String, span: Span },
+    #[error("Function '{name}' is not supported, write out its arithmetic instead")]
+    Function { name: String, span: Span },
+    #[error("No value for cell '{cell}'")]
+    UnmappedCell { cell: String, span: Span },
+    #[error("'{name}' is neither a number nor a cell reference")]
+    UnknownName { name: String, span: Span },
+}
+
+// Converts a formula pasted from a spreadsheet, e.g. `=(1+2)*3`, into
+// calculator input by dropping the leading `=`. Constructs the calculator
+// cannot represent are rejected with their span in `input`, including cell
+// references; see `from_spreadsheet_formula_with` to supply their values.
+pub fn from_spreadsheet_formula(input: &str) -> Result<String, ImportError> {
+    from_spreadsheet_formula_with(input, |_| None)
+}
+
+// Same as `from_spreadsheet_formula`, replacing every cell reference with the
+// value `cells` returns for it. References are passed without `$` and in
+// upper case, so `$b$1` is looked up as `B1`. Negative values are put in
+// parentheses so they cannot merge with a preceding operator.
+pub fn from_spreadsheet_formula_with(
+    input: &str,
+    mut cells: impl FnMut(&str) -> Option<f64>,
+) -> Result<String, ImportError> {
+    let start = input.len() - input.trim_start().len();
+    let start = start + usize::from(input[start..].starts_with('='));
+    let mut out = String::with_capacity(input.len() - start);
+    let mut rest = input[start..].char_indices();
+
+    while let Some((offset, ch)) = rest.next() {
+        let idx = start + offset;
+        match ch {
+            '"' => {
+                let end = input[idx + 1..]
+                    .find('"')
+                    .map_or(input.len(), |close| idx + close + 2);
+                return Err(ImportError::StringLiteral {
+                    span: Span::new(idx, end),
+                });
+            }
+            '&' => {
+                return Err(ImportError::Concatenation {
+                    span: Span::new(idx, idx + 1),
+                });
+            }
+            ch if ch.is_ascii_alphabetic() || ch == '$' || ch == '_' => {
+                let span = Span::new(idx, idx + name_len(&input[idx..]));
+                // Names are ASCII, so each of their bytes is one char.
+                for _ in 1..span.len() {
+                    rest.next();
+                }
+                convert_name(input, span, &mut cells, &mut out)?;
+            }
+            ch => out.push(ch),
+        }
+    }
+
+    Ok(out)
+}
+
+fn convert_name(
+    input: &str,
+    span: Span,
+    cells: &mut impl FnMut(&str) -> Option<f64>,
+    out: &mut String,
+) -> Result<(), ImportError> {
+    let name = span.slice(input);
+    let after = &input[span.end..];
+
+    if after.trim_start().starts_with('(') {
+        return Err(ImportError::Function {
+            name: name.to_string(),
+            span,
+        });
+    }
+
+    let Some(cell) = cell_reference(name) else {
+        return Err(ImportError::UnknownName {
+            name: name.to_string(),
+            span,
+        });
+    };
+
+    if let Some(second) = after.strip_prefix(':') {
+        let end = span.end + 1 + name_len(second);
+        return Err(ImportError::CellRange {
+            range: input[span.start..end].to_string(),
+            span: Span::new(span.start, end),
+        });
+    }
+
+    let Some(value) = cells(&cell) else {
+        return Err(ImportError::UnmappedCell { cell, span });
+    };
+
+    if value.is_sign_negative() {
+        out.push_str("(-");
+        let _ = write_number(out, -value);
+        out.push(')');
+    } else {
+        let _ = write_number(out, value);
+    }
+
+    Ok(())
+}
+
+fn name_len(s: &str) -> usize {
+    s.find(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '$' || ch == '_'))
+        .unwrap_or(s.len())
+}
+
+// `A1`, `$A$1` or `ab12` as `A1` or `AB12`: one to three letters and a row
+// number, each optionally preceded by `$`.
+fn cell_reference(name: &str) -> Option<String> {
+    let rest = name.strip_prefix('$').unwrap_or(name);
+    let letters = rest
+        .find(|ch: char| !ch.is_ascii_alphabetic())
+        .unwrap_or(rest.len());
+    let row = rest[letters..]
+        .strip_prefix('$')
+        .unwrap_or(&rest[letters..]);
+
+    let is_cell = (1..=3).contains(&letters)
+        && !row.is_empty()
+        && row.bytes().all(|byte| byte.is_ascii_digit());
+    is_cell.then(|| format!("{}{row}", rest[..letters].to_ascii_uppercase()))
+}
+
+// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math_expression_parser::MathExpression;
+
+    #[test]
+    fn test_plain_formula() {
+        assert_eq!(
+            from_spreadsheet_formula("=(1+2)*3"),
+            Ok("(1+2)*3".to_string())
+        );
+        assert_eq!(
+            from_spreadsheet_formula("  = 4 / 2"),
+            Ok(" 4 / 2".to_string())
+        );
+        // Input without `=` is taken as it is.
+        assert_eq!(from_spreadsheet_formula("1 - 2"), Ok("1 - 2".to_string()));
+
+        let converted = from_spreadsheet_formula("=(1+2)*3").unwrap();
+        assert!(converted.parse::<MathExpression>().is_ok());
+    }
+
+    #[test]
+    fn test_unsupported_constructs() {
+        assert_eq!(
+            from_spreadsheet_formula("=SUM(A1:A3)"),
+            Err(ImportError::Function {
+                name: "SUM".to_string(),
+                span: Span::new(1, 4),
+            })
+        );
+        assert_eq!(
+            from_spreadsheet_formula("=2 * A1:B2"),
+            Err(ImportError::CellRange {
+                range: "A1:B2".to_string(),
+                span: Span::new(5, 10),
+            })
+        );
+        assert_eq!(
+            from_spreadsheet_formula("=\"A\"&B1"),
+            Err(ImportError::StringLiteral {
+                span: Span::new(1, 4),
+            })
+        );
+        assert_eq!(
+            from_spreadsheet_formula("=1&2"),
+            Err(ImportError::Concatenation {
+                span: Span::new(2, 3),
+            })
+        );
+        assert_eq!(
+            from_spreadsheet_formula("=1 + \"open"),
+            Err(ImportError::StringLiteral {
+                span: Span::new(5, 10),
+            })
+        );
+        assert_eq!(
+            from_spreadsheet_formula("=1 + TRUE"),
+            Err(ImportError::UnknownName {
+                name: "TRUE".to_string(),
+                span: Span::new(5, 9),
+            })
+        );
+    }
+
+    #[test]
+    fn test_cell_references() {
+        let cells = |cell: &str| match cell {
+            "B1" => Some(1.5),
+            "C2" => Some(-2.0),
+            _ => None,
+        };
+
+        let converted = from_spreadsheet_formula_with("=b1 * 2 - $C$2", cells).unwrap();
+        assert_eq!(converted, "1.5 * 2 - (-2)");
+        assert_eq!(
+            converted.parse::<MathExpression>().unwrap().to_string(),
+            "1.5 * 2 - (-2)"
+        );
+
+        assert_eq!(
+            from_spreadsheet_formula_with("=B1 + D4", cells),
+            Err(ImportError::UnmappedCell {
+                cell: "D4".to_string(),
+                span: Span::new(6, 8),
+            })
+        );
+        assert_eq!(
+            from_spreadsheet_formula("=A1"),
+            Err(ImportError::UnmappedCell {
+                cell: "A1".to_string(),
+                span: Span::new(1, 3),
+            })
+        );
+    }
+}
@@ -0,0 +1,324 @@
+use crate::math_expression_parser::{MathExpression, MathExpressionParserError, TokenEdit};
+use crate::math_expression_tokenizer::Token;
+use crate::position::{Position, Span};
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Placeholder {
+    pub name: String,
+    // Byte range of `{name}` in the template source.
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[non_exhaustive]
+pub enum TemplateError {
+    #[error("Template is not a valid expression: {0}")]
+    Invalid(#[from] MathExpressionParserError),
+    #[error("Placeholder at position {idx} is not an identifier in braces")]
+    MalformedPlaceholder { idx: Position, span: Span },
+    // A placeholder stands for a number, so it cannot be an operator or
+    // directly follow an operand.
+    #[error("Placeholder '{name}' is not in the place of a number")]
+    NotAnOperand { name: String, span: Span },
+    #[error("No value for placeholder '{name}'")]
+    MissingValue { name: String, span: Span },
+    #[error("Value for '{name}', which is not a placeholder of the template")]
+    UnknownValue { name: String },
+}
+
+// An expression with `{name}` placeholders for numbers, e.g.
+// `base * (1 + {rate})`, parsed once and instantiated with different values.
+// Names are ASCII identifiers; a name may be used more than once.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    source: String,
+    expression: MathExpression,
+    placeholders: Vec<Placeholder>,
+    // Index of the token standing in for each placeholder.
+    slots: Vec<usize>,
+}
+
+impl Template {
+    // Each placeholder is masked as a `0` literal padded with spaces to the
+    // placeholder's width, so the masked source parses with the usual rules
+    // and every position still refers to `source`.
+    pub fn parse(source: &str) -> Result<Self, TemplateError> {
+        let placeholders = find_placeholders(source)?;
+
+        let mut masked = source.to_string();
+        for placeholder in &placeholders {
+            let width = placeholder.span.len();
+            masked.replace_range(
+                Range::from(placeholder.span),
+                &format!(" 0{:1$}", "", width - 2),
+            );
+        }
+
+        // The masked literal sits one byte into its placeholder.
+        let slot_of = |idx: usize| placeholders.iter().find(|p| p.span.start + 1 == idx);
+        let expression: MathExpression =
+            masked.parse().map_err(|err: MathExpressionParserError| {
+                match err.idx().and_then(|idx| slot_of(idx.byte)) {
+                    Some(placeholder) => TemplateError::NotAnOperand {
+                        name: placeholder.name.clone(),
+                        span: placeholder.span,
+                    },
+                    None => err.into(),
+                }
+            })?;
+
+        let slots = placeholders
+            .iter()
+            .map(|placeholder| {
+                expression
+                    .positioned_tokens()
+                    .position(|(idx, _)| idx == placeholder.span.start + 1)
+            })
+            .collect::<Option<Vec<_>>>()
+            .expect("every placeholder is masked by a literal token");
+
+        Ok(Self {
+            source: source.to_string(),
+            expression,
+            placeholders,
+            slots,
+        })
+    }
+
+    // Placeholders in source order, including repeated names.
+    pub fn placeholders(&self) -> &[Placeholder] {
+        &self.placeholders
+    }
+
+    // Substitutes a literal for every placeholder. `values` must have exactly
+    // one entry per placeholder name.
+    pub fn instantiate(
+        &self,
+        values: &HashMap<String, f64>,
+    ) -> Result<MathExpression, TemplateError> {
+        let mut unknown: Vec<&String> = values
+            .keys()
+            .filter(|name| self.placeholders.iter().all(|p| &p.name != *name))
+            .collect();
+        unknown.sort();
+        if let Some(name) = unknown.first() {
+            return Err(TemplateError::UnknownValue {
+                name: name.to_string(),
+            });
+        }
+
+        let mut literals = Vec::with_capacity(self.slots.len());
+        for placeholder in &self.placeholders {
+            let Some(&value) = values.get(&placeholder.name) else {
+                return Err(TemplateError::MissingValue {
+                    name: placeholder.name.clone(),
+                    span: placeholder.span,
+                });
+            };
+            literals.push(Token::Digit(value));
+        }
+
+        self.expression
+            .map_tokens(&self.source, |idx, _| {
+                match self.slots.iter().position(|&slot| slot == idx) {
+                    Some(placeholder) => TokenEdit::Replace(literals[placeholder].clone()),
+                    None => TokenEdit::Keep,
+                }
+            })
+            .map_err(|failure| failure.error.into())
+    }
+}
+
+fn find_placeholders(source: &str) -> Result<Vec<Placeholder>, TemplateError> {
+    let mut placeholders = Vec::new();
+    let mut rest = source;
+
+    while let Some(open) = rest.find('{') {
+        let start = source.len() - rest.len() + open;
+        let after = &source[start + 1..];
+        let len = after
+            .find(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '_'))
+            .unwrap_or(after.len());
+        let name = &after[..len];
+
+        let is_identifier = name
+            .chars()
+            .next()
+            .is_some_and(|first| first.is_ascii_alphabetic() || first == '_');
+        if !is_identifier || !after[len..].starts_with('}') {
+            let end = start + 1 + len + usize::from(after[len..].starts_with('}'));
+            return Err(TemplateError::MalformedPlaceholder {
+                idx: Position::in_source(source, start),
+                span: Span::new(start, end),
+            });
+        }
+
+        let end = start + len + 2;
+        placeholders.push(Placeholder {
+            name: name.to_string(),
+            span: Span::new(start, end),
+        });
+        rest = &source[end..];
+    }
+
+    Ok(placeholders)
+}
+
+// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(entries: &[(&str, f64)]) -> HashMap<String, f64> {
+        entries
+            .iter()
+            .map(|&(name, value)| (name.to_string(), value))
+            .collect()
+    }
+
+    #[test]
+    fn test_instantiate() {
+        let template = Template::parse("200 * (1 + {rate}) - {discount}").unwrap();
+        assert_eq!(
+            template.placeholders(),
+            [
+                Placeholder {
+                    name: "rate".to_string(),
+                    span: Span::new(11, 17),
+                },
+                Placeholder {
+                    name: "discount".to_string(),
+                    span: Span::new(21, 31),
+                },
+            ]
+        );
+
+        let expression = template
+            .instantiate(&values(&[("rate", 0.25), ("discount", 10.0)]))
+            .unwrap();
+        assert_eq!(expression.to_string(), "200 * (1 + 0.25) - 10");
+        assert_eq!(
+            expression.positioned_tokens().nth(5),
+            Some((12, &Token::Digit(0.25)))
+        );
+    }
+
+    #[test]
+    fn test_placeholder_used_twice() {
+        let template = Template::parse("{x} * {x} + {y}").unwrap();
+        assert_eq!(template.placeholders().len(), 3);
+
+        let expression = template
+            .instantiate(&values(&[("x", 3.0), ("y", 1.0)]))
+            .unwrap();
+        assert_eq!(expression.to_string(), "3 * 3 + 1");
+    }
+
+    #[test]
+    fn test_missing_and_unknown_values() {
+        let template = Template::parse("{a} + {b}").unwrap();
+
+        assert_eq!(
+            template.instantiate(&values(&[("a", 1.0)])),
+            Err(TemplateError::MissingValue {
+                name: "b".to_string(),
+                span: Span::new(6, 9),
+            })
+        );
+        assert_eq!(
+            template.instantiate(&values(&[("a", 1.0), ("b", 2.0), ("c", 3.0)])),
+            Err(TemplateError::UnknownValue {
+                name: "c".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_placeholder_not_in_operand_position() {
+        for (source, span) in [("1 {op} 2", 2..6), ("2{x}", 1..4), ("({x}) {y}", 6..9)] {
+            assert!(
+                matches!(
+                    Template::parse(source),
+                    Err(TemplateError::NotAnOperand { span: ref found, .. }) if Range::from(*found) == span
+                ),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_malformed_placeholders() {
+        for (source, span) in [
+            ("1 + {}", 4..6),
+            ("1 + {2x}", 4..8),
+            ("1 + {rate", 4..9),
+            ("{a b}", 0..2),
+        ] {
+            assert_eq!(
+                Template::parse(source),
+                Err(TemplateError::MalformedPlaceholder {
+                    idx: Position::new(span.start, span.start),
+                    span: span.into(),
+                }),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_malformed_placeholder_message() {
+        let error = Template::parse("\u{3000}1 + {}").unwrap_err();
+        assert_eq!(
+            error,
+            TemplateError::MalformedPlaceholder {
+                idx: Position::new(7, 5),
+                span: Span::new(7, 9),
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "Placeholder at position 6 is not an identifier in braces"
+        );
+    }
+
+    #[test]
+    fn test_errors_outside_placeholders_keep_positions() {
+        assert_eq!(
+            Template::parse("{rate} + $"),
+            Err(TemplateError::Invalid(
+                crate::math_expression_tokenizer::MathExpressionTokenizerError::InvalidToken {
+                    idx: Position::new(9, 9),
+                    ch: '$',
+                    suggestion: None,
+                }
+                .into()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_without_placeholders_is_a_plain_parse() {
+        let source = "(1 + 2) * 3";
+        let template = Template::parse(source).unwrap();
+
+        assert!(template.placeholders().is_empty());
+        assert_eq!(
+            template.instantiate(&HashMap::new()),
+            Ok(source.parse().unwrap())
+        );
+        assert_eq!(
+            Template::parse("(1 + 2"),
+            Err(TemplateError::Invalid(
+                MathExpressionParserError::UnclosedBrace {
+                    idx: Position::new(0, 0)
+                }
+            ))
+        );
+    }
+}
@@ -0,0 +1,120 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use calculator::{MathExpressionTokenizer, TokenizerTraits};
+
+// Counts allocations made by the current thread while counting is enabled,
+// so tests running in parallel do not disturb each other.
+struct CountingAllocator;
+
+thread_local! {
+    static COUNTING: Cell<bool> = const { Cell::new(false) };
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if COUNTING.with(Cell::get) {
+            ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        }
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn count_allocations(f: impl FnOnce()) -> usize {
+    ALLOCATIONS.with(|count| count.set(0));
+    COUNTING.with(|counting| counting.set(true));
+    f();
+    COUNTING.with(|counting| counting.set(false));
+    ALLOCATIONS.with(Cell::get)
+}
+
+#[test]
+fn test_borrowed_tokenization_does_not_allocate() {
+    let allocations = count_allocations(|| {
+        let mut tokenizer = MathExpressionTokenizer::new("+ - * / ( )").unwrap();
+        while tokenizer.has_token() {
+            tokenizer.next_token().unwrap();
+        }
+    });
+
+    assert_eq!(allocations, 0);
+}
+
+#[test]
+fn test_owned_tokenization_allocates_only_input() {
+    let allocations = count_allocations(|| {
+        let tokenizer = MathExpressionTokenizer::new("+ - * /".to_string()).unwrap();
+        assert_eq!(tokenizer.count(), 4);
+    });
+
+    assert_eq!(allocations, 1);
+}
+
+#[cfg(feature = "smallvec")]
+#[test]
+fn test_short_expression_parse_does_not_allocate() {
+    use calculator::MathExpressionParser;
+
+    let parser = MathExpressionParser::new();
+    let allocations = count_allocations(|| {
+        let tokenizer = MathExpressionTokenizer::new("(1 + 2) * 3 - 4").unwrap();
+        let parsed = parser.parse(tokenizer).unwrap();
+        assert_eq!(parsed.tokens().len(), 9);
+    });
+
+    assert_eq!(allocations, 0);
+}
+
+#[test]
+fn test_reused_evaluator_does_not_allocate() {
+    use calculator::{Evaluator, MathExpression};
+
+    let sources = ["(1 + 2) * 3 - 4", "2 ^ (3 ^ (1 + 1))", "1 / 0", "((((5))))"];
+    let parsed: Vec<MathExpression> = sources.iter().map(|s| s.parse().unwrap()).collect();
+    let mut evaluator = Evaluator::new();
+    let mut run = || {
+        for (expr, source) in parsed.iter().zip(sources) {
+            let _ = evaluator.evaluate(expr, source);
+        }
+    };
+
+    // The first run sizes the buffers.
+    run();
+    assert_eq!(count_allocations(run), 0);
+}
+
+#[test]
+fn test_reused_arena_does_not_allocate() {
+    use calculator::{ExprArena, MathExpression};
+
+    let sources = ["(1 + 2) * 3 - 4", "2 ^ (3 ^ (1 + 1))", "-((((5))))"];
+    let parsed: Vec<MathExpression> = sources.iter().map(|s| s.parse().unwrap()).collect();
+
+    // A node per box.
+    let boxed = count_allocations(|| {
+        for expr in &parsed {
+            drop(expr.to_ast());
+        }
+    });
+    assert!(boxed >= 20, "{boxed}");
+
+    let mut arena = ExprArena::new();
+    let mut run = || {
+        for expr in &parsed {
+            arena.clear();
+            expr.to_ast_in(&mut arena);
+        }
+    };
+
+    // The first run sizes the buffers.
+    run();
+    assert_eq!(count_allocations(run), 0);
+}
@@ -0,0 +1,48 @@
+use calculator::{
+    MathExpression, MathExpressionParser, MathExpressionParserError, MathExpressionTokenizer,
+    MathExpressionTokenizerError,
+};
+
+fn parse(expr: &str) -> Result<MathExpression, MathExpressionParserError> {
+    let tokenizer = MathExpressionTokenizer::new(expr.to_string())?;
+    MathExpressionParser::new().parse(tokenizer)
+}
+
+#[test]
+fn test_compare_and_clone_parse_results() {
+    let parsed = parse("(1 + 2) * 3").unwrap();
+    let cached = parsed.clone();
+
+    assert_eq!(parsed, cached);
+    assert_eq!(parsed, parse("(1 + 2) * 3").unwrap());
+    // Expressions remember token positions, so spacing matters for equality.
+    assert_ne!(parsed, parse("( 1+2 )*3").unwrap());
+    assert_eq!(parsed.tokens(), parse("( 1+2 )*3").unwrap().tokens());
+    assert_ne!(parsed, parse("1 + 2 * 3").unwrap());
+    assert_eq!(parse("1 +"), parse("1 +"));
+}
+
+#[test]
+fn test_clone_and_default_parser() {
+    let parser = MathExpressionParser::default().clone();
+    let tokenizer = MathExpressionTokenizer::new("1".to_string()).unwrap();
+
+    assert!(parser.parse(tokenizer).is_ok());
+}
+
+#[test]
+fn test_match_errors_with_wildcard() {
+    let describe = |err: MathExpressionParserError| match err {
+        MathExpressionParserError::Tokenizer(MathExpressionTokenizerError::InvalidToken {
+            ch,
+            ..
+        }) => format!("bad character {ch}"),
+        MathExpressionParserError::Tokenizer(_) => "tokenizer".to_string(),
+        MathExpressionParserError::UnclosedBrace { .. } => "braces".to_string(),
+        _ => "other".to_string(),
+    };
+
+    assert_eq!(describe(parse("1 $").unwrap_err()), "bad character $");
+    assert_eq!(describe(parse("(1").unwrap_err()), "braces");
+    assert_eq!(describe(parse("1 +").unwrap_err()), "other");
+}
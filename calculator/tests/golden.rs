@@ -0,0 +1,249 @@
+// Snapshot tests over `testdata/golden/*.txt`. A case file holds optional
+// `%` directive lines, the input, a `---` line and the expected dump:
+//
+//   % strict         tokenize with `TokenizerConfig::strict()`
+//   % token          parse the input as a single `Token` instead
+//   % cancelled      parse with an already cancelled token
+//   % tree           also print the parsed tree, in both layouts
+//
+// Run with `UPDATE_GOLDEN=1` to rewrite the expectations from the current
+// output. New cases only need the input and the `---` line.
+
+use calculator::cancellation::CancellationToken;
+use calculator::{
+    MathExpression, MathExpressionParser, MathExpressionParserError, MathExpressionTokenizer,
+    MathExpressionTokenizerError, Position, Token, TokenizerConfig, TokenizerTraits,
+};
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+const CASES_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/golden");
+const SEPARATOR: &str = "\n---\n";
+
+#[derive(Default)]
+struct Case {
+    directives: Vec<String>,
+    input: String,
+    expected: String,
+}
+
+impl Case {
+    fn read(path: &Path) -> Case {
+        let content = std::fs::read_to_string(path).unwrap();
+        let (head, expected) = content
+            .split_once(SEPARATOR)
+            .unwrap_or_else(|| panic!("{} has no '---' line", path.display()));
+
+        let mut case = Case {
+            expected: expected.to_string(),
+            ..Case::default()
+        };
+        let mut lines = head.split_inclusive('\n').peekable();
+        while let Some(directive) = lines.next_if(|line| line.starts_with('%')) {
+            case.directives.push(directive[1..].trim().to_string());
+        }
+        case.input = lines.collect();
+        case
+    }
+
+    fn write(&self, path: &Path, actual: &str) {
+        let mut content = String::new();
+        for directive in &self.directives {
+            let _ = writeln!(content, "% {directive}");
+        }
+        content.push_str(&self.input);
+        content.push_str(SEPARATOR);
+        content.push_str(actual);
+        std::fs::write(path, content).unwrap();
+    }
+
+    fn has(&self, directive: &str) -> bool {
+        self.directives.iter().any(|d| d == directive)
+    }
+
+    fn config(&self) -> TokenizerConfig {
+        if self.has("strict") {
+            TokenizerConfig::strict()
+        } else {
+            TokenizerConfig::default()
+        }
+    }
+}
+
+fn tokenizer(case: &Case) -> Result<MathExpressionTokenizer, MathExpressionTokenizerError> {
+    Ok(MathExpressionTokenizer::new(case.input.clone())?.with_config(case.config()))
+}
+
+fn position(idx: Option<Position>) -> String {
+    idx.map_or_else(
+        || "-".to_string(),
+        |idx| format!("{}:{}", idx.byte, idx.char),
+    )
+}
+
+fn tokenizer_error(err: &MathExpressionTokenizerError) -> String {
+    format!("error {} at {}: {err}", err.code(), position(err.idx()))
+}
+
+fn parser_error(err: &MathExpressionParserError) -> String {
+    format!("error {} at {}: {err}", err.code(), position(err.idx()))
+}
+
+fn describe(token: &Token) -> String {
+    let kind = match token {
+        Token::Digit(_) => "number",
+        Token::Operator(_) => "operator",
+        Token::UnaryOperator(_) => "unary",
+        Token::OpenBrace => "open",
+        Token::CloseBrace => "close",
+    };
+    format!("{kind} {token}")
+}
+
+// Every line the tokenizer produces, continuing past errors, then the
+// warnings it collected.
+fn dump_tokens(case: &Case, out: &mut String) {
+    let mut tokenizer = match tokenizer(case) {
+        Ok(tokenizer) => tokenizer,
+        Err(err) => {
+            let _ = writeln!(out, "tokens: {}", tokenizer_error(&err));
+            return;
+        }
+    };
+
+    out.push_str("tokens:\n");
+    while tokenizer.has_token() {
+        let line = match tokenizer.next_token() {
+            Ok((idx, token)) => format!(
+                "{} {}",
+                position(Some(Position::new(idx, case.input[..idx].chars().count()))),
+                describe(&token)
+            ),
+            Err(err) => tokenizer_error(&err),
+        };
+        let _ = writeln!(out, "  {line}");
+    }
+
+    for warning in tokenizer.take_warnings() {
+        let _ = writeln!(out, "warning: {warning:?}");
+    }
+}
+
+fn dump_parse(case: &Case, out: &mut String) {
+    let mut parser = MathExpressionParser::new();
+    if case.has("cancelled") {
+        let token = CancellationToken::new();
+        token.cancel();
+        parser = parser.with_cancellation(token);
+    }
+
+    let result: Result<MathExpression, MathExpressionParserError> = tokenizer(case)
+        .map_err(MathExpressionParserError::from)
+        .and_then(|tokenizer| parser.parse(tokenizer));
+
+    match result {
+        Ok(parsed) => {
+            let _ = writeln!(out, "parse: ok");
+            let _ = writeln!(out, "canonical: {parsed}");
+            let _ = writeln!(out, "key: {}", parsed.canonical_key());
+            if case.has("tree") {
+                let tree = parsed.to_ast();
+                let _ = write!(out, "tree:\n{}", tree.to_pretty_tree());
+                let _ = write!(out, "compact tree:\n{}", tree.to_pretty_tree_with(true));
+            }
+        }
+        Err(err) => {
+            let _ = writeln!(out, "parse: {}", parser_error(&err));
+        }
+    }
+}
+
+fn dump(case: &Case) -> String {
+    let mut out = String::new();
+    if case.has("token") {
+        let line = match case.input.parse::<Token>() {
+            Ok(token) => format!("ok {}", describe(&token)),
+            Err(err) => tokenizer_error(&err),
+        };
+        let _ = writeln!(out, "token: {line}");
+        return out;
+    }
+
+    dump_tokens(case, &mut out);
+    dump_parse(case, &mut out);
+    out
+}
+
+// Lists both sides line by line and marks the lines that differ.
+fn diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<_> = expected.lines().collect();
+    let actual: Vec<_> = actual.lines().collect();
+    let mut out = String::new();
+
+    for i in 0..expected.len().max(actual.len()) {
+        match (expected.get(i), actual.get(i)) {
+            (Some(lhs), Some(rhs)) if lhs == rhs => {
+                let _ = writeln!(out, "  {lhs}");
+            }
+            (lhs, rhs) => {
+                if let Some(lhs) = lhs {
+                    let _ = writeln!(out, "- {lhs}");
+                }
+                if let Some(rhs) = rhs {
+                    let _ = writeln!(out, "+ {rhs}");
+                }
+            }
+        }
+    }
+
+    out
+}
+
+fn case_paths() -> Vec<PathBuf> {
+    let mut paths: Vec<_> = std::fs::read_dir(CASES_DIR)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "txt"))
+        .collect();
+    paths.sort();
+    paths
+}
+
+#[test]
+fn test_golden_cases() {
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let mut failures = Vec::new();
+
+    let paths = case_paths();
+    assert!(paths.len() >= 40, "only {} golden cases", paths.len());
+
+    for path in paths {
+        let case = Case::read(&path);
+        let actual = dump(&case);
+
+        if update {
+            case.write(&path, &actual);
+        } else if actual != case.expected {
+            failures.push(format!(
+                "{}:\n{}",
+                path.display(),
+                diff(&case.expected, &actual)
+            ));
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "golden mismatches, rerun with UPDATE_GOLDEN=1 to accept:\n\n{}",
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn test_dump_is_deterministic() {
+    for path in case_paths() {
+        let case = Case::read(&path);
+        assert_eq!(dump(&case), dump(&case), "{}", path.display());
+    }
+}
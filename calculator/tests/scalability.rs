@@ -0,0 +1,101 @@
+// Checks that parsing stays linear on very long inputs. The inputs are tens
+// of megabytes, so these tests are ignored by default; run them in release
+// mode with `cargo test --release --test scalability -- --ignored`. The
+// inputs come from `bench_corpus`, like the benchmarks'.
+
+use calculator::bench_corpus::Workload;
+use calculator::{MathExpressionParser, MathExpressionTokenizer, TokenizerTraits};
+
+use std::time::{Duration, Instant};
+
+const SIZES: [usize; 3] = [500_000, 1_000_000, 2_000_000];
+const TERMS: usize = 5_000_000;
+
+// Growing the input by `k` may grow the runtime by at most `k * SLACK`, which
+// leaves room for noise and cache effects but not for quadratic behaviour.
+const SLACK: f64 = 2.0;
+const SEED: u64 = 0;
+
+fn chain(terms: usize) -> String {
+    Workload::FlatChain.generate(SEED, terms)
+}
+
+fn nested(depth: usize) -> String {
+    Workload::DeepNesting.generate(SEED, depth)
+}
+
+// Fastest of a few runs, which is the least noisy measurement.
+fn time(f: impl Fn()) -> Duration {
+    (0..3)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn tokenize(source: &str) {
+    let mut tokenizer = MathExpressionTokenizer::new(source.to_owned()).unwrap();
+    while tokenizer.has_token() {
+        tokenizer.next_token().unwrap();
+    }
+}
+
+fn parse(source: &str) {
+    let tokenizer = MathExpressionTokenizer::new(source.to_owned()).unwrap();
+    let parsed = MathExpressionParser::new().parse(tokenizer).unwrap();
+    assert!(!parsed.tokens().is_empty());
+}
+
+fn assert_linear(name: &str, input: fn(usize) -> String, run: fn(&str)) {
+    let timings: Vec<_> = SIZES
+        .iter()
+        .map(|&size| {
+            let source = input(size);
+            (size, time(|| run(&source)))
+        })
+        .collect();
+
+    for pair in timings.windows(2) {
+        let [(small, small_time), (large, large_time)] = pair else {
+            unreachable!();
+        };
+        let growth = large_time.as_secs_f64() / small_time.as_secs_f64().max(1e-6);
+        let allowed = (*large as f64 / *small as f64) * SLACK;
+        assert!(
+            growth <= allowed,
+            "{name}: {small} -> {large} took {small_time:?} -> {large_time:?}"
+        );
+    }
+}
+
+#[test]
+#[ignore]
+fn test_tokenize_chain_is_linear() {
+    assert_linear("tokenize chain", chain, tokenize);
+}
+
+#[test]
+#[ignore]
+fn test_parse_chain_is_linear() {
+    assert_linear("parse chain", chain, parse);
+}
+
+#[test]
+#[ignore]
+fn test_parse_nested_is_linear() {
+    assert_linear("parse nested", nested, parse);
+}
+
+#[test]
+#[ignore]
+fn test_parse_five_million_terms() {
+    let source = chain(TERMS);
+    let elapsed = time(|| parse(&source));
+
+    if !cfg!(debug_assertions) {
+        assert!(elapsed < Duration::from_secs(1), "took {elapsed:?}");
+    }
+}